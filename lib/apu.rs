@@ -0,0 +1,548 @@
+//===== APU =====
+// The four sound channels in front of the bus, like the joypad: register writes go through to
+// the bus (so they end up in save states) and update the channel state, reads come back with
+// the unused bits set. The frame sequencer runs from its own 8192-cycle counter rather than DIV,
+// and wave RAM access while channel 3 plays is not blocked. Samples are only made when
+// `sample_rate` is set; they go through NR50/NR51 and a high-pass filter like the real output.
+// For debuggers, channels can be muted in the mix, `scope` keeps the recent output of each
+// channel and `stems` records every channel on its own alongside the samples. `state` is what
+// a save state needs on top of the registers: the channels, the sweep, the LFSR and the frame
+// sequencer, but not the resampler and filters, which belong to the output.
+use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use crate::mmu::{BusAccess, Mmu};
+
+pub const NR10: u16 = 0xFF10;
+pub const NR11: u16 = 0xFF11;
+pub const NR12: u16 = 0xFF12;
+pub const NR13: u16 = 0xFF13;
+pub const NR14: u16 = 0xFF14;
+pub const NR30: u16 = 0xFF1A;
+pub const NR32: u16 = 0xFF1C;
+pub const NR43: u16 = 0xFF22;
+pub const NR50: u16 = 0xFF24;
+pub const NR51: u16 = 0xFF25;
+pub const NR52: u16 = 0xFF26;
+pub const WAVE_RAM: u16 = 0xFF30;
+
+pub const CLOCK: u32 = 4_194_304;
+const SEQUENCER_CYCLES: u32 = 8192;
+// duty step patterns, bit 7 first: 12.5%, 25%, 50%, 75%
+const DUTY: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+// the scope takes the four outputs every SCOPE_CYCLES and keeps the last SCOPE_LEN
+pub const SCOPE_CYCLES: u32 = 64;
+pub const SCOPE_LEN: usize = 2048;
+// bits that read back as 1, NR10 to 0xFF2F
+const READ_MASK: [u8; 32] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub volume: u8,
+    pub increase: bool,
+    pub period: u8, // 0 stops it
+    timer: u8,
+}
+
+impl Envelope {
+    fn trigger(&mut self, nrx2: u8) {
+        (self.volume, self.increase, self.period) = (nrx2 >> 4, nrx2 & 0x08 != 0, nrx2 & 0x07);
+        self.timer = self.period;
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            match self.increase {
+                true if self.volume < 15 => self.volume += 1,
+                false if self.volume > 0 => self.volume -= 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+// channel 1's frequency sweep
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sweep {
+    pub enabled: bool,
+    pub shadow: u16,
+    timer: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Channel {
+    pub enabled: bool,
+    pub dac: bool,
+    pub length: u16, // counts down while length_enabled, the channel stops at 0
+    pub length_enabled: bool,
+    pub frequency: u16, // 11 bits, not used by the noise channel
+    pub envelope: Envelope, // not used by the wave channel
+    pub position: u8, // duty step or wave sample
+    timer: i32, // T-cycles to the next step
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApuState {
+    pub channels: [Channel; 4],
+    pub sweep: Sweep,
+    pub lfsr: u16,
+    sequencer_timer: u32,
+    sequencer_step: u8,
+}
+
+pub struct Apu<T: Mmu> {
+    pub bus: T,
+    pub channels: [Channel; 4],
+    pub sweep: Sweep,
+    pub lfsr: u16,
+    sequencer_timer: u32,
+    sequencer_step: u8,
+    pub sample_rate: Option<u32>,
+    sample_clock: u64,
+    capacitor: [f32; 2],
+    pub samples: Vec<[f32; 2]>, // left, right in -1..1
+    pub muted: [bool; 4],
+    pub scope: Option<VecDeque<[u8; 4]>>,
+    scope_clock: u32,
+    pub stems: Option<Vec<[[f32; 2]; 4]>>, // each channel's left, right with every sample
+    stem_capacitors: [[f32; 2]; 4],
+}
+
+impl<T: Mmu> Apu<T> {
+    // picks up whatever the registers on the bus hold, e.g. after the boot ROM
+    pub fn new(bus: T) -> Apu<T> {
+        let mut apu = Apu {
+            bus, channels: [Channel::default(); 4], sweep: Sweep::default(), lfsr: 0x7FFF,
+            sequencer_timer: 0, sequencer_step: 0, sample_rate: None, sample_clock: 0,
+            capacitor: [0.0; 2], samples: Vec::new(), muted: [false; 4], scope: None, scope_clock: 0,
+            stems: None, stem_capacitors: [[0.0; 2]; 4],
+        };
+        for (n, channel) in apu.channels.iter_mut().enumerate() {
+            let base = NR10 + n as u16 * 5;
+            channel.dac = match n {
+                2 => apu.bus.read(NR30) & 0x80 != 0,
+                _ => apu.bus.read(base + 2) & 0xF8 != 0,
+            };
+            channel.frequency = (apu.bus.read(base + 4) as u16 & 0x07) << 8 | apu.bus.read(base + 3) as u16;
+        }
+        apu
+    }
+
+    pub fn state(&self) -> ApuState {
+        ApuState {
+            channels: self.channels, sweep: self.sweep, lfsr: self.lfsr,
+            sequencer_timer: self.sequencer_timer, sequencer_step: self.sequencer_step,
+        }
+    }
+
+    pub fn set_state(&mut self, state: &ApuState) {
+        (self.channels, self.sweep, self.lfsr) = (state.channels, state.sweep, state.lfsr);
+        (self.sequencer_timer, self.sequencer_step) = (state.sequencer_timer, state.sequencer_step);
+    }
+
+    pub fn powered(&self) -> bool {
+        self.bus.read(NR52) & 0x80 != 0
+    }
+
+    // digital output of channel `n`, 0..15
+    pub fn output(&self, n: usize) -> u8 {
+        let channel = &self.channels[n];
+        if !channel.enabled {
+            return 0;
+        }
+        match n {
+            0 | 1 => {
+                let duty = DUTY[(self.bus.read(NR11 + n as u16 * 5) >> 6) as usize];
+                if duty & (0x80 >> channel.position) != 0 { channel.envelope.volume } else { 0 }
+            }
+            2 => {
+                let byte = self.bus.read(WAVE_RAM + channel.position as u16 / 2);
+                let sample = if channel.position.is_multiple_of(2) { byte >> 4 } else { byte & 0x0F };
+                match (self.bus.read(NR32) >> 5) & 0x03 {
+                    0 => 0,
+                    shift => sample >> (shift - 1),
+                }
+            }
+            _ => if self.lfsr & 1 == 0 { channel.envelope.volume } else { 0 },
+        }
+    }
+
+    // how often the waveform of channel `n` repeats, or the noise channel's LFSR clock, in Hz
+    pub fn frequency_hz(&self, n: usize) -> f32 {
+        let steps = match n {
+            0 | 1 => 8,
+            2 => 32,
+            _ => 1,
+        };
+        CLOCK as f32 / (self.period(n) * steps) as f32
+    }
+
+    // T-cycles between two steps of channel `n`
+    fn period(&self, n: usize) -> i32 {
+        let frequency = self.channels[n].frequency as i32;
+        match n {
+            0 | 1 => (2048 - frequency) * 4,
+            2 => (2048 - frequency) * 2,
+            _ => {
+                let nr43 = self.bus.read(NR43);
+                (NOISE_DIVISORS[(nr43 & 0x07) as usize] << (nr43 >> 4)) as i32
+            }
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles / 4 {
+            if self.powered() {
+                self.step(4);
+            }
+            self.scope_clock += 4;
+            if self.scope_clock >= SCOPE_CYCLES {
+                self.scope_clock -= SCOPE_CYCLES;
+                if self.scope.is_some() {
+                    let outputs = [0, 1, 2, 3].map(|n| self.output(n));
+                    let scope = self.scope.get_or_insert_default();
+                    if scope.len() == SCOPE_LEN {
+                        scope.pop_front();
+                    }
+                    scope.push_back(outputs);
+                }
+            }
+            if let Some(rate) = self.sample_rate {
+                self.sample_clock += rate as u64 * 4;
+                if self.sample_clock >= CLOCK as u64 {
+                    self.sample_clock -= CLOCK as u64;
+                    let sample = self.mix(rate);
+                    self.samples.push(sample);
+                }
+            }
+        }
+    }
+
+    fn step(&mut self, cycles: u32) {
+        self.sequencer_timer += cycles;
+        if self.sequencer_timer >= SEQUENCER_CYCLES {
+            self.sequencer_timer -= SEQUENCER_CYCLES;
+            self.clock_sequencer();
+        }
+        for n in 0..4 {
+            self.channels[n].timer -= cycles as i32;
+            while self.channels[n].timer <= 0 {
+                self.channels[n].timer += self.period(n);
+                match n {
+                    0 | 1 => self.channels[n].position = (self.channels[n].position + 1) & 7,
+                    2 => self.channels[n].position = (self.channels[n].position + 1) & 31,
+                    _ => {
+                        let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                        self.lfsr = (self.lfsr >> 1) | bit << 14;
+                        if self.bus.read(NR43) & 0x08 != 0 {
+                            self.lfsr = (self.lfsr & !0x40) | bit << 6;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // lengths on even steps, the sweep on 2 and 6, envelopes on 7
+    fn clock_sequencer(&mut self) {
+        let step = self.sequencer_step;
+        self.sequencer_step = (step + 1) & 7;
+        if step.is_multiple_of(2) {
+            for channel in &mut self.channels {
+                if channel.length_enabled && channel.length > 0 {
+                    channel.length -= 1;
+                    if channel.length == 0 {
+                        channel.enabled = false;
+                    }
+                }
+            }
+        }
+        if step == 2 || step == 6 {
+            self.clock_sweep();
+        }
+        if step == 7 {
+            for n in [0, 1, 3] {
+                self.channels[n].envelope.clock();
+            }
+        }
+    }
+
+    fn sweep_frequency(&mut self) -> u16 {
+        let nr10 = self.bus.read(NR10);
+        let delta = self.sweep.shadow >> (nr10 & 0x07);
+        let frequency = match nr10 & 0x08 {
+            0 => self.sweep.shadow + delta,
+            _ => self.sweep.shadow - delta,
+        };
+        if frequency > 2047 {
+            self.channels[0].enabled = false;
+        }
+        frequency
+    }
+
+    fn clock_sweep(&mut self) {
+        let nr10 = self.bus.read(NR10);
+        let (period, shift) = ((nr10 >> 4) & 0x07, nr10 & 0x07);
+        self.sweep.timer = self.sweep.timer.saturating_sub(1);
+        if self.sweep.timer > 0 {
+            return;
+        }
+        self.sweep.timer = if period == 0 { 8 } else { period };
+        if !self.sweep.enabled || period == 0 {
+            return;
+        }
+        let frequency = self.sweep_frequency();
+        if frequency <= 2047 && shift != 0 {
+            self.sweep.shadow = frequency;
+            self.channels[0].frequency = frequency;
+            // the new frequency shows in NR13/NR14 like a write from the game
+            self.bus.write(NR13, frequency as u8);
+            let nr14 = self.bus.read(NR14);
+            self.bus.write(NR14, (nr14 & 0xF8) | (frequency >> 8) as u8);
+            self.sweep_frequency();
+        }
+    }
+
+    fn trigger(&mut self, n: usize) {
+        let base = NR10 + n as u16 * 5;
+        let period = self.period(n);
+        let channel = &mut self.channels[n];
+        channel.enabled = channel.dac;
+        if channel.length == 0 {
+            channel.length = if n == 2 { 256 } else { 64 };
+        }
+        channel.timer = period;
+        channel.position = 0;
+        if n != 2 {
+            let nrx2 = self.bus.read(base + 2);
+            channel.envelope.trigger(nrx2);
+        }
+        match n {
+            0 => {
+                let nr10 = self.bus.read(NR10);
+                let (period, shift) = ((nr10 >> 4) & 0x07, nr10 & 0x07);
+                self.sweep.shadow = self.channels[0].frequency;
+                self.sweep.timer = if period == 0 { 8 } else { period };
+                self.sweep.enabled = period != 0 || shift != 0;
+                if shift != 0 {
+                    self.sweep_frequency();
+                }
+            }
+            3 => self.lfsr = 0x7FFF,
+            _ => {}
+        }
+    }
+
+    fn write_channel(&mut self, addr: u16, value: u8) {
+        let (n, reg) = ((addr - NR10) as usize / 5, (addr - NR10) % 5);
+        let channel = &mut self.channels[n];
+        match (n, reg) {
+            (2, 0) => channel.dac = value & 0x80 != 0,
+            (2, 1) => channel.length = 256 - value as u16,
+            (_, 1) => channel.length = 64 - (value & 0x3F) as u16,
+            (2, 2) => {}
+            (_, 2) => channel.dac = value & 0xF8 != 0,
+            (_, 3) => channel.frequency = (channel.frequency & 0x700) | value as u16,
+            (_, 4) => {
+                channel.frequency = (channel.frequency & 0xFF) | (value as u16 & 0x07) << 8;
+                channel.length_enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger(n);
+                }
+            }
+            _ => {}
+        }
+        if !self.channels[n].dac {
+            self.channels[n].enabled = false;
+        }
+    }
+
+    fn mix(&mut self, rate: u32) -> [f32; 2] {
+        let (nr50, nr51) = (self.bus.read(NR50), self.bus.read(NR51));
+        let volume = [((nr50 >> 4) & 0x07) + 1, (nr50 & 0x07) + 1];
+        // each channel through the panning and master volume
+        let mut levels = [[0.0f32; 2]; 4];
+        for (n, level) in levels.iter_mut().enumerate() {
+            if !self.channels[n].dac {
+                continue;
+            }
+            let analog = self.output(n) as f32 / 7.5 - 1.0;
+            for side in 0..2 {
+                if nr51 & (0x10 >> (side * 4) << n) != 0 {
+                    level[side] = analog * volume[side] as f32 / 8.0 / 4.0;
+                }
+            }
+        }
+        // the output capacitor takes the DC offset of the DACs away
+        let charge = 0.999958f32.powf(CLOCK as f32 / rate as f32);
+        let filter = |level: f32, capacitor: &mut f32| {
+            let out = level - *capacitor;
+            *capacitor = level - out * charge;
+            out
+        };
+        let mut out = [0.0f32; 2];
+        for side in 0..2 {
+            let level = (0..4).filter(|&n| !self.muted[n]).map(|n| levels[n][side]).sum();
+            out[side] = filter(level, &mut self.capacitor[side]);
+        }
+        if let Some(stems) = &mut self.stems {
+            let capacitors = &mut self.stem_capacitors;
+            stems.push([0, 1, 2, 3].map(|n| [0, 1].map(|side| filter(levels[n][side], &mut capacitors[n][side]))));
+        }
+        out
+    }
+
+    // all samples made since the last call
+    pub fn take_samples(&mut self) -> Vec<[f32; 2]> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+// channel `n` of recorded stems as interleaved left, right samples for wav::write
+pub fn stem(stems: &[[[f32; 2]; 4]], n: usize) -> Vec<f32> {
+    stems.iter().flat_map(|channels| channels[n]).collect()
+}
+
+impl<T: Mmu> Mmu for Apu<T> {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            NR52 => {
+                let status = (0..4).filter(|&n| self.channels[n].enabled).fold(0, |acc, n| acc | 1 << n);
+                (self.bus.read(NR52) & 0x80) | 0x70 | status
+            }
+            NR10..=0xFF2F => self.bus.read(addr) | READ_MASK[(addr - NR10) as usize],
+            _ => self.bus.read(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            NR52 => {
+                // powering off clears every register and stops the channels
+                if value & 0x80 == 0 && self.powered() {
+                    for reg in NR10..NR52 {
+                        self.bus.write(reg, 0);
+                    }
+                    self.channels = [Channel::default(); 4];
+                }
+                if value & 0x80 != 0 && !self.powered() {
+                    self.sequencer_step = 0;
+                }
+                self.bus.write(NR52, value & 0x80);
+            }
+            NR10..NR52 if !self.powered() => {}
+            NR10..=0xFF23 => {
+                self.bus.write(addr, value);
+                self.write_channel(addr, value);
+            }
+            _ => self.bus.write(addr, value),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        self.bus.rom_bank(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        self.bus.access(addr, kind, cycle);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    fn apu() -> Apu<FlatMmu> {
+        let mut apu = Apu::new(FlatMmu::new());
+        apu.write(NR52, 0x80);
+        apu.write(NR50, 0x77);
+        apu.write(NR51, 0xFF);
+        apu
+    }
+
+    #[test]
+    fn square_channel_and_length() {
+        let mut apu = apu();
+        apu.write(0xFF16, 0x80 | 62); // 50% duty, length 2
+        apu.write(0xFF17, 0xF0); // full volume, no envelope
+        apu.write(0xFF18, 0x00);
+        apu.write(0xFF19, 0xC7); // frequency 0x700, length enabled, trigger
+        assert_eq!(apu.read(NR52), 0xF2);
+        assert_eq!(apu.read(0xFF16), 0xBF);
+
+        // 50%: one step every 1024 T-cycles, four low then four high
+        let mut outputs = Vec::new();
+        for _ in 0..8 {
+            apu.tick(1024);
+            outputs.push(apu.output(1));
+        }
+        assert_eq!(outputs, [0, 0, 0, 0, 15, 15, 15, 15]);
+
+        // two length clocks later the channel stops
+        apu.tick(SEQUENCER_CYCLES * 3);
+        assert_eq!(apu.read(NR52), 0xF0);
+
+        apu.write(NR52, 0x00);
+        assert_eq!((apu.read(0xFF17), apu.read(NR52)), (0x00, 0x70));
+        apu.write(0xFF17, 0xF0);
+        assert_eq!(apu.read(0xFF17), 0x00);
+    }
+
+    #[test]
+    fn sweep_overflow_and_samples() {
+        let mut apu = apu();
+        apu.sample_rate = Some(44100);
+        apu.write(NR10, 0x11); // period 1, up, shift 1
+        apu.write(NR12, 0xF0);
+        apu.write(NR13, 0x00);
+        apu.write(NR14, 0x85); // frequency 0x500: 0x500 + 0x280 fits, the next one does not
+        assert_eq!(apu.read(NR52) & 0x01, 0x01);
+        apu.tick(SEQUENCER_CYCLES * 4);
+        assert_eq!(apu.channels[0].frequency, 0x780);
+        assert_eq!(apu.read(NR52) & 0x01, 0x00);
+
+        let samples = apu.take_samples();
+        assert_eq!(samples.len() as u64, SEQUENCER_CYCLES as u64 * 4 * 44100 / CLOCK as u64);
+        assert!(samples.iter().any(|s| s[0] > 0.1) && samples.iter().any(|s| s[0] < -0.1));
+    }
+
+    #[test]
+    fn mute_scope_and_stems() {
+        let mut apu = apu();
+        apu.sample_rate = Some(44100);
+        apu.scope = Some(VecDeque::new());
+        apu.stems = Some(Vec::new());
+        apu.muted[1] = true;
+        apu.write(0xFF16, 0x80); // 50% duty
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF18, 0x00);
+        apu.write(0xFF19, 0x87); // frequency 0x700: 512Hz
+        assert_eq!(apu.frequency_hz(1), 512.0);
+        apu.tick(CLOCK / 8);
+
+        let scope = apu.scope.as_ref().unwrap();
+        assert_eq!(scope.len(), SCOPE_LEN);
+        assert!(scope.iter().any(|o| o[1] == 15) && scope.iter().any(|o| o[1] == 0));
+        assert!(scope.iter().all(|o| o[0] == 0));
+        // muted in the mix, but not in its stem
+        assert!(apu.take_samples().iter().all(|s| s[0] == 0.0));
+        let stems = apu.stems.take().unwrap();
+        let square = stem(&stems, 1);
+        assert_eq!(square.len(), stems.len() * 2);
+        assert!(square.iter().any(|&s| s > 0.1) && square.iter().any(|&s| s < -0.1));
+        assert!(stem(&stems, 0).iter().all(|&s| s == 0.0));
+    }
+}
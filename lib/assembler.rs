@@ -0,0 +1,861 @@
+//===== ASSEMBLER =====
+// RGBDS-style SM83 source to bytes: the inverse of INSTRUCTIONS. Supports labels (global,
+// `.local`), EQU constants, expressions, db/dw/ds and SECTION, in two passes: the first one
+// sizes every line and places the labels, the second one evaluates operands.
+use std::collections::HashMap;
+use std::fmt;
+use crate::instructions::*;
+use crate::tracer::rom_offset;
+
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: String,
+    pub kind: String, // ROM0, ROMX, WRAM0, HRAM...
+    pub bank: usize,
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+}
+
+// asm!("ld a, 5\nhalt") or asm!("ld a, {}", value): panics on errors, for tests
+#[macro_export]
+macro_rules! asm {
+    ($src:expr) => {
+        $crate::assembler::assemble($src).unwrap_or_else(|e| panic!("asm!: {}", e))
+    };
+    ($fmt:expr, $($arg:tt)*) => {
+        $crate::assembler::assemble(&format!($fmt, $($arg)*)).unwrap_or_else(|e| panic!("asm!: {}", e))
+    };
+}
+
+// ROM sections laid out as a ROM file (bank N at N * 0x4000); code before any SECTION goes
+// to ROM0 at 0x0000, so small snippets come back as just their bytes
+pub fn assemble(src: &str) -> Result<Vec<u8>, AsmError> {
+    let mut image = Vec::new();
+    for section in assemble_sections(src)? {
+        let offset = match section.kind.as_str() {
+            "ROM0" => section.origin as usize,
+            "ROMX" => rom_offset(section.bank, section.origin),
+            _ => continue,
+        };
+        let end = offset + section.bytes.len();
+        if image.len() < end {
+            image.resize(end, 0);
+        }
+        image[offset..end].copy_from_slice(&section.bytes);
+    }
+    Ok(image)
+}
+
+pub fn assemble_sections(src: &str) -> Result<Vec<Section>, AsmError> {
+    let mut asm = Assembler::new();
+    for (i, line) in src.lines().enumerate() {
+        asm.line = i + 1;
+        asm.first_pass(line)?;
+    }
+    asm.second_pass()
+}
+
+// --- operands ---
+// operand as written in the source
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Reg(RegType),
+    Cond(ConditionType),
+    Mem(RegType), // [bc], [de], [hl]
+    HighC,        // [c], [$ff00+c]
+    HlInc,
+    HlDec,
+    SpPlus(String), // sp+e8
+    Imm(String),
+    MemImm(String), // [n16]
+}
+
+// operand expected by an opcode
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pat {
+    Reg(RegType),
+    Cond(ConditionType),
+    Mem(RegType),
+    HighC,
+    HlInc,
+    HlDec,
+    Imm8,
+    Imm16,
+    Rel8,
+    Mem16,
+    High8,
+    SpPlus8,
+    Num(u8), // BIT/RES/SET bit number, RST vector
+}
+
+fn mnemonic(instr: &Instruction) -> String {
+    format!("{:?}", instr.instr_type).to_lowercase()
+}
+
+// operands of an opcode, mirroring the disassembler
+fn patterns(instr: &Instruction) -> Option<Vec<Pat>> {
+    use AddressingMode as AM;
+    let mut pats = Vec::new();
+    if !matches!(instr.cond, ConditionType::NONE) {
+        pats.push(Pat::Cond(instr.cond));
+    }
+    let mem_or_high = |reg: RegType| if reg == RegType::C { Pat::HighC } else { Pat::Mem(reg) };
+    match instr.mode {
+        AM::IMP => pats.extend(instr.param.map(Pat::Num)),
+        AM::R | AM::MR if instr.param.is_some() => {
+            pats.push(Pat::Num(instr.param.unwrap_or(0)));
+            pats.push(if instr.mode == AM::MR { Pat::Mem(instr.reg_1) } else { Pat::Reg(instr.reg_1) });
+        }
+        AM::R => pats.push(Pat::Reg(instr.reg_1)),
+        AM::MR => pats.push(Pat::Mem(instr.reg_1)),
+        AM::R_R => pats.extend([Pat::Reg(instr.reg_1), Pat::Reg(instr.reg_2)]),
+        AM::R_MR => pats.extend([Pat::Reg(instr.reg_1), mem_or_high(instr.reg_2)]),
+        AM::MR_R => pats.extend([mem_or_high(instr.reg_1), Pat::Reg(instr.reg_2)]),
+        AM::R_D8 => pats.extend([Pat::Reg(instr.reg_1), Pat::Imm8]),
+        AM::MR_D8 => pats.extend([Pat::Mem(instr.reg_1), Pat::Imm8]),
+        AM::R_D16 => pats.extend([Pat::Reg(instr.reg_1), Pat::Imm16]),
+        AM::D16 => pats.push(Pat::Imm16),
+        AM::D8 | AM::JR_D8 => pats.push(Pat::Rel8),
+        AM::A16_R => pats.extend([Pat::Mem16, Pat::Reg(instr.reg_2)]),
+        AM::R_A16 => pats.extend([Pat::Reg(instr.reg_1), Pat::Mem16]),
+        AM::A8_R => pats.extend([Pat::High8, Pat::Reg(instr.reg_2)]),
+        AM::R_A8 => pats.extend([Pat::Reg(instr.reg_1), Pat::High8]),
+        AM::HLI_R => pats.extend([Pat::HlInc, Pat::Reg(instr.reg_2)]),
+        AM::HLD_R => pats.extend([Pat::HlDec, Pat::Reg(instr.reg_2)]),
+        AM::R_HLI => pats.extend([Pat::Reg(instr.reg_1), Pat::HlInc]),
+        AM::R_HLD => pats.extend([Pat::Reg(instr.reg_1), Pat::HlDec]),
+        AM::HL_SPR if instr.reg_2 == RegType::SP => pats.extend([Pat::Reg(RegType::HL), Pat::SpPlus8]),
+        AM::HL_SPR => pats.extend([Pat::Reg(RegType::SP), Pat::Imm8]),
+        AM::JPHL => pats.push(Pat::Reg(RegType::HL)),
+        AM::RLCA | AM::CB => return None,
+    }
+    Some(pats)
+}
+
+// (opcode bytes, mnemonic, operands) for every encodable instruction, CB page included
+fn opcode_table() -> Vec<(Vec<u8>, String, Vec<Pat>)> {
+    let mut table = Vec::new();
+    for (op, instr) in INSTRUCTIONS.iter().enumerate() {
+        if matches!(instr.instr_type, InstructionType::ERR | InstructionType::NONE) {
+            continue;
+        }
+        if let Some(pats) = patterns(instr) {
+            table.push((vec![op as u8], mnemonic(instr), pats));
+        }
+    }
+    for op in 0..=0xFFu8 {
+        let instr = decode_cb(op);
+        if let Some(pats) = patterns(&instr) {
+            table.push((vec![0xCB, op], mnemonic(&instr), pats));
+        }
+    }
+    table
+}
+
+fn parse_reg(text: &str) -> Option<RegType> {
+    Some(match text {
+        "a" => RegType::A, "b" => RegType::B, "c" => RegType::C, "d" => RegType::D,
+        "e" => RegType::E, "h" => RegType::H, "l" => RegType::L, "af" => RegType::AF,
+        "bc" => RegType::BC, "de" => RegType::DE, "hl" => RegType::HL, "sp" => RegType::SP,
+        _ => return None,
+    })
+}
+
+fn parse_operand(text: &str) -> Operand {
+    let lower: String = text.to_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+    if let Some(reg) = parse_reg(&lower) {
+        return Operand::Reg(reg);
+    }
+    match lower.as_str() {
+        "nz" => return Operand::Cond(ConditionType::NZ),
+        "z" => return Operand::Cond(ConditionType::Z),
+        "nc" => return Operand::Cond(ConditionType::NC),
+        _ => {}
+    }
+    if lower.starts_with("sp+") || lower.starts_with("sp-") {
+        let offset = &text[text.find(['+', '-']).unwrap_or(0)..];
+        return Operand::SpPlus(offset.strip_prefix('+').unwrap_or(offset).trim().to_string());
+    }
+
+    // [..] is always memory, (..) only around registers so (1+2)*3 stays an expression
+    let bracketed = lower.starts_with('[') && lower.ends_with(']');
+    let parenthesed = lower.starts_with('(') && lower.ends_with(')');
+    if bracketed || parenthesed {
+        let inner = &lower[1..lower.len() - 1];
+        let form = match inner {
+            "bc" => Some(Operand::Mem(RegType::BC)),
+            "de" => Some(Operand::Mem(RegType::DE)),
+            "hl" => Some(Operand::Mem(RegType::HL)),
+            "hl+" | "hli" => Some(Operand::HlInc),
+            "hl-" | "hld" => Some(Operand::HlDec),
+            "c" | "$ff00+c" | "0xff00+c" | "ff00+c" => Some(Operand::HighC),
+            _ => None,
+        };
+        if let Some(form) = form {
+            return form;
+        }
+        if bracketed {
+            let trimmed = text.trim();
+            return Operand::MemImm(trimmed[1..trimmed.len() - 1].to_string());
+        }
+    }
+    Operand::Imm(text.trim().to_string())
+}
+
+// splits on commas outside of brackets, parentheses and strings
+fn split_operands(text: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut current = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '[' if !in_string => depth += 1,
+            ')' | ']' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    if !current.trim().is_empty() {
+        parts.push(current.trim().to_string());
+    }
+    parts
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '#'
+}
+
+// --- expressions ---
+struct Expr<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    symbols: &'a HashMap<String, i64>,
+    scope: &'a str,
+    pc: u16,
+}
+
+impl Expr<'_> {
+    fn eval(text: &str, symbols: &HashMap<String, i64>, scope: &str, pc: u16) -> Result<i64, String> {
+        let mut expr = Expr { chars: text.chars().collect(), pos: 0, symbols, scope, pc };
+        let value = expr.binary(0)?;
+        expr.skip_spaces();
+        match expr.pos == expr.chars.len() {
+            true => Ok(value),
+            false => Err(format!("unexpected '{}' in expression '{}'", expr.chars[expr.pos], text)),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek_op(&mut self) -> Option<(&'static str, usize)> {
+        self.skip_spaces();
+        // operator and binding power, loosest first
+        const OPS: [(&str, usize); 11] = [
+            ("<<", 4), (">>", 4), ("|", 1), ("^", 2), ("&", 3),
+            ("+", 5), ("-", 5), ("*", 6), ("/", 6), ("%", 6), ("", 0),
+        ];
+        let rest: String = self.chars[self.pos..].iter().take(2).collect();
+        OPS.iter().find(|(op, _)| !op.is_empty() && rest.starts_with(op)).copied()
+    }
+
+    fn binary(&mut self, min_power: usize) -> Result<i64, String> {
+        let mut left = self.unary()?;
+        while let Some((op, power)) = self.peek_op() {
+            if power <= min_power {
+                break;
+            }
+            self.pos += op.len();
+            let right = self.binary(power)?;
+            left = match op {
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err("division by zero".to_string()),
+                "/" => left / right,
+                _ => left % right,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        self.skip_spaces();
+        match self.chars.get(self.pos) {
+            Some('-') => { self.pos += 1; Ok(self.unary()?.wrapping_neg()) }
+            Some('+') => { self.pos += 1; self.unary() }
+            Some('~') => { self.pos += 1; Ok(!self.unary()?) }
+            _ => self.primary(),
+        }
+    }
+
+    fn digits(&mut self, radix: u32) -> Result<i64, String> {
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| c.is_digit(radix) || *c == '_') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().filter(|c| **c != '_').collect();
+        i64::from_str_radix(&text, radix).map_err(|_| format!("bad number '{}'", text))
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        let c = *self.chars.get(self.pos).ok_or("missing operand")?;
+        let next = self.chars.get(self.pos + 1).copied();
+        match c {
+            '(' => {
+                self.pos += 1;
+                let value = self.binary(0)?;
+                self.skip_spaces();
+                match self.chars.get(self.pos) {
+                    Some(')') => { self.pos += 1; Ok(value) }
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            '$' => { self.pos += 1; self.digits(16) }
+            '%' => { self.pos += 1; self.digits(2) }
+            '@' => { self.pos += 1; Ok(self.pc as i64) }
+            '0' if matches!(next, Some('x') | Some('X')) => { self.pos += 2; self.digits(16) }
+            '0' if matches!(next, Some('b') | Some('B')) => { self.pos += 2; self.digits(2) }
+            '\'' if self.chars.get(self.pos + 2) == Some(&'\'') => {
+                self.pos += 3;
+                Ok(next.unwrap_or('\0') as i64)
+            }
+            _ if c.is_ascii_digit() => self.digits(10),
+            _ if is_ident_start(c) => {
+                let start = self.pos;
+                while self.chars.get(self.pos).is_some_and(|c| is_ident_char(*c)) {
+                    self.pos += 1;
+                }
+                let name: String = self.chars[start..self.pos].iter().collect();
+                self.skip_spaces();
+                let lower = name.to_lowercase();
+                if (lower == "high" || lower == "low") && self.chars.get(self.pos) == Some(&'(') {
+                    let value = self.primary()?;
+                    return Ok(if lower == "high" { (value >> 8) & 0xFF } else { value & 0xFF });
+                }
+                let full = match name.starts_with('.') {
+                    true => format!("{}{}", self.scope, name),
+                    false => name.clone(),
+                };
+                self.symbols.get(&full).copied().ok_or(format!("undefined symbol '{}'", name))
+            }
+            _ => Err(format!("unexpected '{}'", c)),
+        }
+    }
+}
+
+// --- passes ---
+enum DataItem {
+    Expr(String),
+    Bytes(Vec<u8>),
+}
+
+enum StmtKind {
+    Instr { opcode: Vec<u8>, imm: Option<(Pat, String)> },
+    Data { width: usize, items: Vec<DataItem> },
+    Space { size: usize, fill: Option<String> },
+}
+
+struct Stmt {
+    line: usize,
+    section: usize,
+    pc: u16,
+    scope: String,
+    kind: StmtKind,
+}
+
+struct Assembler {
+    line: usize,
+    table: Vec<(Vec<u8>, String, Vec<Pat>)>,
+    symbols: HashMap<String, i64>,
+    scope: String,
+    sections: Vec<Section>,
+    pcs: Vec<u16>,
+    current: Option<usize>,
+    stmts: Vec<Stmt>,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            line: 0,
+            table: opcode_table(),
+            symbols: HashMap::new(),
+            scope: String::new(),
+            sections: Vec::new(),
+            pcs: Vec::new(),
+            current: None,
+            stmts: Vec::new(),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError { line: self.line, message: message.into() }
+    }
+
+    fn eval(&self, text: &str, pc: u16) -> Result<i64, AsmError> {
+        Expr::eval(text, &self.symbols, &self.scope, pc).map_err(|e| self.error(e))
+    }
+
+    fn section(&mut self) -> usize {
+        match self.current {
+            Some(section) => section,
+            None => {
+                self.open_section(String::new(), "ROM0".to_string(), None, None);
+                self.sections.len() - 1
+            }
+        }
+    }
+
+    fn open_section(&mut self, name: String, kind: String, origin: Option<u16>, bank: Option<usize>) {
+        let bank = bank.unwrap_or(if kind == "ROMX" { 1 } else { 0 });
+        // floating sections follow the previous one of the same type and bank
+        let origin = origin.unwrap_or_else(|| {
+            let previous = self.sections.iter().zip(&self.pcs).rev().find(|(s, _)| s.kind == kind && s.bank == bank);
+            match previous {
+                Some((_, pc)) => *pc,
+                None => match kind.as_str() {
+                    "ROMX" => 0x4000, "VRAM" => 0x8000, "SRAM" => 0xA000, "WRAM0" => 0xC000,
+                    "WRAMX" => 0xD000, "OAM" => 0xFE00, "HRAM" => 0xFF80, _ => 0x0000,
+                },
+            }
+        });
+        self.sections.push(Section { name, kind, bank, origin, bytes: Vec::new() });
+        self.pcs.push(origin);
+        self.current = Some(self.sections.len() - 1);
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), AsmError> {
+        let full = match name.starts_with('.') {
+            true => format!("{}{}", self.scope, name),
+            false => name.to_string(),
+        };
+        if self.symbols.insert(full.clone(), value).is_some() {
+            return Err(self.error(format!("'{}' is already defined", full)));
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, size: usize, kind: StmtKind) {
+        let section = self.section();
+        let pc = self.pcs[section];
+        self.stmts.push(Stmt { line: self.line, section, pc, scope: self.scope.clone(), kind });
+        self.pcs[section] = pc.wrapping_add(size as u16);
+    }
+
+    fn first_pass(&mut self, line: &str) -> Result<(), AsmError> {
+        let mut rest = strip_comment(line).trim();
+        if rest.is_empty() {
+            return Ok(());
+        }
+
+        // label: Name:, Name::, .local:
+        let ident_len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+        if ident_len > 0 && rest.starts_with(is_ident_start) && rest[ident_len..].starts_with(':') {
+            let name = &rest[..ident_len];
+            let section = self.section();
+            if !name.starts_with('.') {
+                self.scope = name.to_string();
+            }
+            self.define(name, self.pcs[section] as i64)?;
+            rest = rest[ident_len..].trim_start_matches(':').trim();
+            if rest.is_empty() {
+                return Ok(());
+            }
+        }
+
+        let (word, args) = match rest.find(char::is_whitespace) {
+            Some(i) => (&rest[..i], rest[i..].trim()),
+            None => (rest, ""),
+        };
+        let lower = word.to_lowercase();
+
+        // NAME EQU expr
+        if let Some((_, value)) = args.split_once(char::is_whitespace).filter(|(k, _)| k.eq_ignore_ascii_case("equ")) {
+            let pc = self.current.map(|s| self.pcs[s]).unwrap_or(0);
+            let value = self.eval(value.trim(), pc)?;
+            return self.define(word, value);
+        }
+
+        match lower.as_str() {
+            "section" => self.section_directive(args),
+            "db" | "dw" => {
+                let width = if lower == "db" { 1 } else { 2 };
+                let mut size = 0;
+                let mut items = Vec::new();
+                for item in split_operands(args) {
+                    match item.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                        Some(text) if width == 1 => {
+                            let bytes = unescape(text);
+                            size += bytes.len();
+                            items.push(DataItem::Bytes(bytes));
+                        }
+                        _ => {
+                            size += width;
+                            items.push(DataItem::Expr(item));
+                        }
+                    }
+                }
+                self.push(size, StmtKind::Data { width, items });
+                Ok(())
+            }
+            "ds" => {
+                let parts = split_operands(args);
+                let section = self.section();
+                let size = self.eval(parts.first().ok_or(self.error("ds needs a size"))?, self.pcs[section])?;
+                if !(0..=0x10000).contains(&size) {
+                    return Err(self.error(format!("bad ds size {}", size)));
+                }
+                self.push(size as usize, StmtKind::Space { size: size as usize, fill: parts.get(1).cloned() });
+                Ok(())
+            }
+            _ => self.instruction(&lower, args),
+        }
+    }
+
+    fn section_directive(&mut self, args: &str) -> Result<(), AsmError> {
+        let parts = split_operands(args);
+        let name = parts.first().and_then(|p| p.strip_prefix('"')).and_then(|p| p.strip_suffix('"'))
+            .ok_or(self.error("SECTION needs a quoted name"))?;
+        let kind_part = parts.get(1).ok_or(self.error("SECTION needs a type"))?;
+        // "ROMX[$4000]" -> ("ROMX", Some("$4000"))
+        let bracket = |text: &str| -> (String, Option<String>) {
+            match text.split_once('[') {
+                Some((word, inner)) => (word.trim().to_uppercase(), Some(inner.trim_end_matches(']').to_string())),
+                None => (text.trim().to_uppercase(), None),
+            }
+        };
+        let (kind, origin) = bracket(kind_part);
+        let origin = origin.map(|o| self.eval(&o, 0).map(|v| v as u16)).transpose()?;
+        let mut bank = None;
+        for part in &parts[2..] {
+            if let (word, Some(value)) = bracket(part) && word == "BANK" {
+                bank = Some(self.eval(&value, 0)? as usize);
+            }
+        }
+        self.open_section(name.to_string(), kind, origin, bank);
+        Ok(())
+    }
+
+    fn instruction(&mut self, word: &str, args: &str) -> Result<(), AsmError> {
+        let mut word = word.to_string();
+        let mut operands: Vec<Operand> = split_operands(args).iter().map(|a| parse_operand(a)).collect();
+
+        // aliases: ldi/ldd, ldh with (c), jp [hl], and the implicit A of 8-bit arithmetic
+        match word.as_str() {
+            "ldi" | "ldd" => {
+                let step = if word == "ldi" { Operand::HlInc } else { Operand::HlDec };
+                for op in operands.iter_mut().filter(|op| **op == Operand::Mem(RegType::HL)) {
+                    *op = step.clone();
+                }
+                word = "ld".to_string();
+            }
+            "ldh" if operands.contains(&Operand::HighC) => word = "ld".to_string(),
+            "jp" if operands == [Operand::Mem(RegType::HL)] => operands[0] = Operand::Reg(RegType::HL),
+            "add" | "adc" | "sub" | "sbc" | "and" | "xor" | "or" | "cp"
+                if operands.len() == 1 && !matches!(operands[0], Operand::Reg(RegType::HL) | Operand::Reg(RegType::SP)) =>
+            {
+                operands.insert(0, Operand::Reg(RegType::A));
+            }
+            _ => {}
+        }
+
+        let section = self.section();
+        let pc = self.pcs[section];
+        let mut found = None;
+        for (opcode, mnemonic, pats) in &self.table {
+            if *mnemonic != word || pats.len() != operands.len() {
+                continue;
+            }
+            let mut imm = None;
+            let mut matched = true;
+            for (pat, op) in pats.iter().zip(&operands) {
+                let ok = match (pat, op) {
+                    (Pat::Cond(ConditionType::C), Operand::Reg(RegType::C)) => true,
+                    (Pat::Num(n), Operand::Imm(text)) => self.eval(text, pc)? == *n as i64,
+                    (Pat::Imm8 | Pat::Imm16 | Pat::Rel8, Operand::Imm(text))
+                    | (Pat::Mem16 | Pat::High8, Operand::MemImm(text))
+                    | (Pat::SpPlus8, Operand::SpPlus(text)) => {
+                        imm = Some((*pat, text.clone()));
+                        true
+                    }
+                    (Pat::Reg(a), Operand::Reg(b)) => a == b,
+                    (Pat::Cond(a), Operand::Cond(b)) => a == b,
+                    (Pat::Mem(a), Operand::Mem(b)) => a == b,
+                    (Pat::HighC, Operand::HighC) | (Pat::HlInc, Operand::HlInc) | (Pat::HlDec, Operand::HlDec) => true,
+                    _ => false,
+                };
+                if !ok {
+                    matched = false;
+                    break;
+                }
+            }
+            if matched {
+                found = Some((opcode.clone(), imm));
+                break;
+            }
+        }
+
+        let Some((opcode, imm)) = found else {
+            return Err(self.error(format!("no instruction matches '{} {}'", word, args).trim_end().to_string()));
+        };
+        let size = match (opcode[0], &imm) {
+            (0x10, _) => 2, // STOP is padded with 0x00
+            (_, Some((Pat::Imm16 | Pat::Mem16, _))) => opcode.len() + 2,
+            (_, Some(_)) => opcode.len() + 1,
+            (_, None) => opcode.len(),
+        };
+        self.push(size, StmtKind::Instr { opcode, imm });
+        Ok(())
+    }
+
+    fn second_pass(mut self) -> Result<Vec<Section>, AsmError> {
+        let stmts = std::mem::take(&mut self.stmts);
+        for stmt in stmts {
+            self.line = stmt.line;
+            self.scope = stmt.scope;
+            let mut bytes = Vec::new();
+            match stmt.kind {
+                StmtKind::Instr { opcode, imm } => {
+                    bytes.extend(&opcode);
+                    if opcode == [0x10] {
+                        bytes.push(0x00);
+                    }
+                    if let Some((pat, text)) = imm {
+                        let value = self.eval(&text, stmt.pc)?;
+                        let end = stmt.pc as i64 + opcode.len() as i64 + 1;
+                        match pat {
+                            Pat::Imm16 | Pat::Mem16 => bytes.extend(self.fit16(value)?.to_le_bytes()),
+                            Pat::Rel8 => bytes.push(self.fit_signed(value - end, "jump target out of range")?),
+                            Pat::SpPlus8 => bytes.push(self.fit_signed(value, "offset out of range")?),
+                            Pat::High8 => match value {
+                                0xFF00..=0xFFFF => bytes.push(value as u8),
+                                _ => bytes.push(self.fit8(value)?),
+                            },
+                            _ => bytes.push(self.fit8(value)?),
+                        }
+                    }
+                }
+                StmtKind::Data { width, items } => {
+                    for item in items {
+                        match item {
+                            DataItem::Bytes(data) => bytes.extend(data),
+                            DataItem::Expr(text) if width == 1 => bytes.push(self.fit8(self.eval(&text, stmt.pc)?)?),
+                            DataItem::Expr(text) => bytes.extend(self.fit16(self.eval(&text, stmt.pc)?)?.to_le_bytes()),
+                        }
+                    }
+                }
+                StmtKind::Space { size, fill } => {
+                    let fill = match fill {
+                        Some(text) => self.fit8(self.eval(&text, stmt.pc)?)?,
+                        None => 0,
+                    };
+                    bytes.resize(size, fill);
+                }
+            }
+            self.sections[stmt.section].bytes.extend(bytes);
+        }
+        Ok(self.sections.into_iter().filter(|s| !s.bytes.is_empty() || !s.name.is_empty()).collect())
+    }
+
+    fn fit8(&self, value: i64) -> Result<u8, AsmError> {
+        match (-0x80..=0xFF).contains(&value) {
+            true => Ok(value as u8),
+            false => Err(self.error(format!("{} does not fit in 8 bits", value))),
+        }
+    }
+
+    fn fit16(&self, value: i64) -> Result<u16, AsmError> {
+        match (-0x8000..=0xFFFF).contains(&value) {
+            true => Ok(value as u16),
+            false => Err(self.error(format!("{} does not fit in 16 bits", value))),
+        }
+    }
+
+    fn fit_signed(&self, value: i64, message: &str) -> Result<u8, AsmError> {
+        match (-0x80..=0x7F).contains(&value) {
+            true => Ok(value as i8 as u8),
+            false => Err(self.error(format!("{} ({})", message, value))),
+        }
+    }
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(other) => other,
+                None => '\\',
+            },
+            _ => c,
+        };
+        let mut buf = [0; 4];
+        bytes.extend(c.encode_utf8(&mut buf).as_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::disassembler::{disassemble, Syntax};
+    use crate::tracer::RomMap;
+
+    #[test]
+    fn snippets() {
+        assert_eq!(crate::asm!("ld a,5\nhalt"), vec![0x3E, 0x05, 0x76]);
+        assert_eq!(crate::asm!("ld hl, {}", 0x1234), vec![0x21, 0x34, 0x12]);
+        assert_eq!(crate::asm!("ldi a,[hl]\nld (hl-),a\nldh [c],a\nld a,[$ff00+c]"), vec![0x2A, 0x32, 0xE2, 0xF2]);
+        assert_eq!(crate::asm!("ldh [$ff44], a\nldh a, [$80]"), vec![0xE0, 0x44, 0xF0, 0x80]);
+        assert_eq!(crate::asm!("sub a, b\ncp $10\nadd b\nadd hl, de"), vec![0x90, 0xFE, 0x10, 0x80, 0x19]);
+        assert_eq!(crate::asm!("bit 7, [hl]\nswap a\nset 0, b"), vec![0xCB, 0x7E, 0xCB, 0x37, 0xCB, 0xC0]);
+        assert_eq!(crate::asm!("jp hl\njp (hl)\nret c\nrst $38\nstop"), vec![0xE9, 0xE9, 0xD8, 0xFF, 0x10, 0x00]);
+        assert_eq!(crate::asm!("ld hl, sp-2\nadd sp, 5"), vec![0xF8, 0xFE, 0xE8, 0x05]);
+    }
+
+    #[test]
+    fn labels_and_expressions() {
+        let src = "
+            COUNT EQU 3 * 2
+            Start:
+                ld b, COUNT + 1   ; comment
+            .loop:
+                dec b
+                jr nz, .loop
+                jp Start
+            Table:
+                dw Start, Table
+                db \"Hi\", low(Table), high($1234) | %1, 'A'
+                ds 2, $FF
+        ";
+        assert_eq!(assemble(src).unwrap(), vec![
+            0x06, 0x07, 0x05, 0x20, 0xFD, 0xC3, 0x00, 0x00,
+            0x00, 0x00, 0x08, 0x00, b'H', b'i', 0x08, 0x13, 0x41, 0xFF, 0xFF,
+        ]);
+    }
+
+    #[test]
+    fn sections() {
+        let src = "
+            SECTION \"entry\", ROM0[$0100]
+                jp Main
+            SECTION \"bank2\", ROMX[$4000], BANK[2]
+            Main:
+                halt
+            SECTION \"vars\", WRAM0[$C000]
+            wVar: ds 1
+            SECTION \"code\", ROM0
+                ld a, [wVar]
+        ";
+        let sections = assemble_sections(src).unwrap();
+        assert_eq!(sections[0].bytes, vec![0xC3, 0x00, 0x40]);
+        assert_eq!((sections[1].bank, sections[1].origin), (2, 0x4000));
+        assert_eq!(sections[3].origin, 0x0103);
+        assert_eq!(sections[3].bytes, vec![0xFA, 0x00, 0xC0]);
+        let image = assemble(src).unwrap();
+        assert_eq!(image.len(), 0x8001);
+        assert_eq!(image[0x8000], 0x76);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(assemble("nop\nld a, [bc], b").unwrap_err().line, 2);
+        assert!(assemble("jp Nowhere").unwrap_err().message.contains("Nowhere"));
+        assert!(assemble("ld a, 300").is_err());
+        assert!(assemble("jr Far\nds 200\nFar:").is_err());
+        assert!(assemble("X:\nX:").is_err());
+    }
+
+    // every opcode disassembled and assembled again gives the same bytes
+    #[test]
+    fn disassembler_round_trip() {
+        for op in 0..=0xFFu8 {
+            let bytes = match op {
+                0xCB => continue,
+                0x10 => vec![0x10, 0x00],
+                _ => vec![op, 0x34, 0x12],
+            };
+            let line = disassemble(&bytes, 0x0150, Syntax::Rgbds);
+            if line.text.starts_with("db") {
+                continue;
+            }
+            let src = format!("SECTION \"t\", ROM0[$0150]\n{}", line.text);
+            let image = assemble(&src).unwrap_or_else(|e| panic!("{}: {}", line.text, e));
+            assert_eq!(&image[0x150..], &bytes[..line.len as usize], "{}", line.text);
+        }
+        for op in 0..=0xFFu8 {
+            let line = disassemble(&[0xCB, op], 0, Syntax::Rgbds);
+            assert_eq!(assemble(&line.text).unwrap(), vec![0xCB, op], "{}", line.text);
+        }
+    }
+
+    #[test]
+    fn tracer_output_reassembles() {
+        let mut rom = vec![0x00; 0x8000];
+        let code = crate::asm!("
+            SECTION \"boot\", ROM0[$0100]
+                nop
+                jp Main
+            SECTION \"main\", ROM0[$0150]
+            Main:
+                ld hl, sp+$05
+                call Sub
+                jr Main
+            Sub:
+                ldh a, [c]
+                ret
+                db $d3, $10, $05
+        ");
+        rom[..code.len()].copy_from_slice(&code);
+        let asm = RomMap::trace(&rom, &[]).to_asm();
+        assert_eq!(assemble(&asm).unwrap(), rom);
+    }
+}
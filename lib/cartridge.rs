@@ -0,0 +1,289 @@
+//===== CARTRIDGE =====
+// The cartridge header at 0x0100..0x014F (Pan Docs, "The Cartridge Header") and loading ROM
+// files, plain or zipped, optionally soft-patched (patch.rs) before the header is read. A ROM
+// the boot ROM would lock up on (bad header checksum) or one that is shorter than its header
+// says is rejected; the global checksum is only informational, as on hardware. `unsupported`
+// lists what the machine cannot run yet, e.g. mappers.
+use std::fmt;
+use crate::patch::{self, PatchError};
+use crate::zip::{self, ZipError};
+
+pub const HEADER_END: usize = 0x0150;
+pub const ROM_EXTENSIONS: [&str; 3] = ["gb", "gbc", "sgb"];
+
+#[derive(Debug, PartialEq)]
+pub enum CartridgeError {
+    TooSmall(usize),
+    HeaderChecksum { expected: u8, actual: u8 },
+    UnknownType(u8),
+    RomSize(u8),
+    Truncated { expected: usize, actual: usize },
+    Zip(ZipError),
+    NoRom, // a zip without a .gb/.gbc/.sgb file
+    Patch(PatchError),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::TooSmall(size) => write!(f, "{} bytes is too small for a ROM", size),
+            CartridgeError::HeaderChecksum { expected, actual } =>
+                write!(f, "header checksum is {:02X}, the header adds up to {:02X}", expected, actual),
+            CartridgeError::UnknownType(t) => write!(f, "unknown cartridge type {:02X}", t),
+            CartridgeError::RomSize(code) => write!(f, "unknown ROM size code {:02X}", code),
+            CartridgeError::Truncated { expected, actual } =>
+                write!(f, "the header says {}KB but the file has {} bytes", expected / 1024, actual),
+            CartridgeError::Zip(e) => write!(f, "zip: {}", e),
+            CartridgeError::NoRom => write!(f, "no .gb, .gbc or .sgb file in the zip"),
+            CartridgeError::Patch(e) => write!(f, "patch: {}", e),
+        }
+    }
+}
+
+// name of cartridge type byte 0x0147
+pub fn cartridge_type_name(code: u8) -> Option<&'static str> {
+    Some(match code {
+        0x00 => "ROM ONLY",
+        0x01 => "MBC1",
+        0x02 => "MBC1+RAM",
+        0x03 => "MBC1+RAM+BATTERY",
+        0x05 => "MBC2",
+        0x06 => "MBC2+BATTERY",
+        0x08 => "ROM+RAM",
+        0x09 => "ROM+RAM+BATTERY",
+        0x0B => "MMM01",
+        0x0C => "MMM01+RAM",
+        0x0D => "MMM01+RAM+BATTERY",
+        0x0F => "MBC3+TIMER+BATTERY",
+        0x10 => "MBC3+TIMER+RAM+BATTERY",
+        0x11 => "MBC3",
+        0x12 => "MBC3+RAM",
+        0x13 => "MBC3+RAM+BATTERY",
+        0x19 => "MBC5",
+        0x1A => "MBC5+RAM",
+        0x1B => "MBC5+RAM+BATTERY",
+        0x1C => "MBC5+RUMBLE",
+        0x1D => "MBC5+RUMBLE+RAM",
+        0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+        0x20 => "MBC6",
+        0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+        0xFC => "POCKET CAMERA",
+        0xFD => "BANDAI TAMA5",
+        0xFE => "HuC3",
+        0xFF => "HuC1+RAM+BATTERY",
+        _ => return None,
+    })
+}
+
+// the byte at 0x014D: x = x - byte - 1 over 0x0134..=0x014C
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[0x0134..=0x014C].iter().fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1))
+}
+
+// the big-endian word at 0x014E: sum of every other byte of the ROM
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter().enumerate()
+        .filter(|&(i, _)| i != 0x014E && i != 0x014F)
+        .fold(0u16, |sum, (_, &b)| sum.wrapping_add(b as u16))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub title: String,
+    pub cgb_flag: u8,         // 0x80 works on CGB too, 0xC0 CGB only
+    pub sgb: bool,            // uses SGB functions
+    pub cartridge_type: u8,
+    pub rom_size: usize,      // bytes
+    pub ram_size: usize,      // bytes
+    pub licensee: String,     // the new two-letter code, or the old one in hex
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    pub global_checksum_ok: bool,
+}
+
+impl Header {
+    pub fn parse(rom: &[u8]) -> Result<Header, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+        let actual = header_checksum(rom);
+        if actual != rom[0x014D] {
+            return Err(CartridgeError::HeaderChecksum { expected: rom[0x014D], actual });
+        }
+        let cartridge_type = rom[0x0147];
+        cartridge_type_name(cartridge_type).ok_or(CartridgeError::UnknownType(cartridge_type))?;
+        let rom_size = match rom[0x0148] {
+            code @ 0..=8 => 0x8000 << code,
+            code => return Err(CartridgeError::RomSize(code)),
+        };
+        if rom.len() < rom_size {
+            return Err(CartridgeError::Truncated { expected: rom_size, actual: rom.len() });
+        }
+        let ram_size = match rom[0x0149] {
+            2 => 0x2000,
+            3 => 0x8000,
+            4 => 0x20000,
+            5 => 0x10000,
+            _ => 0,
+        };
+
+        // CGB titles stop at 0x0143, which holds the CGB flag
+        let cgb_flag = rom[0x0143] & 0xC0;
+        let title_end = if cgb_flag & 0x80 != 0 { 0x0143 } else { 0x0144 };
+        let title = rom[0x0134..title_end].iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '?' })
+            .collect::<String>().trim_end().to_string();
+        let licensee = match rom[0x014B] {
+            0x33 => String::from_utf8_lossy(&rom[0x0144..0x0146]).into_owned(),
+            code => format!("{:02X}", code),
+        };
+        let global = u16::from_be_bytes([rom[0x014E], rom[0x014F]]);
+        Ok(Header {
+            title,
+            cgb_flag,
+            sgb: rom[0x0146] == 0x03,
+            cartridge_type,
+            rom_size,
+            ram_size,
+            licensee,
+            version: rom[0x014C],
+            header_checksum: actual,
+            global_checksum: global,
+            global_checksum_ok: global_checksum(rom) == global,
+        })
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        cartridge_type_name(self.cartridge_type).unwrap_or("?")
+    }
+
+    // what keeps this ROM from running properly here; a 32KB MBC1 ROM (blargg's single
+    // cpu_instrs tests) has nothing to switch, so it runs on the flat bus
+    pub fn unsupported(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let flat = matches!(self.cartridge_type, 0x00 | 0x08 | 0x09)
+            || matches!(self.cartridge_type, 0x01 | 0x02) && self.rom_size <= 0x8000;
+        if !flat {
+            problems.push(format!("the {} mapper is not emulated, only the first 32KB are mapped", self.type_name()));
+        } else if self.rom_size > 0x8000 {
+            problems.push(format!("{}KB without a mapper, only the first 32KB are mapped", self.rom_size / 1024));
+        }
+        if self.cgb_flag == 0xC0 {
+            problems.push("the game needs a Game Boy Color, CGB graphics are not emulated".to_string());
+        }
+        problems
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cgb = match self.cgb_flag {
+            0xC0 => "CGB only",
+            0x80 => "CGB enhanced",
+            _ => "no",
+        };
+        writeln!(f, "Title      {}", self.title)?;
+        writeln!(f, "Type       {} ({:02X})", self.type_name(), self.cartridge_type)?;
+        writeln!(f, "ROM        {}KB ({} banks)", self.rom_size / 1024, self.rom_size / 0x4000)?;
+        writeln!(f, "RAM        {}KB", self.ram_size / 1024)?;
+        writeln!(f, "CGB        {}", cgb)?;
+        writeln!(f, "SGB        {}", if self.sgb { "yes" } else { "no" })?;
+        writeln!(f, "Licensee   {}", self.licensee)?;
+        writeln!(f, "Version    {}", self.version)?;
+        writeln!(f, "Header sum {:02X} ok", self.header_checksum)?;
+        write!(f, "Global sum {:04X} {}", self.global_checksum, if self.global_checksum_ok { "ok" } else { "mismatch" })
+    }
+}
+
+// a ROM file, or the first .gb/.gbc/.sgb file of a zip, with `patch` applied and its header
+pub fn load(data: &[u8], patch: Option<&[u8]>) -> Result<(Vec<u8>, Header), CartridgeError> {
+    let rom = match data.starts_with(&[0x50, 0x4B, 0x03, 0x04]) {
+        true => {
+            let name = zip::names(data).map_err(CartridgeError::Zip)?.into_iter()
+                .find(|name| name.rsplit_once('.').is_some_and(|(_, ext)| ROM_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())))
+                .ok_or(CartridgeError::NoRom)?;
+            zip::read(data, &name).map_err(CartridgeError::Zip)?.ok_or(CartridgeError::NoRom)?
+        }
+        false => data.to_vec(),
+    };
+    let rom = match patch {
+        Some(patch) => patch::apply(patch, &rom).map_err(CartridgeError::Patch)?,
+        None => rom,
+    };
+    let header = Header::parse(&rom)?;
+    Ok((rom, header))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rom(title: &str, cartridge_type: u8, rom_code: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000 << rom_code];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+        rom[0x0147] = cartridge_type;
+        rom[0x0148] = rom_code;
+        rom[0x0149] = 2;
+        rom[0x014B] = 0x01;
+        rom[0x014D] = header_checksum(&rom);
+        let global = global_checksum(&rom);
+        rom[0x014E..0x0150].copy_from_slice(&global.to_be_bytes());
+        rom
+    }
+
+    #[test]
+    fn parses_the_header() {
+        let header = Header::parse(&rom("TETRIS", 0x00, 0)).unwrap();
+        assert_eq!((header.title.as_str(), header.type_name(), header.rom_size, header.ram_size), ("TETRIS", "ROM ONLY", 0x8000, 0x2000));
+        assert_eq!(header.licensee, "01");
+        assert!(header.global_checksum_ok);
+        assert!(header.unsupported().is_empty());
+
+        let mut cgb = rom("POKEMON CRYSTAL", 0x10, 2);
+        cgb[0x0143] = 0xC0;
+        cgb[0x014D] = header_checksum(&cgb);
+        let header = Header::parse(&cgb).unwrap();
+        assert_eq!((header.title.as_str(), header.rom_size), ("POKEMON CRYSTAL", 0x20000));
+        assert!(!header.global_checksum_ok);
+        assert_eq!(header.unsupported().len(), 2);
+    }
+
+    #[test]
+    fn rejects_bad_roms() {
+        assert_eq!(Header::parse(&[0; 0x100]), Err(CartridgeError::TooSmall(0x100)));
+        let mut bad = rom("GAME", 0x00, 0);
+        bad[0x0134] = b'X';
+        assert!(matches!(Header::parse(&bad), Err(CartridgeError::HeaderChecksum { .. })));
+        let mut short = rom("GAME", 0x01, 1);
+        short.truncate(0x8000);
+        assert_eq!(Header::parse(&short), Err(CartridgeError::Truncated { expected: 0x10000, actual: 0x8000 }));
+        assert_eq!(Header::parse(&rom("GAME", 0x42, 0)), Err(CartridgeError::UnknownType(0x42)));
+    }
+
+    #[test]
+    fn loads_from_zip() {
+        let game = rom("ZIPPED", 0x00, 0);
+        let archive = zip::build(&[("readme.txt", 0, b"hi".to_vec()), ("Game.GB", 0, game.clone())]);
+        assert_eq!(load(&archive, None).unwrap().0, game);
+        let archive = zip::build(&[("readme.txt", 0, b"hi".to_vec())]);
+        assert_eq!(load(&archive, None), Err(CartridgeError::NoRom));
+    }
+
+    #[test]
+    fn patches_before_the_header() {
+        let game = rom("GAME", 0x00, 0);
+        let hacked = [&game[..0x0134], b"HACK", &game[0x0138..]].concat();
+        let mut ips = b"PATCH".to_vec();
+        ips.extend_from_slice(&[0x00, 0x01, 0x34, 0x00, 0x04]);
+        ips.extend_from_slice(b"HACK");
+        // the header checksum no longer matches without this record
+        let checksum = [0x00, 0x01, 0x4D, 0x00, 0x01, header_checksum(&hacked)];
+        let archive = zip::build(&[("game.gb", 0, game.clone())]);
+        let load_with = |records: &[u8]| load(&archive, Some(&[&ips, records, b"EOF"].concat()));
+        assert_eq!(load_with(&checksum).unwrap().1.title, "HACK");
+        assert!(matches!(load_with(&[]), Err(CartridgeError::HeaderChecksum { .. })));
+        assert_eq!(load(&game, Some(b"UPS1")), Err(CartridgeError::Patch(PatchError::Corrupt("cut short"))));
+    }
+}
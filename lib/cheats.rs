@@ -0,0 +1,214 @@
+//===== CHEATS =====
+// GameShark and Game Genie codes in front of the bus. A GameShark code `ttvvaaaa` writes vv to
+// RAM address aaaa (low byte first) at every VBlank, the bank in tt is ignored as there is no
+// mapper. A Game Genie code `ABC-DEF-GHI` makes ROM address FCDE ^ F000 read as AB, but only
+// while the ROM holds the compare value GI (rotated right by 2, ^ BA); `ABC-DEF` has none.
+// Cheat files hold one code per line: `on 01FF34D1 Infinite lives`, `off 3E1-23B-F0E`.
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::mmu::{BusAccess, Mmu};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Code {
+    GameShark { kind: u8, value: u8, addr: u16 },
+    GameGenie { addr: u16, value: u8, compare: Option<u8> },
+}
+
+impl Code {
+    pub fn parse(text: &str) -> Result<Code, String> {
+        let digits: Vec<u8> = text.trim().chars().filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or(format!("'{}' is not a hex code", text.trim()))?;
+        let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+        match digits.len() {
+            8 => Ok(Code::GameShark { kind: byte(0), value: byte(2), addr: u16::from_le_bytes([byte(4), byte(6)]) }),
+            6 | 9 => {
+                let addr = ((digits[5] ^ 0x0F) as u16) << 12 | (digits[2] as u16) << 8 | (digits[3] as u16) << 4 | digits[4] as u16;
+                if addr >= 0x8000 {
+                    return Err(format!("'{}' patches {:04X}, Game Genie codes patch ROM (0000-7FFF)", text.trim(), addr));
+                }
+                let compare = (digits.len() == 9).then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+                Ok(Code::GameGenie { addr, value: byte(0), compare })
+            }
+            _ => Err(format!("'{}' is neither a GameShark code (01vvaaaa) nor a Game Genie code (ABC-DEF or ABC-DEF-GHI)", text.trim())),
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Code::GameShark { kind, value, addr } => write!(f, "GameShark: {:02X} to {:04X} (type {:02X})", value, addr, kind),
+            Code::GameGenie { addr, value, compare: Some(compare) } =>
+                write!(f, "Game Genie: {:04X} reads {:02X} when it holds {:02X}", addr, value, compare),
+            Code::GameGenie { addr, value, compare: None } => write!(f, "Game Genie: {:04X} reads {:02X}", addr, value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cheat {
+    pub text: String, // as entered, for the list and the file
+    pub name: String,
+    pub code: Code,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    pub fn new(text: &str, name: &str) -> Result<Cheat, String> {
+        let code = Code::parse(text)?;
+        Ok(Cheat { text: text.trim().to_ascii_uppercase(), name: name.trim().to_string(), code, enabled: true })
+    }
+}
+
+pub fn parse_file(text: &str) -> Result<Vec<Cheat>, String> {
+    let mut cheats = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (state, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let enabled = match state {
+            "on" => true,
+            "off" => false,
+            _ => return Err(format!("line {}: expected 'on' or 'off', not '{}'", number + 1, state)),
+        };
+        let rest = rest.trim_start();
+        let (code, name) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let mut cheat = Cheat::new(code, name).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        cheat.enabled = enabled;
+        cheats.push(cheat);
+    }
+    Ok(cheats)
+}
+
+pub fn to_file(cheats: &[Cheat]) -> String {
+    cheats.iter()
+        .map(|cheat| format!("{} {} {}", if cheat.enabled { "on" } else { "off" }, cheat.text, cheat.name).trim_end().to_string() + "\n")
+        .collect()
+}
+
+pub struct Cheats<T: Mmu> {
+    pub bus: T,
+    list: Vec<Cheat>,
+    patches: Vec<(u16, u8, Option<u8>)>, // the enabled Game Genie codes, looked up on every ROM read
+}
+
+impl<T: Mmu> Cheats<T> {
+    pub fn new(bus: T) -> Cheats<T> {
+        Cheats { bus, list: Vec::new(), patches: Vec::new() }
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.list
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.list.push(cheat);
+        self.update();
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.list.remove(index);
+        self.update();
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        self.list[index].enabled = enabled;
+        self.update();
+    }
+
+    pub fn replace(&mut self, cheats: Vec<Cheat>) {
+        self.list = cheats;
+        self.update();
+    }
+
+    fn update(&mut self) {
+        self.patches = self.list.iter().filter(|cheat| cheat.enabled).filter_map(|cheat| match cheat.code {
+            Code::GameGenie { addr, value, compare } => Some((addr, value, compare)),
+            Code::GameShark { .. } => None,
+        }).collect();
+    }
+
+    // the GameShark writes, once per VBlank
+    pub fn apply(&mut self) {
+        for cheat in self.list.iter().filter(|cheat| cheat.enabled) {
+            if let Code::GameShark { value, addr, .. } = cheat.code {
+                self.bus.write(addr, value);
+            }
+        }
+    }
+}
+
+impl<T: Mmu> Mmu for Cheats<T> {
+    fn read(&self, addr: u16) -> u8 {
+        let value = self.bus.read(addr);
+        if addr >= 0x8000 || self.patches.is_empty() {
+            return value;
+        }
+        self.patches.iter()
+            .find(|&&(at, _, compare)| at == addr && compare.is_none_or(|compare| compare == value))
+            .map_or(value, |&(_, patched, _)| patched)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.bus.write(addr, value);
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        self.bus.rom_bank(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        self.bus.access(addr, kind, cycle);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    #[test]
+    fn decodes_codes() {
+        assert_eq!(Code::parse("01FF34D1"), Ok(Code::GameShark { kind: 0x01, value: 0xFF, addr: 0xD134 }));
+        // 3E at 4123 over 05: F of FCDE is 4 ^ F, GI is (05 ^ BA) rotated left by 2
+        assert_eq!(Code::parse("3e1-23b-f0e"), Ok(Code::GameGenie { addr: 0x4123, value: 0x3E, compare: Some(0x05) }));
+        assert_eq!(Code::parse("3E1-23B"), Ok(Code::GameGenie { addr: 0x4123, value: 0x3E, compare: None }));
+        assert!(Code::parse("3E1-234").is_err()); // B123 is not ROM
+        assert!(Code::parse("01FF34D").is_err());
+        assert!(Code::parse("XYZ").is_err());
+    }
+
+    #[test]
+    fn patches_reads_and_writes_ram() {
+        let mut bus = FlatMmu::new();
+        bus.write(0x4123, 0x05);
+        bus.write(0x0150, 0x06);
+        let mut cheats = Cheats::new(bus);
+        cheats.add(Cheat::new("3E1-23B-F0E", "").unwrap());
+        cheats.add(Cheat::new("3E1-50F", "").unwrap()); // 0150, no compare
+        cheats.add(Cheat::new("01FF34D1", "Infinite lives").unwrap());
+        assert_eq!((cheats.read(0x4123), cheats.read(0x0150)), (0x3E, 0x3E));
+        // another bank would hold something else there
+        cheats.bus.write(0x4123, 0x07);
+        assert_eq!(cheats.read(0x4123), 0x07);
+
+        assert_eq!(cheats.read(0xD134), 0x00);
+        cheats.apply();
+        assert_eq!(cheats.read(0xD134), 0xFF);
+        cheats.set_enabled(1, false);
+        assert_eq!(cheats.read(0x0150), 0x06);
+
+        let file = to_file(cheats.list());
+        assert_eq!(file, "on 3E1-23B-F0E\noff 3E1-50F\non 01FF34D1 Infinite lives\n");
+        assert_eq!(parse_file(&file).unwrap(), cheats.list());
+        assert!(parse_file("maybe 01FF34D1").is_err());
+    }
+}
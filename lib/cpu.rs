@@ -1,3 +1,31 @@
-use crate::registers::*
-use crate::instructions::*
-
+pub mod registers;
+pub mod instructions;
+pub mod mmu;
+pub mod model;
+pub mod sgb;
+pub mod disassembler;
+pub mod tracer;
+pub mod assembler;
+pub mod processor;
+pub mod debugger;
+pub mod watch;
+pub mod gdb;
+pub mod doctor;
+pub mod testrom;
+pub mod screenshot;
+pub mod savestate;
+pub mod rewind;
+pub mod joypad;
+pub mod zip;
+pub mod movie;
+pub mod lcd;
+pub mod vram;
+pub mod apu;
+pub mod wav;
+pub mod machine;
+pub mod cartridge;
+pub mod cheats;
+pub mod search;
+pub mod patch;
+#[cfg(test)]
+mod single_step;
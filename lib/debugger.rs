@@ -0,0 +1,529 @@
+//===== DEBUGGER =====
+// Breakpoints and stepping around Cpu::step, plus a small command language for a terminal
+// REPL. Locations are `[bank:]addr` in hex, conditions are `&&`-joined register comparisons
+// and flag tests, e.g. `break 1:4123 if a == $3 && !z`.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use crate::disassembler::*;
+use crate::doctor::TraceLog;
+use crate::instructions::InstructionType;
+use crate::mmu::Mmu;
+use crate::processor::Cpu;
+use crate::registers::{CpuFlag, Registers};
+use crate::watch::*;
+
+// a "continue" without breakpoints gives up after 10 emulated seconds
+pub const RUN_LIMIT: u64 = 70224 * 60 * 10;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Location {
+    pub bank: Option<usize>, // None matches any bank
+    pub addr: u16,
+}
+
+impl Location {
+    // "1:4000", "4000" or "$4000"
+    pub fn parse(text: &str) -> Option<Location> {
+        let hex = |t: &str| usize::from_str_radix(t.trim_start_matches('$').trim_start_matches("0x"), 16).ok();
+        let (bank, addr) = match text.split_once(':') {
+            Some((bank, addr)) => (Some(hex(bank)?), hex(addr)?),
+            None => (None, hex(text)?),
+        };
+        Some(Location { bank, addr: u16::try_from(addr).ok()? })
+    }
+
+    // banks only tell ROM addresses apart, RAM matches on the address alone
+    pub fn matches<T: Mmu>(&self, mmu: &T, addr: u16) -> bool {
+        self.addr == addr && (addr >= 0x8000 || self.bank.is_none_or(|bank| mmu.rom_bank(addr) == bank))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Operand {
+    A, B, C, D, E, H, L, AF, BC, DE, HL, SP, PC,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Term {
+    Flag(u8, bool), // CpuFlag mask and expected state
+    Compare(Operand, &'static str, u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    terms: Vec<Term>,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let mut terms = Vec::new();
+        for term in text.split("&&").map(str::trim) {
+            terms.push(Self::term(term).ok_or(format!("bad condition '{}'", term))?);
+        }
+        Ok(Condition { terms })
+    }
+
+    fn term(text: &str) -> Option<Term> {
+        let flag = |name: &str| match name {
+            "z" => Some(CpuFlag::Z as u8),
+            "n" => Some(CpuFlag::N as u8),
+            "h" => Some(CpuFlag::H as u8),
+            "c" => Some(CpuFlag::C as u8),
+            _ => None,
+        };
+        let lower = text.to_lowercase();
+        match lower.as_str() {
+            "nz" => return Some(Term::Flag(CpuFlag::Z as u8, false)),
+            "nc" => return Some(Term::Flag(CpuFlag::C as u8, false)),
+            _ => {}
+        }
+        if let Some(mask) = lower.strip_prefix('!').and_then(flag) {
+            return Some(Term::Flag(mask, false));
+        }
+        if let Some(mask) = flag(&lower) {
+            return Some(Term::Flag(mask, true));
+        }
+
+        let op = ["==", "!=", "<=", ">=", "<", ">"].into_iter().find(|op| lower.contains(op))?;
+        let (reg, value) = lower.split_once(op)?;
+        let reg = match reg.trim() {
+            "a" => Operand::A, "b" => Operand::B, "c" => Operand::C, "d" => Operand::D,
+            "e" => Operand::E, "h" => Operand::H, "l" => Operand::L, "af" => Operand::AF,
+            "bc" => Operand::BC, "de" => Operand::DE, "hl" => Operand::HL, "sp" => Operand::SP,
+            "pc" => Operand::PC,
+            _ => return None,
+        };
+        Some(Term::Compare(reg, op, parse_value(value.trim())?))
+    }
+
+    pub fn eval(&self, regs: &Registers) -> bool {
+        self.terms.iter().all(|term| match *term {
+            Term::Flag(mask, state) => {
+                let flag = [CpuFlag::Z, CpuFlag::N, CpuFlag::H, CpuFlag::C].into_iter().find(|f| *f as u8 == mask);
+                flag.is_some_and(|f| regs.getflag(&f) == state)
+            }
+            Term::Compare(reg, op, value) => {
+                let current = match reg {
+                    Operand::A => regs.a as u16, Operand::B => regs.b as u16, Operand::C => regs.c as u16,
+                    Operand::D => regs.d as u16, Operand::E => regs.e as u16, Operand::H => regs.h as u16,
+                    Operand::L => regs.l as u16, Operand::AF => regs.af(), Operand::BC => regs.bc(),
+                    Operand::DE => regs.de(), Operand::HL => regs.hl(), Operand::SP => regs.sp,
+                    Operand::PC => regs.pc,
+                };
+                match op {
+                    "==" => current == value,
+                    "!=" => current != value,
+                    "<=" => current <= value,
+                    ">=" => current >= value,
+                    "<" => current < value,
+                    _ => current > value,
+                }
+            }
+        })
+    }
+}
+
+// $ff and 0xff are hex, plain numbers decimal
+fn parse_value(text: &str) -> Option<u16> {
+    match text.strip_prefix('$').or(text.strip_prefix("0x")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub at: Location,
+    pub cond: Option<Condition>,
+    pub hits: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stop {
+    Step,
+    Breakpoint(usize),
+    Watchpoint(usize),
+    Limit,  // run_limit cycles went by
+    Locked, // the CPU hit an illegal opcode
+}
+
+pub struct Debugger {
+    pub cpu: Cpu,
+    pub breakpoints: BTreeMap<usize, Breakpoint>,
+    pub watch: Watchpoints,
+    pub run_limit: u64, // cycles a run command may take before it gives up
+    pub trace: Option<TraceLog>, // gameboy-doctor log of every instruction run
+    next_id: usize,
+    last_command: String,
+}
+
+impl Debugger {
+    pub fn new(cpu: Cpu) -> Debugger {
+        Debugger { cpu, breakpoints: BTreeMap::new(), watch: Watchpoints::new(), run_limit: RUN_LIMIT, trace: None, next_id: 1, last_command: String::new() }
+    }
+
+    pub fn add_breakpoint(&mut self, at: Location, cond: Option<Condition>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.insert(id, Breakpoint { at, cond, hits: 0 });
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.breakpoints.remove(&id).is_some()
+    }
+
+    fn breakpoint_hit<T: Mmu>(&mut self, mmu: &T) -> Option<usize> {
+        let (pc, regs) = (self.cpu.regs.pc, self.cpu.regs);
+        let (id, bp) = self.breakpoints.iter_mut()
+            .find(|(_, bp)| bp.at.matches(mmu, pc) && bp.cond.as_ref().is_none_or(|c| c.eval(&regs)))?;
+        bp.hits += 1;
+        Some(*id)
+    }
+
+    // steps until `done` says so or a breakpoint is reached; the instruction under PC always
+    // runs first so resuming from a breakpoint does not stop on it again
+    fn run_until<T: Mmu>(&mut self, mmu: &mut T, mut done: impl FnMut(&Cpu, &T, InstructionType) -> bool) -> Stop {
+        let start = self.cpu.cycles;
+        loop {
+            let instr = self.cpu.peek(mmu).instr_type;
+            if let Some(log) = &mut self.trace {
+                log.log(&self.cpu, mmu).ok();
+            }
+            let before = self.cpu.cycles;
+            match self.watch.is_empty() {
+                true => {
+                    self.cpu.step(mmu);
+                    mmu.stepped(&self.cpu, before);
+                }
+                false => {
+                    let pc = self.cpu.regs.pc;
+                    self.cpu.step(&mut self.watch.attach(mmu, pc, before));
+                    mmu.stepped(&self.cpu, before);
+                    let pc = self.cpu.regs.pc;
+                    self.watch.check(mmu, Access::Execute, pc, mmu.read(pc), pc, self.cpu.cycles);
+                }
+            }
+            if self.cpu.locked {
+                return Stop::Locked;
+            }
+            if let Some(id) = self.watch.take_stop() {
+                return Stop::Watchpoint(id);
+            }
+            if done(&self.cpu, mmu, instr) {
+                return Stop::Step;
+            }
+            if let Some(id) = self.breakpoint_hit(mmu) {
+                return Stop::Breakpoint(id);
+            }
+            if self.cpu.cycles - start >= self.run_limit {
+                return Stop::Limit;
+            }
+        }
+    }
+
+    pub fn step_into<T: Mmu>(&mut self, mmu: &mut T) -> Stop {
+        self.run_until(mmu, |_, _, _| true)
+    }
+
+    // runs a CALL or RST until it returns, anything else is a single step
+    pub fn step_over<T: Mmu>(&mut self, mmu: &mut T) -> Stop {
+        let instr = self.cpu.peek(mmu);
+        if !matches!(instr.instr_type, InstructionType::CALL | InstructionType::RST) {
+            return self.step_into(mmu);
+        }
+        let (ret, sp) = (self.cpu.regs.pc.wrapping_add(instr.size()), self.cpu.regs.sp);
+        self.run_until(mmu, |cpu, _, _| cpu.regs.pc == ret && cpu.regs.sp >= sp)
+    }
+
+    // runs until a RET/RETI pops the frame that was current when it started
+    pub fn step_out<T: Mmu>(&mut self, mmu: &mut T) -> Stop {
+        let sp = self.cpu.regs.sp;
+        self.run_until(mmu, |cpu, _, instr| {
+            matches!(instr, InstructionType::RET | InstructionType::RETI) && cpu.regs.sp > sp
+        })
+    }
+
+    pub fn run_to<T: Mmu>(&mut self, mmu: &mut T, at: Location) -> Stop {
+        self.run_until(mmu, |cpu, mmu, _| at.matches(mmu, cpu.regs.pc))
+    }
+
+    pub fn resume<T: Mmu>(&mut self, mmu: &mut T) -> Stop {
+        self.run_until(mmu, |_, _, _| false)
+    }
+
+    // --- REPL ---
+    pub fn status<T: Mmu>(&self, mmu: &T) -> String {
+        let r = &self.cpu.regs;
+        let flag = |f: CpuFlag, c: char| if r.getflag(&f) { c } else { '-' };
+        let line = disassemble_at(mmu, r.pc, Syntax::Rgbds);
+        format!(
+            "AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} [{}{}{}{}] IME={} cycles={}\n{:02X}:{:04X}  {}",
+            r.af(), r.bc(), r.de(), r.hl(), r.sp,
+            flag(CpuFlag::Z, 'Z'), flag(CpuFlag::N, 'N'), flag(CpuFlag::H, 'H'), flag(CpuFlag::C, 'C'),
+            self.cpu.ime as u8, self.cpu.cycles, mmu.rom_bank(r.pc), r.pc, line.text,
+        )
+    }
+
+    fn stopped<T: Mmu>(&self, mmu: &T, stop: Stop) -> String {
+        let reason = match stop {
+            Stop::Step => String::new(),
+            Stop::Breakpoint(id) => format!("breakpoint {} hit\n", id),
+            Stop::Watchpoint(id) => {
+                let hit = self.watch.hits().into_iter().rev().find(|h| h.id == id);
+                format!("watchpoint {} hit: {}\n", id, hit.map_or(String::new(), |h| h.to_string()))
+            }
+            Stop::Limit => "stopped after the run limit, no breakpoint reached\n".to_string(),
+            Stop::Locked => "CPU locked up on an illegal opcode\n".to_string(),
+        };
+        reason + &self.status(mmu)
+    }
+
+    // runs one command line and returns what to print; an empty line repeats the last command
+    pub fn command<T: Mmu>(&mut self, mmu: &mut T, line: &str) -> String {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        self.last_command = line.clone();
+        let mut words = line.split_whitespace();
+        let Some(cmd) = words.next() else { return String::new() };
+        let args: Vec<&str> = words.collect();
+
+        match cmd {
+            "s" | "step" => {
+                let count = args.first().and_then(|n| n.parse().ok()).unwrap_or(1);
+                let mut stop = Stop::Step;
+                for _ in 0..count {
+                    stop = self.step_into(mmu);
+                    if stop != Stop::Step {
+                        break;
+                    }
+                }
+                self.stopped(mmu, stop)
+            }
+            "n" | "next" => {
+                let stop = self.step_over(mmu);
+                self.stopped(mmu, stop)
+            }
+            "finish" | "out" => {
+                let stop = self.step_out(mmu);
+                self.stopped(mmu, stop)
+            }
+            "c" | "continue" => {
+                let stop = self.resume(mmu);
+                self.stopped(mmu, stop)
+            }
+            "u" | "until" => match args.first().and_then(|a| Location::parse(a)) {
+                Some(at) => {
+                    let stop = self.run_to(mmu, at);
+                    self.stopped(mmu, stop)
+                }
+                None => "usage: until [bank:]addr".to_string(),
+            },
+            "b" | "break" => {
+                let Some(at) = args.first().and_then(|a| Location::parse(a)) else {
+                    return "usage: break [bank:]addr [if condition]".to_string();
+                };
+                let cond = match args.get(1) {
+                    Some(&"if") => match Condition::parse(&args[2..].join(" ")) {
+                        Ok(cond) => Some(cond),
+                        Err(e) => return e,
+                    },
+                    Some(_) => return "usage: break [bank:]addr [if condition]".to_string(),
+                    None => None,
+                };
+                let id = self.add_breakpoint(at, cond);
+                format!("breakpoint {} at {}", id, describe(at))
+            }
+            "d" | "delete" => match args.first().and_then(|a| a.parse().ok()) {
+                Some(id) if self.remove_breakpoint(id) => format!("deleted breakpoint {}", id),
+                _ => "no such breakpoint".to_string(),
+            },
+            "bl" | "breakpoints" => {
+                let mut out = String::new();
+                for (id, bp) in &self.breakpoints {
+                    let cond = if bp.cond.is_some() { " (conditional)" } else { "" };
+                    writeln!(out, "{:>3}  {}{}  hits={}", id, describe(bp.at), cond, bp.hits).unwrap();
+                }
+                match out.is_empty() {
+                    true => "no breakpoints".to_string(),
+                    false => out.trim_end().to_string(),
+                }
+            }
+            "watch" | "trace" => {
+                let point = match args[..] {
+                    [kinds, range] => Watchpoint::parse(range, kinds, cmd == "watch"),
+                    _ => None,
+                };
+                match point {
+                    Some(point) => format!("watchpoint {}: {}", self.watch.add(point), point),
+                    None => format!("usage: {} r|w|x|rw... [bank:]addr[-addr]|register", cmd),
+                }
+            }
+            "unwatch" => match args.first().and_then(|a| a.parse().ok()) {
+                Some(id) if self.watch.remove(id) => format!("deleted watchpoint {}", id),
+                _ => "no such watchpoint".to_string(),
+            },
+            "wl" | "watchpoints" => {
+                let lines: Vec<String> = self.watch.iter().map(|(id, p)| format!("{:>3}  {}", id, p)).collect();
+                match lines.is_empty() {
+                    true => "no watchpoints".to_string(),
+                    false => lines.join("\n"),
+                }
+            }
+            "hits" if args.first() == Some(&"clear") => {
+                self.watch.clear_hits();
+                "hit log cleared".to_string()
+            }
+            "hits" => {
+                let hits = self.watch.hits();
+                let count = args.first().and_then(|n| n.parse().ok()).unwrap_or(20).min(hits.len());
+                let lines: Vec<String> = hits[hits.len() - count..].iter().map(Hit::to_string).collect();
+                match lines.is_empty() {
+                    true => "no hits".to_string(),
+                    false => lines.join("\n"),
+                }
+            }
+            "r" | "regs" => self.status(mmu),
+            "x" => {
+                let Some(addr) = args.first().and_then(|a| Location::parse(a)) else {
+                    return "usage: x addr [count]".to_string();
+                };
+                let count: u16 = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(64);
+                let mut out = String::new();
+                for row in (0..count).step_by(16) {
+                    let start = addr.addr.wrapping_add(row);
+                    let bytes: Vec<String> = (0..16.min(count - row))
+                        .map(|i| format!("{:02X}", mmu.read(start.wrapping_add(i))))
+                        .collect();
+                    writeln!(out, "{:04X}: {}", start, bytes.join(" ")).unwrap();
+                }
+                out.trim_end().to_string()
+            }
+            "l" | "list" => {
+                let mut addr = args.first().and_then(|a| Location::parse(a)).map_or(self.cpu.regs.pc, |l| l.addr);
+                let count = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(10);
+                let mut out = String::new();
+                for _ in 0..count {
+                    let line = disassemble_at(mmu, addr, Syntax::Rgbds);
+                    let marker = if addr == self.cpu.regs.pc { '>' } else { ' ' };
+                    writeln!(out, "{} {:04X}  {}", marker, addr, line.text).unwrap();
+                    addr = addr.wrapping_add(line.len);
+                }
+                out.trim_end().to_string()
+            }
+            "h" | "help" => HELP.to_string(),
+            _ => format!("unknown command '{}', try help", cmd),
+        }
+    }
+}
+
+fn describe(at: Location) -> String {
+    match at.bank {
+        Some(bank) => format!("{:02X}:{:04X}", bank, at.addr),
+        None => format!("{:04X}", at.addr),
+    }
+}
+
+const HELP: &str = "\
+s, step [n]                 run n instructions (default 1)
+n, next                     step over CALL and RST
+finish, out                 run until the current function returns
+u, until [bank:]addr        run to an address
+c, continue                 run until a breakpoint
+b, break [bank:]addr [if c] add a breakpoint, e.g. break 1:4000 if a == $3 && !z
+d, delete id                remove a breakpoint
+bl, breakpoints             list breakpoints
+r, regs                     show registers
+x addr [count]              dump memory
+l, list [addr] [count]      disassemble
+watch kinds range           stop on r/w/x accesses, e.g. watch w nr52, watch rw 1:4000-4fff
+trace kinds range           log accesses without stopping
+unwatch id                  remove a watchpoint
+wl, watchpoints             list watchpoints
+hits [n|clear]              show the last n logged accesses
+save file, load file        write or restore a save state
+rewind [n]                  go back n frames (default 1)
+q, quit                     exit";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    // 0x100: call $0110 / rst $38 / halt; 0x110: ld a, 3 / dec a / jr nz, -3 / ret; 0x38: ret
+    fn setup() -> (Debugger, FlatMmu) {
+        let mut mmu = FlatMmu::new();
+        mmu.load(0x0100, &crate::asm!("call $0110\nrst $38\nhalt"));
+        mmu.load(0x0110, &crate::asm!("ld a, 3\nLoop:\ndec a\njr nz, Loop\nret"));
+        mmu.load(0x0038, &crate::asm!("ret"));
+        (Debugger::new(Cpu::default()), mmu)
+    }
+
+    #[test]
+    fn step_over_and_out() {
+        let (mut dbg, mut mmu) = setup();
+        assert_eq!(dbg.step_over(&mut mmu), Stop::Step);
+        assert_eq!(dbg.cpu.regs.pc, 0x0103);
+        assert_eq!(dbg.step_over(&mut mmu), Stop::Step); // rst
+        assert_eq!(dbg.cpu.regs.pc, 0x0104);
+
+        let (mut dbg, mut mmu) = setup();
+        dbg.step_into(&mut mmu);
+        assert_eq!(dbg.cpu.regs.pc, 0x0110);
+        dbg.step_into(&mut mmu);
+        assert_eq!(dbg.step_out(&mut mmu), Stop::Step);
+        assert_eq!(dbg.cpu.regs.pc, 0x0103);
+        assert_eq!(dbg.cpu.regs.a, 0);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (mut dbg, mut mmu) = setup();
+        let dec = Location::parse("0:0112").unwrap();
+        let id = dbg.add_breakpoint(dec, Some(Condition::parse("a == 1 && !z").unwrap()));
+        assert_eq!(dbg.resume(&mut mmu), Stop::Breakpoint(id));
+        assert_eq!(dbg.cpu.regs.a, 1);
+        // wrong bank never matches
+        dbg.remove_breakpoint(id);
+        dbg.add_breakpoint(Location { bank: Some(1), addr: 0x0038 }, None);
+        assert_eq!(dbg.run_to(&mut mmu, Location::parse("104").unwrap()), Stop::Step);
+        assert_eq!(dbg.cpu.regs.pc, 0x0104);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (mut dbg, mut mmu) = setup();
+        // the RST pushes the return address, then the handler is entered
+        assert_eq!(dbg.command(&mut mmu, "trace w fffc-fffd"), "watchpoint 1: FFFC-FFFD w log");
+        let id = dbg.watch.add(Watchpoint::parse("0038", "x", true).unwrap());
+        assert_eq!(dbg.resume(&mut mmu), Stop::Watchpoint(id));
+        assert_eq!(dbg.cpu.regs.pc, 0x0038);
+        let hits = dbg.watch.hits();
+        assert_eq!(hits.len(), 5); // two for the CALL, two for the RST, then the execute hit
+        assert_eq!((hits[2].pc, hits[2].addr, hits[2].value), (0x0103, 0xFFFD, 0x01));
+        assert!(dbg.command(&mut mmu, "hits 1").ends_with("X 0038=C9 cycle=108"));
+    }
+
+    #[test]
+    fn conditions() {
+        let mut regs = Registers::new();
+        regs.a = 0x10;
+        assert!(Condition::parse("a >= $10 && z && c").unwrap().eval(&regs));
+        assert!(!Condition::parse("nz").unwrap().eval(&regs));
+        assert!(Condition::parse("hl == $014d").unwrap().eval(&regs));
+        assert!(Condition::parse("q == 1").is_err());
+    }
+
+    #[test]
+    fn repl() {
+        let (mut dbg, mut mmu) = setup();
+        assert_eq!(dbg.command(&mut mmu, "break 0:110"), "breakpoint 1 at 00:0110");
+        assert!(dbg.command(&mut mmu, "c").starts_with("breakpoint 1 hit\n"));
+        assert!(dbg.command(&mut mmu, "s").ends_with("00:0112  dec a"));
+        assert!(dbg.command(&mut mmu, "").ends_with("00:0113  jr nz, $0112"));
+        assert!(dbg.command(&mut mmu, "bl").contains("hits=1"));
+        assert_eq!(dbg.command(&mut mmu, "x 100 4"), "0100: CD 10 01 FF");
+    }
+}
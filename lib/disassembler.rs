@@ -0,0 +1,333 @@
+//===== DISASSEMBLER =====
+// Turns bytes into text using the metadata of INSTRUCTIONS and the decoded CB page
+use crate::instructions::*;
+use crate::mmu::Mmu;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Syntax {
+    Rgbds, // ld a, [hl+]   ldh [$ff44], a   jp hl
+    NoGmb, // LD A,(HL+)    LD ($FF00+$44),A JP (HL)
+}
+
+// One disassembled instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub addr: u16,
+    pub len: u16,
+    pub text: String,
+    pub target: Option<u16>, // destination of JR/JP/CALL/RST, for following control flow
+}
+
+pub fn disassemble(bytes: &[u8], addr: u16, syntax: Syntax) -> Line {
+    disassemble_with(bytes, addr, syntax, |_| None)
+}
+
+// like disassemble(), reading the bytes through the bus
+pub fn disassemble_at<T: Mmu>(mmu: &T, addr: u16, syntax: Syntax) -> Line {
+    let bytes = [mmu.read(addr), mmu.read(addr.wrapping_add(1)), mmu.read(addr.wrapping_add(2))];
+    disassemble(&bytes, addr, syntax)
+}
+
+// `label` may name an address, it is used instead of the number for jump targets and a16 operands
+pub fn disassemble_with(bytes: &[u8], addr: u16, syntax: Syntax, label: impl Fn(u16) -> Option<String>) -> Line {
+    let fmt = Formatter { syntax, label: &label };
+    let Some(&opcode) = bytes.first() else {
+        return Line { addr, len: 0, text: String::new(), target: None };
+    };
+
+    let (instr, len) = match opcode {
+        0xCB => match bytes.get(1) {
+            Some(&op) => (decode_cb(op), 2),
+            None => (INSTRUCTIONS[0xCB], 2),
+        },
+        _ => (INSTRUCTIONS[opcode as usize], INSTRUCTIONS[opcode as usize].size()),
+    };
+    // unused opcodes and instructions cut off by the end of the data are emitted as bytes
+    if matches!(instr.instr_type, InstructionType::ERR) || bytes.len() < len as usize {
+        return Line { addr, len: 1, text: fmt.data_byte(opcode), target: None };
+    }
+
+    let d8 = bytes.get(1).copied().unwrap_or(0);
+    let d16 = u16::from_le_bytes([d8, bytes.get(2).copied().unwrap_or(0)]);
+    let jr_target = addr.wrapping_add(2).wrapping_add(d8 as i8 as u16);
+
+    let mut target = None;
+    let mut operands: Vec<String> = Vec::new();
+    if !matches!(instr.cond, ConditionType::NONE) {
+        operands.push(fmt.cond(instr.cond));
+    }
+
+    use AddressingMode as AM;
+    use InstructionType as IN;
+    match instr.mode {
+        AM::IMP => {
+            if let (IN::RST, Some(vector)) = (instr.instr_type, instr.param) {
+                target = Some(vector as u16);
+                operands.push(fmt.hex8(vector));
+            }
+        }
+        AM::R | AM::MR if instr.param.is_some() => {
+            // BIT/RES/SET n, r
+            operands.push(instr.param.unwrap_or(0).to_string());
+            operands.push(fmt.reg_or_mem(instr.reg_1, instr.mode == AM::MR));
+        }
+        AM::R => operands.push(fmt.reg(instr.reg_1)),
+        AM::MR => operands.push(fmt.mem(&fmt.reg(instr.reg_1))),
+        AM::R_R => {
+            if !fmt.implied_a(instr) {
+                operands.push(fmt.reg(instr.reg_1));
+            }
+            operands.push(fmt.reg(instr.reg_2));
+        }
+        AM::R_MR => {
+            if !fmt.implied_a(instr) {
+                operands.push(fmt.reg(instr.reg_1));
+            }
+            operands.push(fmt.high_or_mem(instr.reg_2));
+        }
+        AM::MR_R => {
+            operands.push(fmt.high_or_mem(instr.reg_1));
+            operands.push(fmt.reg(instr.reg_2));
+        }
+        AM::R_D8 => {
+            if !fmt.implied_a(instr) {
+                operands.push(fmt.reg(instr.reg_1));
+            }
+            operands.push(fmt.hex8(d8));
+        }
+        AM::MR_D8 => {
+            operands.push(fmt.mem(&fmt.reg(instr.reg_1)));
+            operands.push(fmt.hex8(d8));
+        }
+        AM::R_D16 => {
+            operands.push(fmt.reg(instr.reg_1));
+            operands.push(fmt.hex16(d16));
+        }
+        AM::D16 => {
+            target = Some(d16);
+            operands.push(fmt.address(d16));
+        }
+        AM::D8 | AM::JR_D8 => {
+            target = Some(jr_target);
+            operands.push(fmt.address(jr_target));
+        }
+        AM::A16_R => {
+            operands.push(fmt.mem(&fmt.address(d16)));
+            operands.push(fmt.reg(instr.reg_2));
+        }
+        AM::R_A16 => {
+            operands.push(fmt.reg(instr.reg_1));
+            operands.push(fmt.mem(&fmt.address(d16)));
+        }
+        AM::A8_R => {
+            operands.push(fmt.high(&fmt.hex8(d8)));
+            operands.push(fmt.reg(instr.reg_2));
+        }
+        AM::R_A8 => {
+            operands.push(fmt.reg(instr.reg_1));
+            operands.push(fmt.high(&fmt.hex8(d8)));
+        }
+        AM::HLI_R | AM::HLD_R => {
+            operands.push(fmt.mem(&fmt.hl_step(instr.mode == AM::HLI_R)));
+            operands.push(fmt.reg(instr.reg_2));
+        }
+        AM::R_HLI | AM::R_HLD => {
+            operands.push(fmt.reg(instr.reg_1));
+            operands.push(fmt.mem(&fmt.hl_step(instr.mode == AM::R_HLI)));
+        }
+        AM::HL_SPR => {
+            let offset = fmt.signed(d8 as i8);
+            match instr.reg_2 {
+                // LD HL, SP+e8
+                RegType::SP => {
+                    operands.push(fmt.reg(RegType::HL));
+                    operands.push(format!("{}{}", fmt.reg(RegType::SP), offset));
+                }
+                // ADD SP, e8
+                _ => {
+                    operands.push(fmt.reg(RegType::SP));
+                    operands.push(offset.trim_start_matches('+').to_string());
+                }
+            }
+        }
+        AM::JPHL => operands.push(match syntax {
+            Syntax::Rgbds => fmt.reg(RegType::HL),
+            Syntax::NoGmb => fmt.mem(&fmt.reg(RegType::HL)),
+        }),
+        AM::RLCA | AM::CB => {}
+    }
+
+    let mnemonic = fmt.mnemonic(instr);
+    let text = match operands.is_empty() {
+        true => mnemonic,
+        false => format!("{} {}", mnemonic, operands.join(fmt.separator())),
+    };
+    Line { addr, len, text, target }
+}
+
+struct Formatter<'a> {
+    syntax: Syntax,
+    label: &'a dyn Fn(u16) -> Option<String>,
+}
+
+impl Formatter<'_> {
+    fn case(&self, text: &str) -> String {
+        match self.syntax {
+            Syntax::Rgbds => text.to_lowercase(),
+            Syntax::NoGmb => text.to_uppercase(),
+        }
+    }
+
+    fn separator(&self) -> &'static str {
+        match self.syntax {
+            Syntax::Rgbds => ", ",
+            Syntax::NoGmb => ",",
+        }
+    }
+
+    fn mnemonic(&self, instr: Instruction) -> String {
+        match (instr.instr_type, self.syntax) {
+            (InstructionType::LDH, Syntax::NoGmb) => "LD".to_string(),
+            // LD (C), A and LD A, (C) are LDH in RGBDS
+            (InstructionType::LD, Syntax::Rgbds) if instr.reg_1 == RegType::C && instr.mode == AddressingMode::MR_R => "ldh".to_string(),
+            (InstructionType::LD, Syntax::Rgbds) if instr.reg_2 == RegType::C && instr.mode == AddressingMode::R_MR => "ldh".to_string(),
+            (instr_type, _) => self.case(&format!("{:?}", instr_type)),
+        }
+    }
+
+    // SUB, AND, XOR, OR and CP are written without their A operand
+    fn implied_a(&self, instr: Instruction) -> bool {
+        use InstructionType::*;
+        matches!(instr.instr_type, SUB | AND | XOR | OR | CP) && instr.reg_1 == RegType::A
+    }
+
+    fn reg(&self, reg: RegType) -> String {
+        self.case(&format!("{:?}", reg))
+    }
+
+    fn cond(&self, cond: ConditionType) -> String {
+        self.case(&format!("{:?}", cond))
+    }
+
+    fn mem(&self, inner: &str) -> String {
+        match self.syntax {
+            Syntax::Rgbds => format!("[{}]", inner),
+            Syntax::NoGmb => format!("({})", inner),
+        }
+    }
+
+    fn reg_or_mem(&self, reg: RegType, mem: bool) -> String {
+        match mem {
+            true => self.mem(&self.reg(reg)),
+            false => self.reg(reg),
+        }
+    }
+
+    // (C) is the high page 0xFF00+C, every other register pair is a plain pointer
+    fn high_or_mem(&self, reg: RegType) -> String {
+        match reg {
+            RegType::C => self.high(&self.reg(RegType::C)),
+            _ => self.mem(&self.reg(reg)),
+        }
+    }
+
+    fn high(&self, offset: &str) -> String {
+        match self.syntax {
+            Syntax::Rgbds if offset.starts_with('$') => self.mem(&format!("$ff{}", &offset[1..])),
+            Syntax::Rgbds => self.mem(offset),
+            Syntax::NoGmb => self.mem(&format!("$FF00+{}", offset)),
+        }
+    }
+
+    fn hl_step(&self, inc: bool) -> String {
+        format!("{}{}", self.reg(RegType::HL), if inc { "+" } else { "-" })
+    }
+
+    fn hex8(&self, value: u8) -> String {
+        self.case(&format!("${:02X}", value))
+    }
+
+    fn hex16(&self, value: u16) -> String {
+        self.case(&format!("${:04X}", value))
+    }
+
+    fn address(&self, value: u16) -> String {
+        (self.label)(value).unwrap_or_else(|| self.hex16(value))
+    }
+
+    fn signed(&self, value: i8) -> String {
+        match value < 0 {
+            true => format!("-{}", self.hex8(value.unsigned_abs())),
+            false => format!("+{}", self.hex8(value as u8)),
+        }
+    }
+
+    fn data_byte(&self, value: u8) -> String {
+        format!("{} {}", self.case("db"), self.hex8(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rgbds(bytes: &[u8]) -> String {
+        disassemble(bytes, 0x0150, Syntax::Rgbds).text
+    }
+
+    fn nogmb(bytes: &[u8]) -> String {
+        disassemble(bytes, 0x0150, Syntax::NoGmb).text
+    }
+
+    #[test]
+    fn rgbds_syntax() {
+        assert_eq!(rgbds(&[0x00]), "nop");
+        assert_eq!(rgbds(&[0x2A]), "ld a, [hl+]");
+        assert_eq!(rgbds(&[0x32]), "ld [hl-], a");
+        assert_eq!(rgbds(&[0x01, 0x34, 0x12]), "ld bc, $1234");
+        assert_eq!(rgbds(&[0x20, 0xFE]), "jr nz, $0150");
+        assert_eq!(rgbds(&[0xFF]), "rst $38");
+        assert_eq!(rgbds(&[0xE0, 0x44]), "ldh [$ff44], a");
+        assert_eq!(rgbds(&[0xF2]), "ldh a, [c]");
+        assert_eq!(rgbds(&[0x90]), "sub b");
+        assert_eq!(rgbds(&[0x8E]), "adc a, [hl]");
+        assert_eq!(rgbds(&[0xE8, 0xFE]), "add sp, -$02");
+        assert_eq!(rgbds(&[0xF8, 0x05]), "ld hl, sp+$05");
+        assert_eq!(rgbds(&[0xE9]), "jp hl");
+        assert_eq!(rgbds(&[0xC0]), "ret nz");
+        assert_eq!(rgbds(&[0x08, 0x00, 0xC0]), "ld [$c000], sp");
+        assert_eq!(rgbds(&[0x10, 0x00]), "stop");
+        assert_eq!(rgbds(&[0xD3]), "db $d3");
+    }
+
+    #[test]
+    fn nogmb_syntax() {
+        assert_eq!(nogmb(&[0x2A]), "LD A,(HL+)");
+        assert_eq!(nogmb(&[0xF0, 0x44]), "LD A,($FF00+$44)");
+        assert_eq!(nogmb(&[0xE2]), "LD ($FF00+C),A");
+        assert_eq!(nogmb(&[0xE9]), "JP (HL)");
+        assert_eq!(nogmb(&[0xCC, 0x00, 0x40]), "CALL Z,$4000");
+    }
+
+    #[test]
+    fn cb_page_and_lengths() {
+        let line = disassemble(&[0xCB, 0x7E], 0, Syntax::Rgbds);
+        assert_eq!((line.text.as_str(), line.len), ("bit 7, [hl]", 2));
+        assert_eq!(rgbds(&[0xCB, 0x11]), "rl c");
+        assert_eq!(rgbds(&[0xCB, 0xFF]), "set 7, a");
+        assert_eq!(disassemble(&[0xC3, 0x50, 0x01], 0, Syntax::Rgbds).len, 3);
+        // truncated operand
+        assert_eq!(disassemble(&[0xC3, 0x50], 0, Syntax::Rgbds).text, "db $c3");
+    }
+
+    #[test]
+    fn targets_and_labels() {
+        let line = disassemble(&[0x18, 0x10], 0x0200, Syntax::Rgbds);
+        assert_eq!(line.target, Some(0x0212));
+        let line = disassemble_with(&[0xCD, 0x00, 0x20], 0x0200, Syntax::Rgbds, |addr| {
+            (addr == 0x2000).then(|| "Init".to_string())
+        });
+        assert_eq!(line.text, "call Init");
+        assert_eq!(line.target, Some(0x2000));
+    }
+}
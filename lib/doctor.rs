@@ -0,0 +1,135 @@
+//===== DOCTOR =====
+// Execution trace in the gameboy-doctor format, one line per instruction before it runs:
+//   A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02
+// Reference logs are taken with LY stuck at 0x90, which DoctorMmu reproduces.
+use std::io::{self, Write};
+use crate::mmu::{BusAccess, Mmu};
+use crate::processor::Cpu;
+
+pub const LY: u16 = 0xFF44;
+
+pub fn trace_line<T: Mmu>(cpu: &Cpu, mmu: &T) -> String {
+    let r = &cpu.regs;
+    let pc = r.pc;
+    let mem = |i: u16| mmu.read(pc.wrapping_add(i));
+    format!(
+        "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+        r.a, r.af() as u8, r.b, r.c, r.d, r.e, r.h, r.l, r.sp, pc, mem(0), mem(1), mem(2), mem(3),
+    )
+}
+
+pub struct TraceLog {
+    out: Box<dyn Write>,
+}
+
+impl TraceLog {
+    pub fn new(out: impl Write + 'static) -> TraceLog {
+        TraceLog { out: Box::new(io::BufWriter::new(out)) }
+    }
+
+    // logs the instruction about to run, interrupt dispatches and halted cycles are not logged
+    pub fn log<T: Mmu>(&mut self, cpu: &Cpu, mmu: &T) -> io::Result<()> {
+        if cpu.executes(mmu) {
+            writeln!(self.out, "{}", trace_line(cpu, mmu))?;
+        }
+        Ok(())
+    }
+
+    pub fn step<T: Mmu>(&mut self, cpu: &mut Cpu, mmu: &mut T) -> io::Result<u32> {
+        self.log(cpu, mmu)?;
+        Ok(cpu.step(mmu))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl Drop for TraceLog {
+    fn drop(&mut self) {
+        self.out.flush().ok();
+    }
+}
+
+// LY always reads 0x90 (the first VBlank line), as in the reference logs
+pub struct DoctorMmu<T: Mmu> {
+    pub bus: T,
+}
+
+impl<T: Mmu> Mmu for DoctorMmu<T> {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            LY => 0x90,
+            _ => self.bus.read(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.bus.write(addr, value);
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        self.bus.rom_bank(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        self.bus.access(addr, kind, cycle);
+    }
+
+    fn stepped(&mut self, cpu: &Cpu, before: u64) {
+        self.bus.stepped(cpu, before);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::mmu::FlatMmu;
+    use crate::processor::{IE, IF};
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn doctor_format() {
+        let mut mmu = DoctorMmu { bus: FlatMmu::new() };
+        mmu.bus.load(0x0100, &[0x00, 0xC3, 0x13, 0x02]);
+        let cpu = Cpu::default();
+        assert_eq!(trace_line(&cpu, &mmu), "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02");
+        assert_eq!(mmu.read(LY), 0x90);
+    }
+
+    #[test]
+    fn interrupts_are_not_lines() {
+        let mut mmu = FlatMmu::new();
+        mmu.load(0x0100, &crate::asm!("ei\nnop\nhalt"));
+        mmu.write(IE, 0x01);
+        mmu.write(IF, 0x01);
+        let out = Shared::default();
+        let mut log = TraceLog::new(out.clone());
+        let mut cpu = Cpu::default();
+        for _ in 0..4 {
+            log.step(&mut cpu, &mut mmu).unwrap();
+        }
+        log.flush().unwrap();
+        let text = String::from_utf8(out.0.borrow().clone()).unwrap();
+        let pcs: Vec<&str> = text.lines().map(|l| &l[l.find("PC:").unwrap()..][..7]).collect();
+        assert_eq!(pcs, ["PC:0100", "PC:0101", "PC:0040"]);
+    }
+}
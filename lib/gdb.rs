@@ -0,0 +1,430 @@
+//===== GDB STUB =====
+// GDB remote serial protocol over TCP on top of the Debugger. The register file is AF, BC,
+// DE, HL, SP, PC (16-bit, little endian) and is described to the client through target.xml.
+// Software and hardware breakpoints are both kept by the debugger, nothing is patched into
+// memory; addresses above 0xFFFF carry a ROM bank in the upper bits (bank << 16 | addr). With no
+// banked access to the cartridge, m and M on such an address fail unless that bank is mapped.
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use crate::debugger::*;
+use crate::mmu::Mmu;
+use crate::processor::FRAME_CYCLES;
+use crate::watch::*;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.sm83.core">
+    <reg name="af" bitsize="16" type="uint16"/>
+    <reg name="bc" bitsize="16" type="uint16"/>
+    <reg name="de" bitsize="16" type="uint16"/>
+    <reg name="hl" bitsize="16" type="uint16"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// SIGTRAP for breakpoints and steps, SIGINT when the client interrupts, SIGILL on a lock up
+const SIGTRAP: &str = "S05";
+const SIGINT: &str = "S02";
+const SIGILL: &str = "S04";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Point {
+    Break(usize),
+    Watch(usize),
+}
+
+pub struct GdbStub<'a, T: Mmu> {
+    pub dbg: &'a mut Debugger,
+    pub mmu: &'a mut T,
+    points: HashMap<(u8, u32), Point>, // Z packet type and address
+    no_ack: bool,
+    pub detached: bool,
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    let digit = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+    text.chunks(2).map(|pair| Some(digit(pair[0])? << 4 | digit(pair[1])?)).collect()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+// "$data#cs"
+pub fn frame(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data.as_bytes()))
+}
+
+fn ack_or_interrupt(byte: u8) -> bool {
+    matches!(byte, b'+' | b'-' | 0x03)
+}
+
+// takes the next whole packet out of `pending`: its data and whether the checksum matched. Acks
+// and interrupts ahead of it need no answer and are dropped; a packet cut short by the read is
+// left alone, '+' and '-' inside it included, until the rest arrives.
+fn next_packet(pending: &mut Vec<u8>) -> Option<(Vec<u8>, bool)> {
+    let skip = pending.iter().take_while(|b| ack_or_interrupt(**b)).count();
+    pending.drain(..skip);
+    let start = pending.iter().position(|b| *b == b'$')?;
+    let hash = start + pending[start..].iter().position(|b| *b == b'#')?;
+    if pending.len() < hash + 3 {
+        return None;
+    }
+    let data = pending[start + 1..hash].to_vec();
+    let sum = std::str::from_utf8(&pending[hash + 1..hash + 3]).ok().and_then(|sum| u8::from_str_radix(sum, 16).ok());
+    pending.drain(..hash + 3);
+    let ok = sum == Some(checksum(&data));
+    Some((data, ok))
+}
+
+impl<'a, T: Mmu> GdbStub<'a, T> {
+    pub fn new(dbg: &'a mut Debugger, mmu: &'a mut T) -> GdbStub<'a, T> {
+        GdbStub { dbg, mmu, points: HashMap::new(), no_ack: false, detached: false }
+    }
+
+    fn registers(&self) -> [u16; 6] {
+        let r = &self.dbg.cpu.regs;
+        [r.af(), r.bc(), r.de(), r.hl(), r.sp, r.pc]
+    }
+
+    fn set_register(&mut self, index: usize, value: u16) -> bool {
+        let r = &mut self.dbg.cpu.regs;
+        match index {
+            0 => r.setaf(value),
+            1 => r.setbc(value),
+            2 => r.setde(value),
+            3 => r.sethl(value),
+            4 => r.sp = value,
+            5 => r.pc = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn stop_reply(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Watchpoint(id) => {
+                let hit = self.dbg.watch.hits().into_iter().rev().find(|h| h.id == id);
+                let kind = self.points.iter().find(|(_, p)| **p == Point::Watch(id)).map(|((kind, _), _)| *kind);
+                let name = match kind {
+                    Some(b'3') => "rwatch",
+                    Some(b'4') => "awatch",
+                    _ => "watch",
+                };
+                format!("T05{}:{:04x};", name, hit.map_or(0, |h| h.addr))
+            }
+            Stop::Locked => SIGILL.to_string(),
+            _ => SIGTRAP.to_string(),
+        }
+    }
+
+    // Z0/Z1 breakpoints, Z2/Z3/Z4 write/read/access watchpoints
+    fn insert_point(&mut self, kind: u8, addr: u32, len: u32) -> bool {
+        let bank = (addr > 0xFFFF).then_some((addr >> 16) as usize);
+        let start = addr as u16;
+        let point = match kind {
+            b'0' | b'1' => Point::Break(self.dbg.add_breakpoint(Location { bank, addr: start }, None)),
+            b'2' | b'3' | b'4' => {
+                let end = start.saturating_add((len.max(1) - 1).min(0xFFFF) as u16);
+                let (read, write) = (kind != b'2', kind != b'3');
+                Point::Watch(self.dbg.watch.add(Watchpoint { start, end, bank, read, write, execute: false, stop: true }))
+            }
+            _ => return false,
+        };
+        if let Some(old) = self.points.insert((kind, addr), point) {
+            self.remove(old);
+        }
+        true
+    }
+
+    fn remove(&mut self, point: Point) {
+        match point {
+            Point::Break(id) => self.dbg.remove_breakpoint(id),
+            Point::Watch(id) => self.dbg.watch.remove(id),
+        };
+    }
+
+    // the bus address of `addr` + `offset` (bank << 16 | addr), None for a ROM bank not mapped
+    fn mapped(&self, addr: u32, offset: u32) -> Option<u16> {
+        let at = (addr as u16).wrapping_add(offset as u16);
+        (addr <= 0xFFFF || at >= 0x8000 || self.mmu.rom_bank(at) == (addr >> 16) as usize).then_some(at)
+    }
+
+    // answers one packet (without framing); `interrupted` is polled while the CPU runs
+    pub fn handle(&mut self, packet: &[u8], interrupted: &mut dyn FnMut() -> bool) -> String {
+        let Some((&cmd, args)) = packet.split_first() else { return String::new() };
+        // arguments are hex, separators and query names, anything else is a bad packet
+        let Ok(args) = std::str::from_utf8(args) else { return "E01".to_string() };
+        match cmd {
+            b'?' => SIGTRAP.to_string(),
+            b'g' => self.registers().iter().map(|r| hex_bytes(&r.to_le_bytes())).collect(),
+            b'G' => match parse_hex_bytes(args) {
+                Some(bytes) if bytes.len() >= 12 => {
+                    for (i, pair) in bytes.chunks(2).take(6).enumerate() {
+                        self.set_register(i, u16::from_le_bytes([pair[0], pair[1]]));
+                    }
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            b'p' => match usize::from_str_radix(args, 16).ok().and_then(|i| self.registers().get(i).copied()) {
+                Some(value) => hex_bytes(&value.to_le_bytes()),
+                None => "E01".to_string(),
+            },
+            b'P' => {
+                let parsed = args.split_once('=').and_then(|(index, value)| {
+                    let bytes = parse_hex_bytes(value)?;
+                    Some((usize::from_str_radix(index, 16).ok()?, u16::from_le_bytes([*bytes.first()?, *bytes.get(1).unwrap_or(&0)])))
+                });
+                match parsed {
+                    Some((index, value)) if self.set_register(index, value) => "OK".to_string(),
+                    _ => "E01".to_string(),
+                }
+            }
+            b'm' => {
+                let parsed = args.split_once(',').and_then(|(addr, len)| {
+                    Some((u32::from_str_radix(addr, 16).ok()?, u32::from_str_radix(len, 16).ok()?))
+                });
+                match parsed {
+                    Some((addr, len)) => {
+                        let bytes: Option<Vec<u8>> = (0..len.min(0x10000)).map(|i| Some(self.mmu.read(self.mapped(addr, i)?))).collect();
+                        bytes.map_or("E02".to_string(), |bytes| hex_bytes(&bytes))
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            b'M' => {
+                let parsed = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, _) = range.split_once(',')?;
+                    Some((u32::from_str_radix(addr, 16).ok()?, parse_hex_bytes(data)?))
+                });
+                match parsed {
+                    Some((addr, bytes)) => {
+                        let Some(at) = (0..bytes.len() as u32).map(|i| self.mapped(addr, i)).collect::<Option<Vec<u16>>>() else {
+                            return "E02".to_string();
+                        };
+                        for (at, byte) in at.into_iter().zip(bytes) {
+                            self.mmu.write(at, byte);
+                        }
+                        "OK".to_string()
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            b'Z' | b'z' => {
+                let mut parts = args.split(',');
+                let kind = parts.next().and_then(|k| k.bytes().next());
+                let addr = parts.next().and_then(|a| u32::from_str_radix(a, 16).ok());
+                let len = parts.next().and_then(|l| u32::from_str_radix(l, 16).ok()).unwrap_or(1);
+                match (cmd, kind, addr) {
+                    (b'Z', Some(kind), Some(addr)) if self.insert_point(kind, addr, len) => "OK".to_string(),
+                    (b'z', Some(kind), Some(addr)) => {
+                        if let Some(point) = self.points.remove(&(kind, addr)) {
+                            self.remove(point);
+                        }
+                        "OK".to_string()
+                    }
+                    (b'Z', Some(b'0'..=b'4'), Some(_)) => "E01".to_string(),
+                    _ => String::new(),
+                }
+            }
+            b's' => {
+                let stop = self.dbg.step_into(self.mmu);
+                self.stop_reply(stop)
+            }
+            // runs a frame at a time so an interrupt from the client is seen quickly
+            b'c' => {
+                let limit = std::mem::replace(&mut self.dbg.run_limit, FRAME_CYCLES);
+                let reply = loop {
+                    match self.dbg.resume(self.mmu) {
+                        Stop::Limit if interrupted() => break SIGINT.to_string(),
+                        Stop::Limit => continue,
+                        stop => break self.stop_reply(stop),
+                    }
+                };
+                self.dbg.run_limit = limit;
+                reply
+            }
+            b'D' => {
+                self.detached = true;
+                "OK".to_string()
+            }
+            b'k' => {
+                self.detached = true;
+                String::new()
+            }
+            b'H' => "OK".to_string(),
+            // the command letter is ASCII, so the whole packet is valid UTF-8 here
+            b'q' | b'Q' | b'v' => self.query(&format!("{}{}", cmd as char, args)),
+            _ => String::new(),
+        }
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;hwbreak+".to_string();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = range.split_once(',').and_then(|(o, l)| {
+                Some((usize::from_str_radix(o, 16).ok()?, usize::from_str_radix(l, 16).ok()?))
+            }) else {
+                return "E01".to_string();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = (start + len).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+            return format!("{}{}", more, &TARGET_XML[start..end]);
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // serves one client until it detaches, kills the session or disconnects
+    pub fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        let mut pending = Vec::new();
+        let mut buf = [0u8; 4096];
+        while !self.detached {
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            pending.extend_from_slice(&buf[..n]);
+
+            while let Some((data, ok)) = next_packet(&mut pending) {
+                if !self.no_ack {
+                    stream.write_all(if ok { b"+" } else { b"-" })?;
+                    if !ok {
+                        continue;
+                    }
+                }
+
+                // a 0x03 byte from the client, after any acks, stops a running "continue"; it is
+                // only peeked at, so the start of the client's next packet stays in the socket
+                let mut poll = stream.try_clone()?;
+                let mut interrupted = || {
+                    let mut bytes = [0u8; 64];
+                    poll.set_nonblocking(true).ok();
+                    let n = poll.peek(&mut bytes).unwrap_or(0);
+                    let at = bytes[..n].iter().position(|b| *b == 0x03 || !ack_or_interrupt(*b));
+                    let hit = at.is_some_and(|at| bytes[at] == 0x03 && poll.read_exact(&mut bytes[..at + 1]).is_ok());
+                    poll.set_nonblocking(false).ok();
+                    hit
+                };
+                let reply = self.handle(&data, &mut interrupted);
+                if data != b"k" {
+                    stream.write_all(frame(&reply).as_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// waits for one client on 127.0.0.1:port and serves it
+pub fn listen<T: Mmu>(dbg: &mut Debugger, mmu: &mut T, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    GdbStub::new(dbg, mmu).serve(stream)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+    use crate::processor::Cpu;
+
+    fn setup() -> (Debugger, FlatMmu) {
+        let mut mmu = FlatMmu::new();
+        mmu.load(0x0100, &crate::asm!("ld a, $42\nld [$c000], a\ninc b\njr @-1"));
+        (Debugger::new(Cpu::default()), mmu)
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let (mut dbg, mut mmu) = setup();
+        let mut stub = GdbStub::new(&mut dbg, &mut mmu);
+        let mut never = || false;
+        assert_eq!(stub.handle(b"g", &mut never), "b0011300d8004d01feff0001");
+        assert_eq!(stub.handle(b"P5=5001", &mut never), "OK");
+        assert_eq!(stub.handle(b"p5", &mut never), "5001");
+        assert_eq!(stub.handle(b"M150,2:3e07", &mut never), "OK");
+        assert_eq!(stub.handle(b"m150,3", &mut never), "3e0700");
+        assert!(stub.handle(b"qXfer:features:read:target.xml:0,20", &mut never).starts_with("m<?xml"));
+        assert_eq!(frame("OK"), "$OK#9a");
+        // bank 1 is mapped at 4000 on a flat bus, bank 2 is not
+        assert_eq!(stub.handle(b"m14000,1", &mut never), "00");
+        assert_eq!(stub.handle(b"m24000,1", &mut never), "E02");
+        assert_eq!(stub.handle(b"M24000,1:01", &mut never), "E02");
+    }
+
+    #[test]
+    fn bad_packets() {
+        let (mut dbg, mut mmu) = setup();
+        let mut stub = GdbStub::new(&mut dbg, &mut mmu);
+        let mut never = || false;
+        assert_eq!(stub.handle(b"Z2,c000,10000", &mut never), "OK");
+        assert_eq!(stub.handle("\u{FFFD}g".as_bytes(), &mut never), "E01");
+        assert_eq!(stub.handle(b"g\xFF", &mut never), "E01");
+        assert_eq!(stub.handle("M150,2:3\u{FFFD}".as_bytes(), &mut never), "E01");
+        assert_eq!(stub.handle(b"", &mut never), "");
+    }
+
+    // a read that ends inside a packet keeps its '+' and '-', only acks ahead of it go
+    #[test]
+    fn split_packets() {
+        let packet = frame("qSupported:swbreak+;hwbreak+;X-1");
+        let (head, tail) = packet.split_at(20);
+        let mut pending = [b"+-\x03", head.as_bytes()].concat();
+        assert_eq!(next_packet(&mut pending), None);
+        assert_eq!(pending, head.as_bytes());
+        pending.extend_from_slice(tail.as_bytes());
+        pending.extend_from_slice(b"+$g#6");
+        assert_eq!(next_packet(&mut pending), Some((b"qSupported:swbreak+;hwbreak+;X-1".to_vec(), true)));
+        assert_eq!(next_packet(&mut pending), None);
+        pending.extend_from_slice(b"8");
+        assert_eq!(next_packet(&mut pending), Some((b"g".to_vec(), false)));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn run_control() {
+        let (mut dbg, mut mmu) = setup();
+        let mut stub = GdbStub::new(&mut dbg, &mut mmu);
+        let mut never = || false;
+        assert_eq!(stub.handle(b"s", &mut never), "S05");
+        assert_eq!(stub.handle(b"Z2,c000,1", &mut never), "OK");
+        assert_eq!(stub.handle(b"c", &mut never), "T05watch:c000;");
+        assert_eq!(stub.handle(b"z2,c000,1", &mut never), "OK");
+        assert_eq!(stub.handle(b"Z1,105,1", &mut never), "OK");
+        assert_eq!(stub.handle(b"c", &mut never), "S05");
+        assert_eq!(stub.dbg.cpu.regs.pc, 0x0105);
+        assert_eq!(stub.handle(b"z1,105,1", &mut never), "OK");
+        // endless loop: only the client can stop it
+        let mut polls = 0;
+        let mut after_two = || { polls += 1; polls == 2 };
+        assert_eq!(stub.handle(b"c", &mut after_two), "S02");
+        assert_eq!(stub.mmu.read(0xC000), 0x42);
+    }
+}
@@ -0,0 +1,193 @@
+//===== JOYPAD =====
+// P1 (0xFF00) in front of the bus. The game writes the two select bits, which are kept on the
+// bus so save states carry them; the four input lines read back low for every pressed button
+// of the selected groups. A line going low requests the joypad interrupt. On an SGB the writes
+// also go to its packet receiver, and with more than one controller P1 reads back the selected
+// controller's ID; the buttons held are player 1's.
+use crate::mmu::{BusAccess, Mmu};
+use crate::processor::IF;
+use crate::sgb::Sgb;
+
+pub const P1: u16 = 0xFF00;
+
+// one bit per button: the d-pad in the low nibble, the buttons in the high one, in P1 line order
+pub const RIGHT: u8 = 0x01;
+pub const LEFT: u8 = 0x02;
+pub const UP: u8 = 0x04;
+pub const DOWN: u8 = 0x08;
+pub const A: u8 = 0x10;
+pub const B: u8 = 0x20;
+pub const SELECT: u8 = 0x40;
+pub const START: u8 = 0x80;
+
+pub const BUTTONS: [(&str, u8); 8] = [
+    ("Right", RIGHT), ("Left", LEFT), ("Up", UP), ("Down", DOWN),
+    ("A", A), ("B", B), ("Select", SELECT), ("Start", START),
+];
+
+// Scripted input for unattended runs, one change per line: the frame it starts on and the
+// buttons held from then on, e.g. `120 Start`, `126`, `300 A Right`. `#` starts a comment.
+pub fn parse_script(text: &str) -> Result<Vec<(u64, u8)>, String> {
+    let mut changes: Vec<(u64, u8)> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let Some(frame) = words.next() else { continue };
+        let frame: u64 = frame.parse().map_err(|_| format!("line {}: bad frame number '{}'", number + 1, frame))?;
+        if changes.last().is_some_and(|&(last, _)| frame < last) {
+            return Err(format!("line {}: frame {} comes before the line above", number + 1, frame));
+        }
+        let mut buttons = 0;
+        for word in words {
+            let (_, bit) = BUTTONS.iter().find(|(name, _)| name.eq_ignore_ascii_case(word))
+                .ok_or(format!("line {}: unknown button '{}'", number + 1, word))?;
+            buttons |= bit;
+        }
+        changes.push((frame, buttons));
+    }
+    Ok(changes)
+}
+
+pub struct Joypad<T: Mmu> {
+    pub bus: T,
+    pub sgb: Option<Sgb>,
+    buttons: u8,
+}
+
+impl<T: Mmu> Joypad<T> {
+    pub fn new(bus: T) -> Joypad<T> {
+        Joypad { bus, sgb: None, buttons: 0 }
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.update(|joypad| joypad.buttons = buttons);
+    }
+
+    // lines that went low because of `change` raise the interrupt
+    fn update(&mut self, change: impl FnOnce(&mut Self)) {
+        let before = self.p1();
+        change(self);
+        if before & !self.p1() & 0x0F != 0 {
+            let flags = self.bus.read(IF);
+            self.bus.write(IF, flags | 0x10);
+        }
+    }
+
+    fn p1(&self) -> u8 {
+        let select = self.bus.read(P1) & 0x30;
+        if let Some(id) = self.sgb.as_ref().and_then(Sgb::read_p1_id) {
+            return 0xC0 | select | id;
+        }
+        let buttons = match &self.sgb {
+            Some(sgb) if sgb.current_player() != 0 => 0,
+            _ => self.buttons,
+        };
+        let mut lines = 0x0F;
+        if select & 0x10 == 0 {
+            lines &= !buttons & 0x0F;
+        }
+        if select & 0x20 == 0 {
+            lines &= !(buttons >> 4);
+        }
+        0xC0 | select | lines
+    }
+}
+
+impl<T: Mmu> Mmu for Joypad<T> {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            P1 => self.p1(),
+            _ => self.bus.read(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            P1 => self.update(|joypad| {
+                if let Some(sgb) = &mut joypad.sgb {
+                    sgb.write_p1(value);
+                }
+                joypad.bus.write(P1, value & 0x30);
+            }),
+            _ => self.bus.write(addr, value),
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        self.bus.rom_bank(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        self.bus.access(addr, kind, cycle);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    #[test]
+    fn select_groups_and_interrupt() {
+        let mut joypad = Joypad::new(FlatMmu::new());
+        joypad.write(P1, 0x30);
+        joypad.set_buttons(START | LEFT);
+        assert_eq!(joypad.read(P1), 0xFF);
+        assert_eq!(joypad.read(IF), 0x00);
+
+        joypad.write(P1, 0x10); // buttons
+        assert_eq!(joypad.read(P1), 0xD7);
+        assert_eq!(joypad.read(IF), 0x10);
+        joypad.write(P1, 0x20); // d-pad
+        assert_eq!(joypad.read(P1), 0xED);
+
+        joypad.write(IF, 0x00);
+        joypad.set_buttons(START | LEFT | A); // A is not selected
+        assert_eq!(joypad.read(IF), 0x00);
+        joypad.set_buttons(START | LEFT | A | DOWN);
+        assert_eq!((joypad.read(P1), joypad.read(IF)), (0xE5, 0x10));
+    }
+
+    // MLT_REQ for two players: the ID reads back with both lines high, the next poll moves to
+    // player 2, whose buttons are not held
+    #[test]
+    fn sgb_multiplayer() {
+        let mut joypad = Joypad::new(FlatMmu::new());
+        joypad.sgb = Some(Sgb::new());
+        joypad.set_buttons(A);
+        let mut packet = [0u8; 16];
+        packet[..2].copy_from_slice(&[0x11 << 3 | 1, 0x01]);
+        joypad.write(P1, 0x00);
+        joypad.write(P1, 0x30);
+        for bit in 0..128 {
+            let one = packet[bit / 8] >> (bit % 8) & 1 != 0;
+            joypad.write(P1, if one { 0x10 } else { 0x20 });
+            joypad.write(P1, 0x30);
+        }
+        joypad.write(P1, 0x20); // stop bit
+        joypad.write(P1, 0x30);
+        assert_eq!(joypad.read(P1) & 0x0F, 0x0F);
+        joypad.write(P1, 0x10);
+        assert_eq!(joypad.read(P1) & 0x0F, 0x0E); // player 1 holds A
+        joypad.write(P1, 0x30);
+        assert_eq!(joypad.read(P1) & 0x0F, 0x0E); // ID of player 2
+        joypad.write(P1, 0x10);
+        assert_eq!(joypad.read(P1) & 0x0F, 0x0F);
+    }
+
+    #[test]
+    fn script() {
+        let script = "# title screen\n60 start\n62\n\n300 A right  # run\n";
+        assert_eq!(parse_script(script), Ok(vec![(60, START), (62, 0), (300, A | RIGHT)]));
+        assert!(parse_script("10 jump").is_err());
+        assert!(parse_script("10 A\n5 B").is_err());
+    }
+}
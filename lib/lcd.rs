@@ -0,0 +1,161 @@
+//===== LCD =====
+// Scanline renderer: one line of background, window and sprites from VRAM, OAM and the LCD
+// registers as they are when the line is drawn. There is no pixel FIFO, so register writes in
+// the middle of a line show from the next one. Output is DMG colour numbers after BGP/OBP0/OBP1,
+// the same screen the SGB code composes; CGB attributes and palettes are not drawn yet.
+use crate::mmu::Mmu;
+use crate::sgb::SCREEN_W;
+
+pub const LCDC: u16 = 0xFF40;
+pub const STAT: u16 = 0xFF41;
+pub const SCY: u16 = 0xFF42;
+pub const SCX: u16 = 0xFF43;
+pub const LY: u16 = 0xFF44;
+pub const LYC: u16 = 0xFF45;
+pub const BGP: u16 = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16 = 0xFF4A;
+pub const WX: u16 = 0xFF4B;
+pub const OAM: u16 = 0xFE00;
+
+pub const LINE_CYCLES: u64 = 456;
+pub const LINES: u64 = 154;
+pub const VBLANK_LINE: u8 = 144;
+// OAM scan, then mode 3 until the end of the (shortest) drawing period
+pub const MODE3_START: u64 = 80;
+pub const HBLANK_START: u64 = 252;
+pub const SPRITES_PER_LINE: usize = 10;
+
+// colour number of pixel `x` of row `row` of the tile at `addr`
+pub fn tile_pixel<T: Mmu>(mmu: &T, addr: u16, row: u8, x: u8) -> u8 {
+    let lo = mmu.read(addr + row as u16 * 2);
+    let hi = mmu.read(addr + row as u16 * 2 + 1);
+    let bit = 7 - x;
+    ((hi >> bit) & 1) << 1 | ((lo >> bit) & 1)
+}
+
+// BG and window tiles: 0x8000 unsigned or 0x9000 signed, by LCDC bit 4
+pub fn bg_tile_addr(lcdc: u8, index: u8) -> u16 {
+    match lcdc & 0x10 {
+        0 => (0x9000i32 + index as i8 as i32 * 16) as u16,
+        _ => 0x8000 + index as u16 * 16,
+    }
+}
+
+pub fn shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0x03
+}
+
+// draws line `ly` into `out` (SCREEN_W colour numbers). `window_line` is the window's own line
+// counter, it only moves on lines where the window is shown.
+pub fn render_line<T: Mmu>(mmu: &T, ly: u8, window_line: &mut u8, out: &mut [u8]) {
+    let lcdc = mmu.read(LCDC);
+    // BG/window colour numbers before BGP, sprites behind the BG only show over colour 0
+    let mut raw = [0u8; SCREEN_W];
+    if lcdc & 0x01 != 0 {
+        let (scx, y) = (mmu.read(SCX), ly.wrapping_add(mmu.read(SCY)));
+        let map = if lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+        for (x, pixel) in raw.iter_mut().enumerate() {
+            let px = (x as u8).wrapping_add(scx);
+            let tile = mmu.read(map + (y / 8) as u16 * 32 + (px / 8) as u16);
+            *pixel = tile_pixel(mmu, bg_tile_addr(lcdc, tile), y % 8, px % 8);
+        }
+
+        let (wy, wx) = (mmu.read(WY), mmu.read(WX) as usize);
+        if lcdc & 0x20 != 0 && ly >= wy && wx <= 166 {
+            let map = if lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
+            let y = *window_line;
+            for (x, pixel) in raw.iter_mut().enumerate().skip(wx.saturating_sub(7)) {
+                let px = (x + 7 - wx) as u8;
+                let tile = mmu.read(map + (y / 8) as u16 * 32 + (px / 8) as u16);
+                *pixel = tile_pixel(mmu, bg_tile_addr(lcdc, tile), y % 8, px % 8);
+            }
+            *window_line += 1;
+        }
+    }
+    let bgp = mmu.read(BGP);
+    for (pixel, &color) in out.iter_mut().zip(&raw) {
+        *pixel = shade(bgp, color);
+    }
+
+    if lcdc & 0x02 == 0 {
+        return;
+    }
+    let height = if lcdc & 0x04 != 0 { 16 } else { 8 };
+    // the first ten in OAM order on this line, the lowest X (then OAM index) wins a pixel
+    let mut sprites: Vec<(u8, u16)> = (0..40u16)
+        .filter(|i| (0..height).contains(&(ly as i16 + 16 - mmu.read(OAM + i * 4) as i16)))
+        .take(SPRITES_PER_LINE)
+        .map(|i| (mmu.read(OAM + i * 4 + 1), i))
+        .collect();
+    sprites.sort();
+    let mut taken = [false; SCREEN_W];
+    for (sx, i) in sprites {
+        let attr = mmu.read(OAM + i * 4 + 3);
+        let mut row = (ly as i16 + 16 - mmu.read(OAM + i * 4) as i16) as u8;
+        if attr & 0x40 != 0 {
+            row = height as u8 - 1 - row;
+        }
+        let tile = match height {
+            16 => mmu.read(OAM + i * 4 + 2) & 0xFE,
+            _ => mmu.read(OAM + i * 4 + 2),
+        };
+        let palette = mmu.read(if attr & 0x10 != 0 { OBP1 } else { OBP0 });
+        for px in 0..8u8 {
+            let x = sx as i16 - 8 + px as i16;
+            if !(0..SCREEN_W as i16).contains(&x) || taken[x as usize] {
+                continue;
+            }
+            let color = tile_pixel(mmu, 0x8000 + tile as u16 * 16, row, if attr & 0x20 != 0 { 7 - px } else { px });
+            if color == 0 {
+                continue;
+            }
+            // a sprite behind the BG still hides the sprites under it
+            taken[x as usize] = true;
+            if attr & 0x80 == 0 || raw[x as usize] == 0 {
+                out[x as usize] = shade(palette, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    #[test]
+    fn background_window_and_sprites() {
+        let mut mmu = FlatMmu::new();
+        mmu.write(LCDC, 0x80 | 0x40 | 0x20 | 0x10 | 0x02 | 0x01);
+        mmu.write(BGP, 0xE4);
+        mmu.write(OBP0, 0xE4);
+        // tile 1: colour 3 everywhere, tile 2: colour 1 in the left half
+        for row in 0..8 {
+            mmu.write(0x8010 + row * 2, 0xFF);
+            mmu.write(0x8011 + row * 2, 0xFF);
+            mmu.write(0x8020 + row * 2, 0xF0);
+        }
+        mmu.write(0x9800, 0x01); // BG tile (0, 0)
+        mmu.write(SCX, 4);
+        mmu.write(WY, 4);
+        mmu.write(WX, 7 + 80); // window at x = 80 with tile 0 (colour 0)
+        mmu.write(0x9C00, 0x00);
+        // sprite with tile 2 at (2, 0), behind the BG
+        mmu.load(OAM, &[16, 10, 0x02, 0x80]);
+
+        let mut line = [0u8; SCREEN_W];
+        let mut window_line = 0;
+        render_line(&mmu, 0, &mut window_line, &mut line);
+        // the sprite is hidden by BG colour 3 and shows over colour 0
+        assert_eq!(&line[0..8], &[3, 3, 3, 3, 1, 1, 0, 0]);
+        assert_eq!(window_line, 0);
+
+        // the window does not wrap SCX and counts its own lines
+        mmu.write(0x9800, 0x00);
+        mmu.write(0x9C00, 0x01);
+        render_line(&mmu, 5, &mut window_line, &mut line);
+        assert_eq!((line[79], line[80], window_line), (0, 3, 1));
+    }
+}
@@ -0,0 +1,493 @@
+//===== MACHINE =====
+// A whole DMG as the frontends drive it: CPU, joypad, APU, cheats, the model's quirks, the boot
+// ROM overlay and the flat bus, plus the LCD timing that moves LY/STAT, raises the VBlank and
+// STAT interrupts and draws the screen line by line. GameShark cheats are written at the start
+// of VBlank. On the SGB models the joypad carries the SGB's packet receiver, VRAM transfers are
+// taken from the screen at VBlank and `frame` is the 256x224 picture with the border around
+// the coloured game screen.
+// Lines are drawn when they enter mode 3. Timer, DMA and mappers are not emulated here, DIV
+// just follows the cycle counter.
+use crate::apu::Apu;
+use crate::cheats::Cheats;
+use crate::joypad::Joypad;
+use crate::lcd::{self, HBLANK_START, LCDC, LINE_CYCLES, LINES, LY, LYC, MODE3_START, STAT, VBLANK_LINE};
+use crate::mmu::{check_boot_rom, BootRom, BootRomError, BusAccess, FlatMmu, Mmu};
+use crate::model::{Model, ModelBus};
+use crate::processor::{Cpu, FRAME_CYCLES, IF};
+use crate::registers::Registers;
+use crate::savestate::{SaveState, SaveStateError};
+use crate::screenshot::{Image, Screen};
+use crate::sgb::{Sgb, FRAME_H, FRAME_W, SCREEN_H, SCREEN_W};
+use crate::vram::Vram;
+
+const DIV: u16 = 0xFF04;
+
+pub struct GameBoy {
+    pub cpu: Cpu,
+    pub bus: Joypad<Apu<Cheats<ModelBus<BootRom<FlatMmu>>>>>,
+    pub model: Model,
+    pub screen: Vec<u8>, // SCREEN_W * SCREEN_H colour numbers
+    sgb_frame: Vec<u32>, // FRAME_W * FRAME_H, the SGB's output as of the last VBlank
+    rom: Vec<u8>,
+    boot_rom: Option<Vec<u8>>,
+    window_line: u8,
+}
+
+impl GameBoy {
+    // starts at 0x0100 in the state the boot ROM of `model` leaves behind
+    pub fn new(rom: Vec<u8>, model: Model) -> GameBoy {
+        let (cpu, bus) = start(&rom, model, None);
+        let sgb_frame = vec![0; if model.is_sgb() { FRAME_W * FRAME_H } else { 0 }];
+        GameBoy { cpu, bus: wire(model, bus), model, screen: vec![0; SCREEN_W * SCREEN_H], sgb_frame, rom, boot_rom: None, window_line: 0 }
+    }
+
+    // runs `boot_rom` from 0x0000 on a cleared machine until it unmaps itself, or starts like
+    // `new` without one
+    pub fn with_boot_rom(rom: Vec<u8>, model: Model, boot_rom: Option<Vec<u8>>) -> Result<GameBoy, BootRomError> {
+        if let Some(boot) = &boot_rom {
+            check_boot_rom(boot)?;
+        }
+        let mut gb = GameBoy::new(rom, model);
+        gb.boot_rom = boot_rom;
+        gb.reset();
+        Ok(gb)
+    }
+
+    // power cycle with the same ROM, the buttons held stay held, cheats and audio debugging go on
+    pub fn reset(&mut self) {
+        let (cpu, bus) = start(&self.rom, self.model, self.boot_rom.as_deref());
+        let buttons = self.bus.buttons();
+        let cheats = self.cheats().list().to_vec();
+        let old = std::mem::replace(&mut self.bus, wire(self.model, bus)).bus;
+        self.cpu = cpu;
+        self.bus.set_buttons(buttons);
+        self.cheats().replace(cheats);
+        let apu = self.apu();
+        (apu.sample_rate, apu.muted, apu.scope, apu.stems) = (old.sample_rate, old.muted, old.scope, old.stems);
+        self.screen.fill(0);
+        self.sgb_frame.fill(0);
+        self.window_line = 0;
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn apu(&mut self) -> &mut Apu<Cheats<ModelBus<BootRom<FlatMmu>>>> {
+        &mut self.bus.bus
+    }
+
+    pub fn cheats(&mut self) -> &mut Cheats<ModelBus<BootRom<FlatMmu>>> {
+        &mut self.bus.bus.bus
+    }
+
+    fn quirks(&mut self) -> &mut ModelBus<BootRom<FlatMmu>> {
+        &mut self.bus.bus.bus.bus
+    }
+
+    // the 64KB behind the joypad, APU, cheats, quirks and boot ROM, for debuggers that poke ROM
+    // as well
+    pub fn memory(&self) -> &FlatMmu {
+        &self.bus.bus.bus.bus.bus.bus
+    }
+
+    pub fn memory_mut(&mut self) -> &mut FlatMmu {
+        &mut self.bus.bus.bus.bus.bus.bus
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        let apu = &self.bus.bus;
+        SaveState {
+            model: Some(self.model), cpu: (&self.cpu).into(), memory: self.memory().mem.clone(),
+            boot_rom_mapped: apu.bus.bus.bus.is_mapped(), buttons: self.bus.buttons(), cheats: apu.bus.list().to_vec(),
+            apu: apu.state(), sgb: self.bus.sgb.clone(), cgb: apu.bus.bus.cgb.clone(), screen: self.screen.clone(), sgb_frame: self.sgb_frame.clone(),
+            window_line: self.window_line,
+        }
+    }
+
+    // a state of another model, or one taken while a boot ROM ran on a machine without one,
+    // is refused and leaves the machine as it was
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), SaveStateError> {
+        if let Some(model) = state.model && model != self.model {
+            return Err(SaveStateError::ModelMismatch { expected: model, actual: self.model });
+        }
+        if state.boot_rom_mapped && self.boot_rom.is_none() {
+            return Err(SaveStateError::NoBootRom);
+        }
+        // the buttons before the memory, so the joypad interrupt they might raise is undone
+        self.bus.set_buttons(state.buttons);
+        self.cpu = state.cpu.into();
+        self.memory_mut().mem.copy_from_slice(&state.memory);
+        self.quirks().bus.set_mapped(state.boot_rom_mapped);
+        self.quirks().stat_written = false;
+        let cgb = self.quirks().quirks.cgb_features;
+        self.quirks().cgb = cgb.then(|| state.cgb.clone().unwrap_or_default());
+        self.cheats().replace(state.cheats.clone());
+        self.apu().set_state(&state.apu);
+        if let Some(sgb) = &state.sgb {
+            self.bus.sgb = Some(sgb.clone());
+            self.sgb_frame.clone_from(&state.sgb_frame);
+        }
+        self.screen.clone_from(&state.screen);
+        self.window_line = state.window_line;
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> u32 {
+        let before = self.cpu.cycles;
+        let cycles = self.cpu.step(&mut self.bus);
+        self.catch_up(before);
+        cycles
+    }
+
+    pub fn run_frame(&mut self) -> u64 {
+        let frame = self.cpu.cycles / FRAME_CYCLES + 1;
+        self.run_until(frame * FRAME_CYCLES, |_| false);
+        frame
+    }
+
+    // steps until cycle `end`, or until `stop` holds after an instruction; true when it stopped
+    pub fn run_until(&mut self, end: u64, mut stop: impl FnMut(&GameBoy) -> bool) -> bool {
+        while self.cpu.cycles < end {
+            self.step();
+            if stop(self) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // the screen through the model's monochrome palette
+    pub fn image(&self) -> Image {
+        Image::from_shades(&self.screen, self.model.dmg_palette())
+    }
+
+    // what the player sees: the SGB's frame on the SGB models, the screen on the others
+    pub fn frame(&self) -> Image {
+        match self.bus.sgb {
+            Some(_) => Image::new(FRAME_W, FRAME_H, self.sgb_frame.clone()),
+            None => self.image(),
+        }
+    }
+
+    // VRAM, OAM and the palettes as they are now, for the graphics debugger
+    pub fn vram(&self) -> Vram {
+        Vram::from_bus(self.memory(), self.bus.bus.bus.bus.cgb.as_ref())
+    }
+
+    fn raise(&mut self, bit: u8) {
+        let flags = self.bus.read(IF);
+        self.bus.write(IF, flags | bit);
+    }
+
+    // runs the APU and the LCD for an instruction that started at cycle `before`
+    fn catch_up(&mut self, before: u64) {
+        let cycles = (self.cpu.cycles - before) as u32;
+        self.apu().tick(cycles);
+        self.update_lcd(before);
+    }
+
+    // finishes a pending CHR_TRN/PCT_TRN with the frame just drawn and composes the output
+    fn sgb_vblank(&mut self) {
+        let transfer = self.bus.sgb.as_ref().is_some_and(|sgb| sgb.pending.is_some()).then(|| transfer_data(self.memory()));
+        let Some(sgb) = &mut self.bus.sgb else { return };
+        if let Some(data) = transfer {
+            sgb.vram_transfer(&data);
+        }
+        sgb.render(&self.screen, &mut self.sgb_frame);
+    }
+
+    // catches the LCD up with the CPU after an instruction that started at cycle `before`
+    fn update_lcd(&mut self, before: u64) {
+        let now = self.cpu.cycles;
+        self.bus.write(DIV, (now >> 8) as u8);
+        let lcdc = self.bus.read(LCDC);
+        let stat = self.bus.read(STAT);
+        let stat_written = std::mem::take(&mut self.quirks().stat_written);
+        if lcdc & 0x80 == 0 {
+            self.bus.write(LY, 0);
+            self.bus.write(STAT, stat & 0xF8);
+            return;
+        }
+
+        // number of lines whose point `offset` into the line is at or before cycle `c`
+        let passed = |c: u64, offset: u64| if c < offset { 0 } else { (c - offset) / LINE_CYCLES + 1 };
+        for n in passed(before, 0)..passed(now, 0) {
+            match (n % LINES) as u8 {
+                0 => self.window_line = 0,
+                VBLANK_LINE => {
+                    self.cheats().apply();
+                    self.sgb_vblank();
+                    self.raise(0x01);
+                    if stat & 0x10 != 0 {
+                        self.raise(0x02);
+                    }
+                }
+                _ => {}
+            }
+        }
+        // from the flat memory, which holds VRAM bank 0 whatever VBK selects for the CPU
+        for n in passed(before, MODE3_START)..passed(now, MODE3_START) {
+            let ly = (n % LINES) as u8;
+            if ly < VBLANK_LINE {
+                let start = ly as usize * SCREEN_W;
+                lcd::render_line(&self.bus.bus.bus.bus.bus.bus, ly, &mut self.window_line, &mut self.screen[start..start + SCREEN_W]);
+            }
+        }
+
+        let ly = (now / LINE_CYCLES % LINES) as u8;
+        let dot = now % LINE_CYCLES;
+        let mode = match dot {
+            _ if ly >= VBLANK_LINE => 1,
+            0..MODE3_START => 2,
+            MODE3_START..HBLANK_START => 3,
+            _ => 0,
+        };
+        let coincidence = ly == self.bus.read(LYC);
+        if coincidence && stat & 0x04 == 0 && stat & 0x40 != 0 {
+            self.raise(0x02);
+        }
+        // on the DMG a STAT write enables every source for a moment
+        if stat_written && (mode <= 1 || coincidence) {
+            self.raise(0x02);
+        }
+        self.bus.write(LY, ly);
+        self.bus.write(STAT, (stat & 0xF8) | (coincidence as u8) << 2 | mode);
+    }
+}
+
+// the state the boot ROM of `model` leaves behind
+pub fn power_on(rom: &[u8], model: Model) -> (Cpu, FlatMmu) {
+    let mut bus = FlatMmu::with_rom(rom);
+    model.apply_post_boot(&mut bus, rom.get(0x0104..0x0134).unwrap_or(&[]));
+    (Cpu::new(Registers::for_model(model)), bus)
+}
+
+// the layers in front of the memory
+fn wire(model: Model, bus: BootRom<FlatMmu>) -> Joypad<Apu<Cheats<ModelBus<BootRom<FlatMmu>>>>> {
+    let mut joypad = Joypad::new(Apu::new(Cheats::new(ModelBus::new(model, bus))));
+    if model.is_sgb() {
+        joypad.sgb = Some(Sgb::new());
+    }
+    joypad
+}
+
+// the 4KB an SGB VRAM transfer reads off the screen: the tile data of the first 256 background
+// tiles shown, left to right and top to bottom (games set the map up as 0, 1, 2...)
+fn transfer_data<T: Mmu>(mmu: &T) -> Vec<u8> {
+    let lcdc = mmu.read(LCDC);
+    let map = if lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+    (0..256u16).flat_map(|n| {
+        let tile = lcd::bg_tile_addr(lcdc, mmu.read(map + n / 20 * 32 + n % 20));
+        (0..16).map(move |i| mmu.read(tile + i))
+    }).collect()
+}
+
+// the machine at power on with `boot_rom` mapped, or after it without one
+fn start(rom: &[u8], model: Model, boot_rom: Option<&[u8]>) -> (Cpu, BootRom<FlatMmu>) {
+    match boot_rom {
+        Some(boot) => {
+            let bus = BootRom::new(boot.to_vec(), FlatMmu::with_rom(rom)).expect("boot ROM size checked by with_boot_rom");
+            (Cpu::new(Registers::power_on()), bus)
+        }
+        None => {
+            let (cpu, bus) = power_on(rom, model);
+            (cpu, BootRom::skipped(bus))
+        }
+    }
+}
+
+// the machine as the bus of a debugger that steps its own copy of the CPU: after each step it
+// takes that CPU over and catches up, so LY, the interrupts and the screen move as they would
+impl Mmu for GameBoy {
+    fn read(&self, addr: u16) -> u8 {
+        self.bus.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.bus.write(addr, value);
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        self.bus.rom_bank(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        self.bus.access(addr, kind, cycle);
+    }
+
+    fn stepped(&mut self, cpu: &Cpu, before: u64) {
+        self.cpu = *cpu;
+        self.catch_up(before);
+    }
+}
+
+impl Screen for GameBoy {
+    fn run_frame(&mut self) {
+        GameBoy::run_frame(self);
+    }
+
+    fn screen(&self) -> Image {
+        self.image()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // the post-boot logo is drawn, and VBlank comes once per frame
+    #[test]
+    fn draws_the_logo_and_raises_vblank() {
+        let mut rom = crate::asm!("SECTION \"main\", ROM0[$100]\ndi\n.loop: jr .loop");
+        rom.resize(0x8000, 0);
+        rom[0x0104..0x0134].fill(0xFF);
+        let mut gb = GameBoy::new(rom, Model::DMG);
+        gb.bus.write(IF, 0);
+        gb.run_frame();
+        assert_eq!(gb.bus.read(IF) & 0x01, 0x01);
+        gb.bus.write(IF, 0);
+        gb.run_frame();
+        assert_eq!(gb.bus.read(IF) & 0x01, 0x01);
+
+        // logo tiles 0x01..0x0C at map row 8, columns 4..15
+        assert_eq!(gb.screen[64 * SCREEN_W + 32], 3);
+        assert_eq!(gb.screen[64 * SCREEN_W + 31], 0);
+        assert_eq!(gb.screen[0], 0);
+        assert_eq!(gb.image().pixels[64 * SCREEN_W + 32], Model::DMG.dmg_palette()[3]);
+    }
+
+    #[test]
+    fn runs_until_a_stop() {
+        let mut rom = crate::asm!("SECTION \"main\", ROM0[$100]\nld a, 1\ninc a\n.loop: jr .loop");
+        rom.resize(0x8000, 0);
+        let mut gb = GameBoy::new(rom, Model::DMG);
+        assert!(gb.run_until(FRAME_CYCLES, |gb| gb.cpu.regs.pc == 0x0103));
+        assert_eq!((gb.cpu.regs.a, gb.cpu.regs.pc), (2, 0x0103));
+        assert!(!gb.run_until(FRAME_CYCLES, |gb| gb.cpu.regs.pc == 0x0150));
+        assert!(gb.cpu.cycles >= FRAME_CYCLES);
+    }
+
+    // the boot ROM runs from 0x0000 on a cleared machine and hands over at 0x0100 by unmapping
+    #[test]
+    fn runs_a_boot_rom() {
+        let mut rom = crate::asm!("SECTION \"main\", ROM0[$100]\nld b, a\n.loop: jr .loop");
+        rom.resize(0x8000, 0);
+        rom[0x0000] = 0xAA;
+        let mut boot = vec![0x00; 0x100]; // NOPs up to ld a, 1 / ldh [$50], a
+        boot[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        assert!(GameBoy::with_boot_rom(rom.clone(), Model::DMG, Some(vec![0; 0x200])).is_err());
+        let mut gb = GameBoy::with_boot_rom(rom, Model::DMG, Some(boot)).unwrap();
+        assert_eq!((gb.cpu.regs.pc, gb.read(0x0000), gb.read(LCDC)), (0x0000, 0x00, 0x00));
+        assert!(gb.run_until(FRAME_CYCLES, |gb| gb.cpu.regs.pc == 0x0100));
+        assert_eq!((gb.cpu.regs.a, gb.cpu.regs.sp, gb.read(0x0000)), (0x01, 0x0000, 0xAA));
+        gb.reset();
+        assert_eq!((gb.cpu.regs.pc, gb.read(0x0000)), (0x0000, 0x00));
+    }
+
+    fn run_on(model: Model, body: &str) -> GameBoy {
+        let mut rom = crate::asm!("SECTION \"main\", ROM0[$100]\n{}\n.loop: jr .loop", body);
+        rom.resize(0x8000, 0);
+        let mut gb = GameBoy::new(rom, model);
+        for i in 0..0xA0 {
+            gb.bus.write(0xFE00 + i, i as u8);
+        }
+        gb.bus.write(IF, 0);
+        // until jr .loop
+        assert!(gb.run_until(FRAME_CYCLES, |gb| gb.read(gb.cpu.regs.pc) == 0x18 && gb.read(gb.cpu.regs.pc + 1) == 0xFE));
+        gb
+    }
+
+    fn oam_intact(gb: &GameBoy) -> bool {
+        (0..0xA0).all(|i| gb.read(0xFE00 + i) == i as u8)
+    }
+
+    // waits for mode 2, then touches OAM with HL
+    const IN_MODE_2: &str = "ld hl, $FE40\n.wait: ldh a, [$41]\nand 3\ncp 2\njr nz, .wait";
+
+    #[test]
+    fn oam_bug_per_model() {
+        let read = format!("{}\nld a, [hl]", IN_MODE_2);
+        assert!(!oam_intact(&run_on(Model::DMG, &read)));
+        assert!(oam_intact(&run_on(Model::CGB, &read)));
+
+        // the incrementer alone: the CGB still corrupts, the AGB does not
+        let inc = format!("{}\ninc hl", IN_MODE_2);
+        assert!(!oam_intact(&run_on(Model::DMG, &inc)));
+        assert!(!oam_intact(&run_on(Model::CGB, &inc)));
+        assert!(oam_intact(&run_on(Model::AGB, &inc)));
+
+        // outside mode 2 nothing happens
+        assert!(oam_intact(&run_on(Model::DMG, "ld hl, $FE40\n.wait: ldh a, [$41]\nand 3\njr nz, .wait\ninc hl\nld a, [hl]")));
+    }
+
+    #[test]
+    fn stat_write_interrupt() {
+        // LY == LYC == 0 right after boot
+        let write = "xor a\nldh [$41], a";
+        assert_eq!(run_on(Model::DMG, write).read(IF) & 0x02, 0x02);
+        assert_eq!(run_on(Model::SGB, write).read(IF) & 0x02, 0x02);
+        assert_eq!(run_on(Model::CGB, write).read(IF) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn cgb_registers() {
+        let write = "ld a, 1\nldh [$4F], a\nldh [$70], a\nld a, $5A\nld [$8000], a\nld a, $82\nldh [$68], a\nld a, $1F\nldh [$69], a";
+        let dmg = run_on(Model::DMG, write);
+        assert_eq!((dmg.read(0xFF4F), dmg.read(0xFF70), dmg.read(0x8000)), (0xFF, 0xFF, 0x5A));
+        assert_eq!((dmg.vram().banks.len(), dmg.vram().bg_palettes), (1, None));
+        // VBK 1 puts the write in bank 1, bank 0 is left as it was for the LCD
+        let cgb = run_on(Model::CGB, write);
+        assert_eq!((cgb.read(0xFF4F), cgb.read(0xFF70), cgb.read(0x8000)), (0xFF, 0x01, 0x5A));
+        assert_eq!(cgb.memory().read(0x8000), 0x00);
+        let vram = cgb.vram();
+        assert_eq!((vram.banks[0][0], vram.banks[1][0], vram.palettes([0; 4])[3][1]), (0x00, 0x5A, 0xFF0000));
+        assert_eq!(cgb.read(0xFF68), 0xC3);
+
+        let state = cgb.save_state();
+        let mut restored = GameBoy::new(cgb.rom().to_vec(), Model::CGB);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.vram(), vram);
+    }
+
+    // a PAL01 packet sent bit by bit through P1 colours the SGB frame from the next VBlank
+    #[test]
+    fn sgb_packets_through_p1() {
+        let mut rom = crate::asm!("SECTION \"main\", ROM0[$100]\n\
+            ld hl, packet\nxor a\nldh [$00], a\nld a, $30\nldh [$00], a\nld c, 16\n\
+            .byte: ld a, [hl+]\nld d, a\nld b, 8\n\
+            .bit: ld a, $10\nsrl d\njr c, .send\nld a, $20\n\
+            .send: ldh [$00], a\nld a, $30\nldh [$00], a\ndec b\njr nz, .bit\ndec c\njr nz, .byte\n\
+            ld a, $20\nldh [$00], a\nld a, $30\nldh [$00], a\n\
+            .loop: jr .loop\n\
+            packet: db $01, $1F, $00, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0");
+        rom.resize(0x8000, 0);
+        let mut gb = GameBoy::new(rom.clone(), Model::SGB);
+        gb.run_frame();
+        gb.run_frame();
+        let frame = gb.frame();
+        assert_eq!((frame.width, frame.height, frame.pixels[0]), (FRAME_W, FRAME_H, 0xFF0000));
+
+        let frame = GameBoy::new(rom, Model::DMG).frame();
+        assert_eq!((frame.width, frame.height), (SCREEN_W, SCREEN_H));
+    }
+
+    // a debugger stepping its own CPU on the machine still sees LY move and VBlank come
+    #[test]
+    fn debugger_drives_the_machine() {
+        let mut rom = crate::asm!("SECTION \"main\", ROM0[$100]\ndi\n.loop: jr .loop");
+        rom.resize(0x8000, 0);
+        let mut gb = GameBoy::new(rom, Model::DMG);
+        gb.bus.write(IF, 0);
+        let mut dbg = crate::debugger::Debugger::new(gb.cpu);
+        dbg.run_limit = 145 * LINE_CYCLES;
+        dbg.command(&mut gb, "continue");
+        assert_eq!(gb.cpu, dbg.cpu);
+        assert_eq!(gb.read(LY), 145);
+        assert_eq!(gb.read(IF) & 0x01, 0x01);
+    }
+}
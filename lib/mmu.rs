@@ -0,0 +1,212 @@
+//===== MMU =====
+use std::fmt;
+use crate::processor::{Cpu, IE, IF};
+
+// what the CPU does with the address it puts on the bus in an M-cycle; Idu is the 16-bit
+// incrementer of INC/DEC rr and PUSH driving the bus without a read or write, ReadIdu a read
+// whose register is incremented in the same cycle (LD A,(HL+) and LD A,(HL-))
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BusAccess {
+    Read,
+    Write,
+    Idu,
+    ReadIdu,
+}
+
+// Memory bus as seen by the CPU: cartridge, RAM and I/O registers are all reached through it
+pub trait Mmu {
+    fn read(&self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, value: u8);
+
+    // interrupts both requested (IF) and enabled (IE); the CPU sees these on dedicated lines,
+    // so buses that record accesses can answer without counting them as reads
+    fn pending_interrupts(&self) -> u8 {
+        self.read(IE) & self.read(IF) & 0x1F
+    }
+
+    // ROM bank mapped at `addr`, for bank-qualified breakpoints; without a mapper the
+    // cartridge is a flat 32KB
+    fn rom_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x3FFF => 0,
+            _ => 1,
+        }
+    }
+
+    // called by the CPU before every access and IDU cycle at T-cycle `cycle`, for buses where
+    // touching an address has side effects beyond read and write (the OAM bug)
+    fn access(&mut self, _addr: u16, _kind: BusAccess, _cycle: u64) {}
+
+    // called by drivers that step their own Cpu (the debugger) after a step that started at
+    // cycle `before`, so a bus that is a whole machine can catch its LCD and APU up
+    fn stepped(&mut self, _cpu: &Cpu, _before: u64) {}
+}
+
+// 64KB of plain RAM, every address readable and writable below `read_only`: for tests and
+// bare ROMs
+pub struct FlatMmu {
+    pub mem: Vec<u8>,
+    pub read_only: usize, // writes below are dropped, 0x8000 for a cartridge without a mapper
+}
+
+impl Default for FlatMmu {
+    fn default() -> Self { FlatMmu::new() }
+}
+
+impl FlatMmu {
+    pub fn new() -> FlatMmu {
+        FlatMmu { mem: vec![0; 0x10000], read_only: 0 }
+    }
+
+    // the first 32KB of `rom` mapped read-only, as on a cartridge without a mapper
+    pub fn with_rom(rom: &[u8]) -> FlatMmu {
+        let mut mmu = FlatMmu::new();
+        mmu.load(0x0000, &rom[..rom.len().min(0x8000)]);
+        mmu.read_only = 0x8000;
+        mmu
+    }
+
+    pub fn load(&mut self, addr: u16, bytes: &[u8]) {
+        let start = addr as usize;
+        let end = (start + bytes.len()).min(self.mem.len());
+        self.mem[start..end].copy_from_slice(&bytes[..end - start]);
+    }
+}
+
+impl Mmu for FlatMmu {
+    fn read(&self, addr: u16) -> u8 {
+        self.mem[addr as usize]
+    }
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr as usize >= self.read_only {
+            self.mem[addr as usize] = value;
+        }
+    }
+}
+
+// writing any non-zero value here unmaps the boot ROM until the next reset
+pub const BOOT_OFF: u16 = 0xFF50;
+
+#[derive(Debug, PartialEq)]
+pub enum BootRomError {
+    BadSize(usize),
+}
+
+impl fmt::Display for BootRomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BootRomError::BadSize(size) => write!(f, "boot ROM must be 256 or 2304 bytes, got {}", size),
+        }
+    }
+}
+
+// Boot ROM overlay in front of the real bus. Reads of 0x0000..0x00FF (and 0x0200..0x08FF for
+// the CGB/AGB dumps) come from the boot ROM until the 0xFF50 write, everything else goes through.
+pub struct BootRom<T: Mmu> {
+    rom: Vec<u8>,
+    mapped: bool,
+    pub bus: T,
+}
+
+// 256 bytes for the DMG and SGB boot ROMs, 2304 for the CGB one
+pub fn check_boot_rom(rom: &[u8]) -> Result<(), BootRomError> {
+    match rom.len() {
+        0x100 | 0x900 => Ok(()),
+        size => Err(BootRomError::BadSize(size)),
+    }
+}
+
+impl<T: Mmu> BootRom<T> {
+    pub fn new(rom: Vec<u8>, bus: T) -> Result<BootRom<T>, BootRomError> {
+        check_boot_rom(&rom)?;
+        Ok(BootRom { rom, mapped: true, bus })
+    }
+
+    // a boot ROM that has already run: every read goes to `bus`
+    pub fn skipped(bus: T) -> BootRom<T> {
+        BootRom { rom: Vec::new(), mapped: false, bus }
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    // for save states; false when asked to map a boot ROM that is not there
+    pub fn set_mapped(&mut self, mapped: bool) -> bool {
+        self.mapped = mapped && !self.rom.is_empty();
+        self.mapped == mapped
+    }
+
+    pub fn into_inner(self) -> T {
+        self.bus
+    }
+
+    fn covers(&self, addr: u16) -> bool {
+        self.mapped && (addr < 0x100 || (self.rom.len() == 0x900 && (0x200..0x900).contains(&addr)))
+    }
+}
+
+impl<T: Mmu> Mmu for BootRom<T> {
+    fn read(&self, addr: u16) -> u8 {
+        match self.covers(addr) {
+            true => self.rom[addr as usize],
+            false => self.bus.read(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr == BOOT_OFF && value != 0 {
+            self.mapped = false;
+        }
+        self.bus.write(addr, value);
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        self.bus.rom_bank(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        self.bus.access(addr, kind, cycle);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn boot_rom_overlay() {
+        let bus = FlatMmu { mem: vec![0xAA; 0x10000], read_only: 0 };
+        let mut mmu = BootRom::new(vec![0x31; 0x100], bus).unwrap();
+        assert_eq!(mmu.read(0x0000), 0x31);
+        assert_eq!(mmu.read(0x00FF), 0x31);
+        assert_eq!(mmu.read(0x0100), 0xAA);
+
+        // a zero write keeps it mapped
+        mmu.write(BOOT_OFF, 0x00);
+        assert!(mmu.is_mapped());
+        mmu.write(BOOT_OFF, 0x01);
+        assert!(!mmu.is_mapped());
+        assert_eq!(mmu.read(0x0000), 0xAA);
+    }
+
+    #[test]
+    fn cgb_boot_rom_hole() {
+        let bus = FlatMmu { mem: vec![0xAA; 0x10000], read_only: 0 };
+        let mmu = BootRom::new(vec![0x31; 0x900], bus).unwrap();
+        assert_eq!(mmu.read(0x0150), 0xAA); // cartridge header stays visible
+        assert_eq!(mmu.read(0x0200), 0x31);
+        assert_eq!(mmu.read(0x08FF), 0x31);
+        assert_eq!(mmu.read(0x0900), 0xAA);
+    }
+
+    #[test]
+    fn boot_rom_size() {
+        let bus = FlatMmu { mem: vec![], read_only: 0 };
+        assert_eq!(BootRom::new(vec![0; 0x200], bus).err(), Some(BootRomError::BadSize(0x200)));
+    }
+}
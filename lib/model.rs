@@ -0,0 +1,424 @@
+//===== MODEL =====
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::lcd::{LCDC, LINE_CYCLES, LINES, MODE3_START, OAM, STAT, VBLANK_LINE};
+use crate::mmu::{BusAccess, Mmu};
+use crate::vram::CgbVram;
+
+// Hardware revision being emulated
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Model {
+    DMG0, // early japanese DMG with the older boot ROM
+    #[default]
+    DMG,
+    MGB,  // Game Boy Pocket / Light
+    SGB,
+    SGB2,
+    CGB,
+    AGB,  // Game Boy Advance running in CGB mode
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "DMG0" => Ok(Model::DMG0),
+            "DMG" => Ok(Model::DMG),
+            "MGB" => Ok(Model::MGB),
+            "SGB" => Ok(Model::SGB),
+            "SGB2" => Ok(Model::SGB2),
+            "CGB" => Ok(Model::CGB),
+            "AGB" => Ok(Model::AGB),
+            _ => Err(format!("unknown model '{}'", s)),
+        }
+    }
+}
+
+// Hardware differences between models, looked up once instead of matching on Model everywhere
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quirks {
+    pub cgb_features: bool,  // VRAM/WRAM banks, colour palettes, double speed, HDMA
+    pub oam_bug: bool,       // OAM corruption when the CPU touches 0xFE00..0xFEFF in mode 2
+    pub idu_oam_bug: bool,   // INC rr / DEC rr and PUSH with rr in 0xFE00..0xFEFF also corrupt OAM
+    pub stat_write_irq: bool, // writing STAT in mode 0/1 fires a spurious STAT interrupt
+}
+
+impl Model {
+    pub fn is_cgb(&self) -> bool {
+        matches!(self, Model::CGB | Model::AGB)
+    }
+
+    pub fn is_sgb(&self) -> bool {
+        matches!(self, Model::SGB | Model::SGB2)
+    }
+
+    // the CGB fixed the OAM bug for reads and writes and the STAT write bug, but its 16-bit
+    // incrementer still puts the register on the bus, so INC/DEC rr into OAM corrupts it. On the
+    // AGB that is gone too.
+    pub fn quirks(&self) -> Quirks {
+        let mono = !self.is_cgb();
+        Quirks {
+            cgb_features: self.is_cgb(),
+            oam_bug: mono,
+            idu_oam_bug: *self != Model::AGB,
+            stat_write_irq: mono,
+        }
+    }
+
+    // RGB shades for colour numbers 0..3 when the model displays monochrome graphics.
+    // CGB/AGB pick a palette from the title in the boot ROM, here they fall back to grey.
+    pub fn dmg_palette(&self) -> [u32; 4] {
+        match self {
+            Model::DMG0 | Model::DMG => [0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F],
+            // SGB palette 0 before the game sends PAL01
+            Model::SGB | Model::SGB2 => [0xF8E8C8, 0xD89048, 0xA82820, 0x301850],
+            Model::MGB | Model::CGB | Model::AGB => [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000],
+        }
+    }
+
+    // size of the boot ROM dump: the CGB/AGB one also covers 0x0200..0x08FF
+    pub fn boot_rom_size(&self) -> usize {
+        match self {
+            Model::CGB | Model::AGB => 0x900,
+            _ => 0x100,
+        }
+    }
+
+    // hardware registers as the boot ROM leaves them at PC = 0x0100 (Pan Docs, "Power Up Sequence")
+    // registers whose value is unknown or varies between units are left out
+    pub fn post_boot_io(&self) -> Vec<(u16, u8)> {
+        let cgb = self.is_cgb();
+        let sgb = self.is_sgb();
+        let mut io = vec![
+            (0xFF00, 0xCF), // P1
+            (0xFF01, 0x00), // SB
+            (0xFF02, if cgb { 0x7F } else { 0x7E }), // SC
+            (0xFF05, 0x00), // TIMA
+            (0xFF06, 0x00), // TMA
+            (0xFF07, 0xF8), // TAC
+            (0xFF0F, 0xE1), // IF
+            (0xFF10, 0x80), // NR10
+            (0xFF11, 0xBF), // NR11
+            (0xFF12, 0xF3), // NR12
+            (0xFF13, 0xFF), // NR13
+            (0xFF14, 0xBF), // NR14
+            (0xFF16, 0x3F), // NR21
+            (0xFF17, 0x00), // NR22
+            (0xFF18, 0xFF), // NR23
+            (0xFF19, 0xBF), // NR24
+            (0xFF1A, 0x7F), // NR30
+            (0xFF1B, 0xFF), // NR31
+            (0xFF1C, 0x9F), // NR32
+            (0xFF1D, 0xFF), // NR33
+            (0xFF1E, 0xBF), // NR34
+            (0xFF20, 0xFF), // NR41
+            (0xFF21, 0x00), // NR42
+            (0xFF22, 0x00), // NR43
+            (0xFF23, 0xBF), // NR44
+            (0xFF24, 0x77), // NR50
+            (0xFF25, 0xF3), // NR51
+            (0xFF26, if sgb { 0xF0 } else { 0xF1 }), // NR52
+            (0xFF40, 0x91), // LCDC
+            (0xFF42, 0x00), // SCY
+            (0xFF43, 0x00), // SCX
+            (0xFF45, 0x00), // LYC
+            (0xFF46, if cgb { 0x00 } else { 0xFF }), // DMA
+            (0xFF47, 0xFC), // BGP
+            (0xFF4A, 0x00), // WY
+            (0xFF4B, 0x00), // WX
+            (0xFFFF, 0x00), // IE
+        ];
+        match self {
+            Model::DMG0 => io.extend([(0xFF04, 0x18), (0xFF41, 0x81)]), // DIV, STAT
+            Model::DMG | Model::MGB => io.extend([(0xFF04, 0xAB), (0xFF41, 0x85)]),
+            _ => {}
+        }
+        // CGB-only registers read back as 0xFF on the older models
+        if cgb {
+            io.extend([(0xFF4D, 0x7E), (0xFF4F, 0xFE), (0xFF56, 0x3E), (0xFF70, 0xF8)]);
+        } else {
+            io.extend([(0xFF4D, 0xFF), (0xFF4F, 0xFF), (0xFF56, 0xFF), (0xFF70, 0xFF)]);
+        }
+        io
+    }
+
+    // writes the post-boot I/O registers and, on the monochrome models, the logo the boot ROM
+    // copied from the cartridge header into VRAM. `header_logo` is 0x0104..0x0133 of the ROM.
+    // The CGB/AGB boot ROMs leave title-dependent VRAM contents, which are not reproduced.
+    pub fn apply_post_boot<T: Mmu>(&self, mmu: &mut T, header_logo: &[u8]) {
+        for (addr, value) in self.post_boot_io() {
+            mmu.write(addr, value);
+        }
+        if self.is_cgb() {
+            return;
+        }
+
+        // every logo nibble is doubled in both directions: one byte becomes four tile rows
+        let mut addr = 0x8010;
+        for byte in header_logo.iter().take(48) {
+            for nibble in [byte >> 4, byte & 0x0F] {
+                let row = double_bits(nibble);
+                mmu.write(addr, row);
+                mmu.write(addr + 2, row);
+                addr += 4;
+            }
+        }
+        // the (R) symbol is stored in the boot ROM itself
+        for (i, row) in REGISTERED_TILE.iter().enumerate() {
+            mmu.write(0x8190 + 2 * i as u16, *row);
+        }
+
+        // tile map: logo tiles 0x01..0x0C and 0x0D..0x18 on two rows, (R) next to the first one
+        mmu.write(0x9910, 0x19);
+        for i in 0..12u16 {
+            mmu.write(0x9904 + i, 0x01 + i as u8);
+            mmu.write(0x9924 + i, 0x0D + i as u8);
+        }
+    }
+}
+
+// --- OAM corruption (Pan Docs, "OAM Corruption Bug") ---
+// OAM is seen as 20 rows of four 16-bit words; `row` is the row the PPU is reading in mode 2.
+// Row 0 is never corrupted.
+fn oam_word(oam: &[u8; 0xA0], row: usize, word: usize) -> u16 {
+    let i = row * 8 + word * 2;
+    u16::from_le_bytes([oam[i], oam[i + 1]])
+}
+
+fn set_oam_word(oam: &mut [u8; 0xA0], row: usize, word: usize, value: u16) {
+    let i = row * 8 + word * 2;
+    oam[i..i + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+// first word is mixed with the previous row, the other three are copied from it
+fn oam_corrupt(oam: &mut [u8; 0xA0], row: usize, glitch: fn(u16, u16, u16) -> u16) {
+    if row == 0 || row >= 20 {
+        return;
+    }
+    let a = oam_word(oam, row, 0);
+    let b = oam_word(oam, row - 1, 0);
+    let c = oam_word(oam, row - 1, 2);
+    set_oam_word(oam, row, 0, glitch(a, b, c));
+    oam.copy_within((row - 1) * 8 + 2..row * 8, row * 8 + 2);
+}
+
+// write to OAM, PUSH/POP, and INC rr / DEC rr on affected models
+pub fn oam_corrupt_write(oam: &mut [u8; 0xA0], row: usize) {
+    oam_corrupt(oam, row, |a, b, c| ((a ^ c) & (b ^ c)) ^ c);
+}
+
+pub fn oam_corrupt_read(oam: &mut [u8; 0xA0], row: usize) {
+    oam_corrupt(oam, row, |a, b, c| b | (a & c));
+}
+
+// read combined with an increment/decrement of the same register, e.g. LD A,(HL+)
+pub fn oam_corrupt_read_idu(oam: &mut [u8; 0xA0], row: usize) {
+    if (4..19).contains(&row) {
+        let a = oam_word(oam, row - 2, 0);
+        let b = oam_word(oam, row - 1, 0);
+        let c = oam_word(oam, row, 0);
+        let d = oam_word(oam, row - 1, 2);
+        set_oam_word(oam, row - 1, 0, (b & (a | c | d)) | (a & c & d));
+        oam.copy_within((row - 1) * 8..row * 8, row * 8);
+        oam.copy_within((row - 1) * 8..row * 8, (row - 2) * 8);
+    }
+    oam_corrupt_read(oam, row);
+}
+
+// The model's differences as the CPU sees them on the bus: the OAM bug when it touches
+// 0xFE00..0xFEFF while the PPU scans OAM (mode 2), STAT writes flagged for the LCD to raise the
+// DMG STAT interrupt, and the CGB-only registers, which read 0xFF and ignore writes elsewhere.
+// On the CGB models VBK with VRAM bank 1 and the colour palette RAM live here in `cgb`.
+// Mode 2 is worked out from the cycle of the access, on the same line grid as the LCD.
+pub struct ModelBus<T: Mmu> {
+    pub quirks: Quirks,
+    pub stat_written: bool, // the CPU wrote STAT since the LCD last took the flag
+    pub cgb: Option<CgbVram>,
+    pub bus: T,
+}
+
+impl<T: Mmu> ModelBus<T> {
+    pub fn new(model: Model, bus: T) -> ModelBus<T> {
+        let quirks = model.quirks();
+        ModelBus { quirks, stat_written: false, cgb: quirks.cgb_features.then(CgbVram::default), bus }
+    }
+
+    // OAM row the PPU reads at T-cycle `cycle`, two sprites per M-cycle
+    fn scanned_row(&self, cycle: u64) -> Option<usize> {
+        let (line, dot) = (cycle / LINE_CYCLES % LINES, cycle % LINE_CYCLES);
+        let scanning = self.bus.read(LCDC) & 0x80 != 0 && line < VBLANK_LINE as u64 && dot < MODE3_START;
+        scanning.then_some(dot as usize / 4)
+    }
+
+    fn corrupt(&mut self, row: usize, glitch: fn(&mut [u8; 0xA0], usize)) {
+        let mut oam = [0u8; 0xA0];
+        for (i, byte) in oam.iter_mut().enumerate() {
+            *byte = self.bus.read(OAM + i as u16);
+        }
+        glitch(&mut oam, row);
+        for (i, byte) in oam.into_iter().enumerate() {
+            self.bus.write(OAM + i as u16, byte);
+        }
+    }
+}
+
+// KEY1, VBK, HDMA1-5, RP, BCPS/BCPD/OCPS/OCPD, OPRI and SVBK
+fn cgb_only(addr: u16) -> bool {
+    matches!(addr, 0xFF4D | 0xFF4F | 0xFF51..=0xFF56 | 0xFF68..=0xFF6C | 0xFF70)
+}
+
+impl<T: Mmu> Mmu for ModelBus<T> {
+    fn read(&self, addr: u16) -> u8 {
+        if let Some(value) = self.cgb.as_ref().and_then(|cgb| cgb.read(addr)) {
+            return value;
+        }
+        match !self.quirks.cgb_features && cgb_only(addr) {
+            true => 0xFF,
+            false => self.bus.read(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if let Some(cgb) = &mut self.cgb
+            && cgb.write(addr, value) {
+            return;
+        }
+        if self.quirks.cgb_features || !cgb_only(addr) {
+            self.bus.write(addr, value);
+        }
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        self.bus.rom_bank(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        if addr == STAT && kind == BusAccess::Write && self.quirks.stat_write_irq {
+            self.stat_written = true;
+        }
+        let bug = match kind {
+            BusAccess::Idu => self.quirks.idu_oam_bug,
+            _ => self.quirks.oam_bug,
+        };
+        if bug && (0xFE00..=0xFEFF).contains(&addr)
+            && let Some(row) = self.scanned_row(cycle) {
+            self.corrupt(row, match kind {
+                BusAccess::Read => oam_corrupt_read,
+                BusAccess::ReadIdu => oam_corrupt_read_idu,
+                BusAccess::Write | BusAccess::Idu => oam_corrupt_write,
+            });
+        }
+        self.bus.access(addr, kind, cycle);
+    }
+}
+
+const REGISTERED_TILE: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+
+// 0b1011 -> 0b11001111
+fn double_bits(nibble: u8) -> u8 {
+    let mut res = 0;
+    for bit in 0..4 {
+        if nibble & (1 << bit) != 0 {
+            res |= 0b11 << (bit * 2);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    #[test]
+    fn doubled_nibbles() {
+        assert_eq!(double_bits(0x0), 0x00);
+        assert_eq!(double_bits(0xF), 0xFF);
+        assert_eq!(double_bits(0b1011), 0b11001111);
+    }
+
+    #[test]
+    fn post_boot_io_per_model() {
+        let dmg = Model::DMG.post_boot_io();
+        assert!(dmg.contains(&(0xFF04, 0xAB)));
+        assert!(dmg.contains(&(0xFF4D, 0xFF)));
+        let cgb = Model::CGB.post_boot_io();
+        assert!(cgb.contains(&(0xFF70, 0xF8)));
+        assert!(!cgb.iter().any(|(addr, _)| *addr == 0xFF04));
+        assert!(Model::SGB.post_boot_io().contains(&(0xFF26, 0xF0)));
+    }
+
+    #[test]
+    fn model_names() {
+        assert_eq!("cgb".parse::<Model>(), Ok(Model::CGB));
+        assert_eq!("SGB2".parse::<Model>(), Ok(Model::SGB2));
+        assert!("gba".parse::<Model>().is_err());
+    }
+
+    #[test]
+    fn quirks_per_model() {
+        assert!(Model::DMG.quirks().oam_bug);
+        assert!(Model::SGB.quirks().stat_write_irq);
+        assert!(!Model::DMG.quirks().cgb_features);
+        let cgb = Model::CGB.quirks();
+        assert!(cgb.cgb_features && !cgb.oam_bug && cgb.idu_oam_bug && !cgb.stat_write_irq);
+        let agb = Model::AGB.quirks();
+        assert!(agb.cgb_features && !agb.oam_bug && !agb.idu_oam_bug && !agb.stat_write_irq);
+    }
+
+    #[test]
+    fn oam_write_corruption() {
+        let mut oam = [0u8; 0xA0];
+        for (i, byte) in oam.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let before = oam;
+        oam_corrupt_write(&mut oam, 0);
+        assert_eq!(oam, before);
+
+        oam_corrupt_write(&mut oam, 2);
+        let (a, b, c) = (0x1110u16, 0x0908u16, 0x0D0Cu16);
+        assert_eq!(oam_word(&oam, 2, 0), ((a ^ c) & (b ^ c)) ^ c);
+        assert_eq!(&oam[18..24], &before[10..16]);
+        assert_eq!(&oam[24..], &before[24..]);
+    }
+
+    #[test]
+    fn oam_read_idu_corruption() {
+        let mut oam = [0u8; 0xA0];
+        for (i, byte) in oam.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let before = oam;
+        oam_corrupt_read_idu(&mut oam, 5);
+        // rows 3 and 5 now mirror the glitched row 4 apart from row 5's read corruption
+        assert_eq!(&oam[24..32], &oam[32..40]);
+        assert_eq!(&oam[42..48], &oam[34..40]);
+        assert_eq!(&oam[48..], &before[48..]);
+    }
+
+    #[test]
+    fn logo_in_vram() {
+        let mut mmu = FlatMmu::new();
+        let mut logo = [0u8; 48];
+        logo[0] = 0xCE; // first byte of the Nintendo logo
+        Model::DMG.apply_post_boot(&mut mmu, &logo);
+        assert_eq!(mmu.read(0xFF40), 0x91);
+        // 0xC -> 0xF0, 0xE -> 0xFC, each written twice on the colour-0 bitplane
+        assert_eq!(&mmu.mem[0x8010..0x8018], &[0xF0, 0x00, 0xF0, 0x00, 0xFC, 0x00, 0xFC, 0x00]);
+        assert_eq!(mmu.read(0x8190), 0x3C);
+        assert_eq!(mmu.read(0x9904), 0x01);
+        assert_eq!(mmu.read(0x992F), 0x18);
+        assert_eq!(mmu.read(0x9910), 0x19);
+
+        let mut mmu = FlatMmu::new();
+        Model::CGB.apply_post_boot(&mut mmu, &logo);
+        assert_eq!(mmu.read(0x8010), 0x00);
+    }
+}
@@ -0,0 +1,269 @@
+//===== MOVIES =====
+// Joypad input recorded one byte per frame (joypad::BUTTONS bits), with everything needed to
+// replay it bit for bit on a machine::GameBoy: the ROM's CRC-32, the model, the RTC seed and the
+// starting point (power on or an embedded save state). A finished recording keeps the CRC-32 of
+// the last frame's picture, so playing it back doubles as a regression test. The RTC seed is kept
+// for clock cartridges (MBC3), which are not emulated yet.
+// Layout: "GBMV", version (u16 LE), then the bincode body. Version 1 hashed the save state
+// instead of the picture. BizHawk .bk2 input logs import too.
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::joypad;
+use crate::machine::GameBoy;
+use crate::model::Model;
+use crate::savestate::{crc32, SaveState, SaveStateError};
+use crate::zip;
+
+pub const MAGIC: [u8; 4] = *b"GBMV";
+pub const VERSION: u16 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum MovieError {
+    BadMagic,
+    Version(u16),
+    RomMismatch { expected: u32, actual: u32 },
+    Corrupt(String),
+    State(SaveStateError),
+    Desync { expected: u32, actual: u32 },
+    Bk2(String),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "not a movie"),
+            MovieError::Version(v) => write!(f, "movie version {} is newer than {}", v, VERSION),
+            MovieError::RomMismatch { expected, actual } =>
+                write!(f, "movie was recorded on ROM {:08X}, this ROM is {:08X}", expected, actual),
+            MovieError::Corrupt(e) => write!(f, "corrupt movie: {}", e),
+            MovieError::State(e) => write!(f, "starting state: {}", e),
+            MovieError::Desync { expected, actual } =>
+                write!(f, "playback ended on frame {:08X}, the recording on {:08X}", actual, expected),
+            MovieError::Bk2(e) => write!(f, "bk2: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Start {
+    PowerOn,
+    State(Vec<u8>), // a save state file
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Movie {
+    pub rom_crc: u32,
+    pub model: Model,
+    pub rtc_seed: u64, // starting time of cartridge clocks
+    pub start: Start,
+    pub frames: Vec<u8>,
+    pub end_hash: Option<u32>, // frame_hash after the last frame
+}
+
+// version 1, frozen
+#[derive(Deserialize)]
+struct MovieV1 {
+    rom_crc: u32,
+    model: Model,
+    rtc_seed: u64,
+    start: Start,
+    frames: Vec<u8>,
+    _end_hash: Option<u32>,
+}
+
+impl From<MovieV1> for Movie {
+    // the old end hash was of the save state, it cannot be checked against a picture
+    fn from(v1: MovieV1) -> Movie {
+        Movie { rom_crc: v1.rom_crc, model: v1.model, rtc_seed: v1.rtc_seed, start: v1.start, frames: v1.frames, end_hash: None }
+    }
+}
+
+// CRC-32 of the screen as the model shows it, what a movie's end hash is checked against
+pub fn frame_hash(gb: &GameBoy) -> u32 {
+    crc32(&gb.image().rgb())
+}
+
+impl Movie {
+    pub fn new(rom: &[u8], model: Model, rtc_seed: u64, start: Start) -> Movie {
+        Movie { rom_crc: crc32(rom), model, rtc_seed, start, frames: Vec::new(), end_hash: None }
+    }
+
+    // the machine where the movie starts
+    pub fn boot(&self, rom: &[u8]) -> Result<GameBoy, MovieError> {
+        let actual = crc32(rom);
+        if actual != self.rom_crc {
+            return Err(MovieError::RomMismatch { expected: self.rom_crc, actual });
+        }
+        let mut gb = GameBoy::new(rom.to_vec(), self.model);
+        if let Start::State(data) = &self.start {
+            let state = SaveState::from_bytes(data, rom).map_err(MovieError::State)?;
+            gb.load_state(&state).map_err(MovieError::State)?;
+        }
+        Ok(gb)
+    }
+
+    // runs one frame with `buttons` held and appends it to the recording
+    pub fn record(&mut self, buttons: u8, gb: &mut GameBoy) {
+        gb.bus.set_buttons(buttons);
+        gb.run_frame();
+        self.frames.push(buttons);
+    }
+
+    pub fn finish(&mut self, gb: &GameBoy) {
+        self.end_hash = Some(frame_hash(gb));
+    }
+
+    // replays every frame and checks the final picture against the recording
+    pub fn play(&self, rom: &[u8]) -> Result<GameBoy, MovieError> {
+        let mut gb = self.boot(rom)?;
+        for &buttons in &self.frames {
+            gb.bus.set_buttons(buttons);
+            gb.run_frame();
+        }
+        let actual = frame_hash(&gb);
+        match self.end_hash {
+            Some(expected) if expected != actual => Err(MovieError::Desync { expected, actual }),
+            _ => Ok(gb),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        bincode::serialize_into(&mut out, self).expect("movies always serialize");
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        if data.len() < 6 || data[..4] != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let corrupt = |e: bincode::Error| MovieError::Corrupt(e.to_string());
+        match u16::from_le_bytes([data[4], data[5]]) {
+            1 => bincode::deserialize::<MovieV1>(&data[6..]).map(Movie::from).map_err(corrupt),
+            VERSION => bincode::deserialize(&data[6..]).map_err(corrupt),
+            v => Err(MovieError::Version(v)),
+        }
+    }
+
+    // the input log of a BizHawk movie (a zip with "Input Log.txt"), from power on
+    pub fn from_bk2(data: &[u8], rom: &[u8], model: Model) -> Result<Movie, MovieError> {
+        let bk2 = |e: zip::ZipError| MovieError::Bk2(e.to_string());
+        if zip::read(data, "Core.bin").map_err(bk2)?.is_some() {
+            return Err(MovieError::Bk2("movies starting from a BizHawk save state are not supported".to_string()));
+        }
+        let log = zip::read(data, "Input Log.txt").map_err(bk2)?.ok_or(MovieError::Bk2("no Input Log.txt".to_string()))?;
+        let mut movie = Movie::new(rom, model, 0, Start::PowerOn);
+        movie.frames = parse_input_log(&String::from_utf8_lossy(&log))?;
+        Ok(movie)
+    }
+}
+
+// "LogKey:#Up|Down|Left|Right|Start|Select|B|A|Power|" names the columns of the "|UDLRSsBAP|"
+// frame lines, where '.' is a released button
+fn parse_input_log(log: &str) -> Result<Vec<u8>, MovieError> {
+    let mut keys: Vec<String> = ["Up", "Down", "Left", "Right", "Start", "Select", "B", "A", "Power"]
+        .iter().map(|k| k.to_string()).collect();
+    let mut frames = Vec::new();
+    for line in log.lines().map(str::trim) {
+        if let Some(key) = line.strip_prefix("LogKey:") {
+            keys = key.split(['#', '|']).filter(|k| !k.is_empty()).map(|k| k.to_string()).collect();
+        } else if line.starts_with('|') {
+            let columns: Vec<char> = line.chars().filter(|&c| c != '|').collect();
+            if columns.len() != keys.len() {
+                return Err(MovieError::Bk2(format!("frame {} has {} columns, LogKey has {}", frames.len(), columns.len(), keys.len())));
+            }
+            let buttons = keys.iter().zip(columns)
+                .filter(|(_, c)| *c != '.' && *c != ' ')
+                .filter_map(|(key, _)| {
+                    let name = key.strip_prefix("P1 ").unwrap_or(key);
+                    joypad::BUTTONS.iter().find(|(n, _)| *n == name).map(|(_, bit)| bit)
+                })
+                .fold(0, |acc, bit| acc | bit);
+            frames.push(buttons);
+        }
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use crate::mmu::Mmu;
+
+    // counts Start presses into $C000 and shows the count as BGP, copies P1 to $C001
+    fn rom() -> Vec<u8> {
+        crate::asm!("SECTION \"main\", ROM0[$100]\n\
+            .loop: ld a, $10\nldh [$00], a\nldh a, [$00]\nld [$C001], a\nbit 3, a\njr nz, .loop\n\
+            ld hl, $C000\ninc [hl]\nld a, [hl]\nldh [$47], a\n.wait: ldh a, [$00]\nbit 3, a\njr z, .wait\njr .loop")
+    }
+
+    #[test]
+    fn record_and_play_back() {
+        let rom = rom();
+        let mut movie = Movie::new(&rom, Model::DMG, 1_700_000_000, Start::PowerOn);
+        let mut gb = movie.boot(&rom).unwrap();
+        for frame in 0..20 {
+            let buttons = if frame % 3 == 0 { joypad::START } else { joypad::A };
+            movie.record(buttons, &mut gb);
+        }
+        movie.finish(&gb);
+        assert_eq!(gb.bus.read(0xC000), 7);
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.rtc_seed, 1_700_000_000);
+        let played = movie.play(&rom).unwrap();
+        assert_eq!(played.cpu, gb.cpu);
+        assert!(played.memory().mem == gb.memory().mem);
+        assert_eq!(played.screen, gb.screen);
+
+        let mut changed = movie.clone();
+        changed.frames[3] = joypad::A;
+        assert!(matches!(changed.play(&rom), Err(MovieError::Desync { .. })));
+        assert!(matches!(movie.play(b"other rom"), Err(MovieError::RomMismatch { .. })));
+    }
+
+    #[test]
+    fn starts_from_a_save_state() {
+        let rom = rom();
+        let mut gb = Movie::new(&rom, Model::DMG, 0, Start::PowerOn).boot(&rom).unwrap();
+        gb.bus.set_buttons(joypad::START);
+        for _ in 0..3 {
+            gb.run_frame();
+        }
+        let state = gb.save_state().to_bytes(&rom);
+        let movie = Movie::new(&rom, Model::DMG, 0, Start::State(state));
+        let gb = movie.play(&rom).unwrap();
+        assert_eq!(gb.cpu.cycles / crate::processor::FRAME_CYCLES, 3);
+        assert_eq!(gb.bus.read(0xC000), 1);
+    }
+
+    // version 1 movies load with their RTC seed, without the state hash
+    #[test]
+    fn migrates_version_1() {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&1u16.to_le_bytes());
+        let v1 = (0x1234_5678u32, Model::MGB, 99u64, Start::PowerOn, vec![1u8, 2, 3], Some(0xABCDu32));
+        bincode::serialize_into(&mut data, &v1).unwrap();
+        let movie = Movie::from_bytes(&data).unwrap();
+        assert_eq!(movie, Movie { rom_crc: 0x1234_5678, model: Model::MGB, rtc_seed: 99, start: Start::PowerOn, frames: vec![1, 2, 3], end_hash: None });
+    }
+
+    // a two-file zip, the input log deflated
+    fn bk2(log: &str) -> Vec<u8> {
+        let mut deflated = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        deflated.write_all(log.as_bytes()).unwrap();
+        zip::build(&[("Header.txt", 0, b"Platform GB\n".to_vec()), ("Input Log.txt", 8, deflated.finish().unwrap())])
+    }
+
+    #[test]
+    fn bk2_import() {
+        let log = "[Input]\nLogKey:#Up|Down|Left|Right|Start|Select|B|A|Power|\n\
+                   |.........|\n|....S....|\n|U.....B..|\n|...R...A.|\n[/Input]\n";
+        let movie = Movie::from_bk2(&bk2(log), &rom(), Model::DMG).unwrap();
+        assert_eq!(movie.frames, vec![0, joypad::START, joypad::UP | joypad::B, joypad::RIGHT | joypad::A]);
+        assert!(Movie::from_bk2(b"not a zip", &rom(), Model::DMG).is_err());
+        assert!(matches!(Movie::from_bk2(&bk2("|..|\n"), &rom(), Model::DMG), Err(MovieError::Bk2(_))));
+    }
+}
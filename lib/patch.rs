@@ -0,0 +1,330 @@
+//===== PATCH =====
+// Soft-patching ROMs in memory with IPS, UPS or BPS patches, told apart by their magic. IPS is
+// records of (offset, bytes) or runs of one byte, with no checksums. UPS XORs runs of the ROM
+// and BPS rebuilds it from reads and copies of the source, the target and the patch; both end
+// with the CRC-32 of the source, the target and the patch itself, all three checked. A patch
+// sitting beside the ROM shares its name: game.gb's is game.bps, game.ups or game.ips.
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::savestate::crc32;
+
+// looked for in this order, the checksummed formats first
+pub const PATCH_EXTENSIONS: [&str; 3] = ["bps", "ups", "ips"];
+// the largest ROM size a header can give, 8MB
+const MAX_TARGET: usize = 0x8000 << 8;
+
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    Format, // no known magic
+    Corrupt(&'static str),
+    PatchChecksum { expected: u32, actual: u32 },
+    SourceChecksum { expected: u32, actual: u32 },
+    TargetChecksum { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::Format => write!(f, "not an IPS, UPS or BPS patch"),
+            PatchError::Corrupt(what) => write!(f, "corrupt patch: {}", what),
+            PatchError::PatchChecksum { expected, actual } =>
+                write!(f, "patch CRC-32 is {:08X}, the file adds up to {:08X}", expected, actual),
+            PatchError::SourceChecksum { expected, actual } =>
+                write!(f, "the patch is for ROM {:08X}, this ROM is {:08X}", expected, actual),
+            PatchError::TargetChecksum { expected, actual } =>
+                write!(f, "the patched ROM should be {:08X} but is {:08X}", expected, actual),
+        }
+    }
+}
+
+// the first patch file named after the ROM, if any
+pub fn beside(rom: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS.iter().map(|ext| rom.with_extension(ext)).find(|path| path.is_file())
+}
+
+// the patched copy of `rom`
+pub fn apply(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if let Some(records) = patch.strip_prefix(b"PATCH") {
+        ips(records, rom)
+    } else if patch.starts_with(b"UPS1") {
+        ups(patch, rom)
+    } else if patch.starts_with(b"BPS1") {
+        bps(patch, rom)
+    } else {
+        Err(PatchError::Format)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, n: usize) -> Result<&[u8], PatchError> {
+        let bytes = self.data.get(self.pos..self.pos.saturating_add(n)).ok_or(PatchError::Corrupt("cut short"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn big_endian(&mut self, n: usize) -> Result<usize, PatchError> {
+        Ok(self.bytes(n)?.iter().fold(0, |value, &b| value << 8 | b as usize))
+    }
+
+    // UPS and BPS numbers: 7 bits at a time, low first, the last byte has bit 7 set and every
+    // byte before it counts one more. Nine bytes cover 63 bits, more is not a number we can use.
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let too_large = || PatchError::Corrupt("number too large");
+        let (mut value, mut shift) = (0usize, 1usize);
+        for _ in 0..9 {
+            let byte = self.byte()?;
+            value = (byte as usize & 0x7F).checked_mul(shift).and_then(|v| value.checked_add(v)).ok_or_else(too_large)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift <<= 7;
+            value = value.checked_add(shift).ok_or_else(too_large)?;
+        }
+        Err(too_large())
+    }
+}
+
+fn ips(records: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut patch = Reader { data: records, pos: 0 };
+    loop {
+        if patch.data[patch.pos..].starts_with(b"EOF") {
+            patch.pos += 3;
+            break;
+        }
+        let offset = patch.big_endian(3)?;
+        let (bytes, size) = match patch.big_endian(2)? {
+            0 => {
+                let size = patch.big_endian(2)?;
+                (vec![patch.byte()?; size], size)
+            }
+            size => (patch.bytes(size)?.to_vec(), size),
+        };
+        if out.len() < offset + size {
+            out.resize(offset + size, 0);
+        }
+        out[offset..offset + size].copy_from_slice(&bytes);
+    }
+    // an extension: the size to cut the ROM down to
+    if let Ok(size) = patch.big_endian(3) {
+        out.truncate(size);
+    }
+    Ok(out)
+}
+
+// the three CRC-32s at the end, after checking the last one covers the rest of the patch
+fn footer(patch: &[u8]) -> Result<(u32, u32), PatchError> {
+    let end = patch.len().checked_sub(12).filter(|&end| end >= 4).ok_or(PatchError::Corrupt("cut short"))?;
+    let crc = |at: usize| u32::from_le_bytes(patch[at..at + 4].try_into().unwrap());
+    let (expected, actual) = (crc(end + 8), crc32(&patch[..end + 8]));
+    if expected != actual {
+        return Err(PatchError::PatchChecksum { expected, actual });
+    }
+    Ok((crc(end), crc(end + 4)))
+}
+
+fn check_source(expected: u32, rom: &[u8]) -> Result<(), PatchError> {
+    match crc32(rom) {
+        actual if actual != expected => Err(PatchError::SourceChecksum { expected, actual }),
+        _ => Ok(()),
+    }
+}
+
+fn check_target(expected: u32, out: Vec<u8>) -> Result<Vec<u8>, PatchError> {
+    match crc32(&out) {
+        actual if actual != expected => Err(PatchError::TargetChecksum { expected, actual }),
+        _ => Ok(out),
+    }
+}
+
+fn target(size: usize) -> Result<usize, PatchError> {
+    match size {
+        0..=MAX_TARGET => Ok(size),
+        _ => Err(PatchError::Corrupt("the target is larger than any ROM")),
+    }
+}
+
+fn ups(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (source_crc, target_crc) = footer(patch)?;
+    check_source(source_crc, rom)?;
+    let end = patch.len() - 12;
+    let mut hunks = Reader { data: &patch[..end], pos: 4 };
+    let _source_size = hunks.number()?;
+    let target_size = target(hunks.number()?)?;
+    let mut out = rom.to_vec();
+    out.resize(target_size, 0);
+    let mut pos = 0usize;
+    while hunks.pos < end {
+        pos = pos.saturating_add(hunks.number()?);
+        // XOR up to and including a zero byte
+        loop {
+            let byte = hunks.byte()?;
+            if let Some(b) = out.get_mut(pos) {
+                *b ^= byte;
+            }
+            pos = pos.saturating_add(1);
+            if byte == 0 {
+                break;
+            }
+        }
+    }
+    check_target(target_crc, out)
+}
+
+fn bps(patch: &[u8], rom: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (source_crc, target_crc) = footer(patch)?;
+    check_source(source_crc, rom)?;
+    let end = patch.len() - 12;
+    let mut actions = Reader { data: &patch[..end], pos: 4 };
+    let _source_size = actions.number()?;
+    let target_size = target(actions.number()?)?;
+    let metadata = actions.number()?;
+    actions.bytes(metadata)?;
+    let mut out = Vec::with_capacity(target_size);
+    let (mut source_at, mut target_at) = (0usize, 0usize);
+    // relative offsets: bit 0 is the sign
+    let step = |at: usize, offset: usize| match offset & 1 {
+        0 => at.checked_add(offset >> 1),
+        _ => at.checked_sub(offset >> 1),
+    }.ok_or(PatchError::Corrupt("copy out of range"));
+    while actions.pos < end {
+        let action = actions.number()?;
+        let length = (action >> 2) + 1;
+        if length > target_size - out.len() {
+            return Err(PatchError::Corrupt("writes past the target size"));
+        }
+        match action & 3 {
+            // source read: the ROM's bytes at the same place
+            0 => {
+                let at = out.len();
+                out.extend_from_slice(rom.get(at..at + length).ok_or(PatchError::Corrupt("read past the ROM"))?);
+            }
+            1 => out.extend_from_slice(actions.bytes(length)?),
+            2 => {
+                source_at = step(source_at, actions.number()?)?;
+                out.extend_from_slice(rom.get(source_at..source_at.saturating_add(length)).ok_or(PatchError::Corrupt("copy past the ROM"))?);
+                source_at += length;
+            }
+            // target copy: may overlap what it writes, so a byte at a time
+            _ => {
+                target_at = step(target_at, actions.number()?)?;
+                for _ in 0..length {
+                    let byte = *out.get(target_at).ok_or(PatchError::Corrupt("copy past the output"))?;
+                    out.push(byte);
+                    target_at += 1;
+                }
+            }
+        }
+    }
+    if out.len() != target_size {
+        return Err(PatchError::Corrupt("the output is shorter than the target size"));
+    }
+    check_target(target_crc, out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn number(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let x = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | x);
+                return bytes;
+            }
+            bytes.push(x);
+            value -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn reads_numbers() {
+        for value in [0, 1, 127, 128, 300, 0x4000, 0x123456] {
+            let bytes = number(value);
+            assert_eq!(Reader { data: &bytes, pos: 0 }.number(), Ok(value));
+        }
+        let overlong = [0x7F; 9];
+        assert_eq!(Reader { data: &overlong, pos: 0 }.number(), Err(PatchError::Corrupt("number too large")));
+        // with a valid footer, as the size of the target
+        let mut patch = b"UPS1".to_vec();
+        patch.extend_from_slice(&overlong);
+        let patch = with_footer(patch, b"ROM", b"ROM");
+        assert_eq!(apply(&patch, b"ROM"), Err(PatchError::Corrupt("number too large")));
+    }
+
+    #[test]
+    fn applies_ips() {
+        let rom = vec![0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x03, 0x11]); // RLE, grows the ROM
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply(&patch, &rom).unwrap(), [0, 0, 0xAA, 0xBB, 0, 0, 0, 0, 0x11, 0x11, 0x11]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x04]);
+        assert_eq!(apply(&patch, &rom).unwrap(), [0, 0, 0xAA, 0xBB]);
+        assert_eq!(apply(b"PATCH\x00\x00", &rom), Err(PatchError::Corrupt("cut short")));
+        assert_eq!(apply(b"ROM", &rom), Err(PatchError::Format));
+    }
+
+    #[test]
+    fn applies_ups_and_checks_crcs() {
+        let rom = b"HELLO WORLD".to_vec();
+        let target = b"HELLO THERE!".to_vec();
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(rom.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(6));
+        // against the ROM padded with zeros to the target size
+        patch.extend((6..target.len()).map(|i| rom.get(i).unwrap_or(&0) ^ target[i]));
+        patch.push(0);
+        let patch = with_footer(patch, &rom, &target);
+        assert_eq!(apply(&patch, &rom).unwrap(), target);
+
+        let other = b"HELLO WORLD?".to_vec();
+        assert_eq!(apply(&patch, &other), Err(PatchError::SourceChecksum { expected: crc32(&rom), actual: crc32(&other) }));
+        let mut damaged = patch.clone();
+        damaged[8] ^= 1;
+        assert!(matches!(apply(&damaged, &rom), Err(PatchError::PatchChecksum { .. })));
+    }
+
+    #[test]
+    fn applies_bps() {
+        let rom = b"ABCDEFGH".to_vec();
+        let target = b"ABCDxyxyxyGH".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(number(rom.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(0));
+        patch.extend(number((4 - 1) << 2)); // source read ABCD
+        patch.extend(number((2 - 1) << 2 | 1)); // target read xy
+        patch.extend_from_slice(b"xy");
+        patch.extend(number((4 - 1) << 2 | 3)); // target copy from 4, overlapping: xyxy
+        patch.extend(number(4 << 1));
+        patch.extend(number((2 - 1) << 2 | 2)); // source copy GH
+        patch.extend(number(6 << 1));
+        let patch = with_footer(patch, &rom, &target);
+        assert_eq!(apply(&patch, &rom).unwrap(), target);
+
+        let wrong = with_footer(patch[..patch.len() - 12].to_vec(), &rom, b"ABCDxyxyxyGG");
+        assert_eq!(apply(&wrong, &rom), Err(PatchError::TargetChecksum { expected: crc32(b"ABCDxyxyxyGG"), actual: crc32(&target) }));
+    }
+}
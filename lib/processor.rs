@@ -0,0 +1,649 @@
+//===== PROCESSOR =====
+// Executes INSTRUCTIONS against an Mmu. Every bus access costs 4 T-cycles and the internal
+// delays are added where the hardware has them, so `cycles` follows real instruction timing.
+use serde::{Deserialize, Serialize};
+use crate::instructions::*;
+use crate::mmu::{BusAccess, Mmu};
+use crate::registers::*;
+use crate::registers::CpuFlag::*;
+
+// interrupt flag and enable registers, bit 0 (VBlank) has the highest priority
+pub const IF: u16 = 0xFF0F;
+pub const IE: u16 = 0xFFFF;
+
+// T-cycles in one 154-line LCD frame at single speed
+pub const FRAME_CYCLES: u64 = 70224;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cpu {
+    pub regs: Registers,
+    pub ime: bool,
+    pub ei_delay: bool, // EI only takes effect after the next instruction
+    pub halted: bool,
+    pub halt_bug: bool, // HALT with IME=0 and a pending interrupt: the next opcode is read twice
+    pub locked: bool, // an illegal opcode hangs the CPU until reset
+    pub cycles: u64, // T-cycles since reset
+}
+
+impl Default for Cpu {
+    fn default() -> Self { Cpu::new(Registers::new()) }
+}
+
+impl Cpu {
+    pub fn new(regs: Registers) -> Cpu {
+        Cpu { regs, ime: false, ei_delay: false, halted: false, halt_bug: false, locked: false, cycles: 0 }
+    }
+
+    // instruction at PC, with the CB page decoded
+    pub fn peek<T: Mmu>(&self, mmu: &T) -> Instruction {
+        match mmu.read(self.regs.pc) {
+            0xCB => decode_cb(mmu.read(self.regs.pc.wrapping_add(1))),
+            op => INSTRUCTIONS[op as usize],
+        }
+    }
+
+    // true when the next step() runs an instruction, not an interrupt dispatch, an idle halt
+    // cycle or a locked CPU
+    pub fn executes<T: Mmu>(&self, mmu: &T) -> bool {
+        let pending = mmu.pending_interrupts();
+        match pending {
+            0 => !self.locked && !self.halted,
+            _ => !self.locked && !self.ime,
+        }
+    }
+
+    // an EI whose delay has not run out yet
+    pub fn ei_pending(&self) -> bool {
+        self.ei_delay
+    }
+
+    // runs to the first instruction boundary of the next frame and returns that frame's number
+    pub fn run_frame<T: Mmu>(&mut self, mmu: &mut T) -> u64 {
+        let frame = self.cycles / FRAME_CYCLES + 1;
+        while self.cycles < frame * FRAME_CYCLES {
+            self.step(mmu);
+        }
+        frame
+    }
+
+    // runs one instruction (or an interrupt dispatch, or one idle cycle while halted) and
+    // returns the T-cycles it took
+    pub fn step<T: Mmu>(&mut self, mmu: &mut T) -> u32 {
+        let start = self.cycles;
+        if self.locked {
+            self.tick();
+            return 4;
+        }
+        let pending = mmu.pending_interrupts();
+        if self.halted {
+            if pending == 0 {
+                self.tick();
+                return 4;
+            }
+            self.halted = false;
+        }
+        if self.ime && pending != 0 {
+            self.interrupt(mmu, pending);
+            return (self.cycles - start) as u32;
+        }
+        if self.ei_delay {
+            self.ei_delay = false;
+            self.ime = true;
+        }
+
+        let pc = self.regs.pc;
+        let op = self.fetch8(mmu);
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.regs.pc = pc;
+        }
+        let instr = match op {
+            0xCB => decode_cb(self.fetch8(mmu)),
+            _ => INSTRUCTIONS[op as usize],
+        };
+        self.execute(mmu, &instr, pending);
+        (self.cycles - start) as u32
+    }
+
+    // 5 M-cycles: two idle, PC pushed, then the jump to 0x40 + 8 * bit
+    fn interrupt<T: Mmu>(&mut self, mmu: &mut T, pending: u8) {
+        let bit = pending.trailing_zeros() as u16;
+        self.ime = false;
+        self.ei_delay = false;
+        mmu.write(IF, mmu.read(IF) & !(1 << bit));
+        self.tick();
+        self.tick();
+        self.push(mmu, self.regs.pc);
+        self.tick();
+        self.regs.pc = 0x40 + 8 * bit;
+    }
+
+    // --- bus ---
+    fn tick(&mut self) {
+        self.cycles += 4;
+    }
+
+    fn read<T: Mmu>(&mut self, mmu: &mut T, addr: u16) -> u8 {
+        self.read_as(mmu, addr, BusAccess::Read)
+    }
+
+    fn read_as<T: Mmu>(&mut self, mmu: &mut T, addr: u16, kind: BusAccess) -> u8 {
+        mmu.access(addr, kind, self.cycles);
+        self.tick();
+        mmu.read(addr)
+    }
+
+    fn write<T: Mmu>(&mut self, mmu: &mut T, addr: u16, value: u8) {
+        mmu.access(addr, BusAccess::Write, self.cycles);
+        self.tick();
+        mmu.write(addr, value);
+    }
+
+    // an internal cycle with the 16-bit incrementer putting `addr` on the bus
+    fn idu<T: Mmu>(&mut self, mmu: &mut T, addr: u16) {
+        mmu.access(addr, BusAccess::Idu, self.cycles);
+        self.tick();
+    }
+
+    fn fetch8<T: Mmu>(&mut self, mmu: &mut T) -> u8 {
+        let value = self.read(mmu, self.regs.pc);
+        self.regs.pc = self.regs.pc.wrapping_add(1);
+        value
+    }
+
+    fn fetch16<T: Mmu>(&mut self, mmu: &mut T) -> u16 {
+        let lo = self.fetch8(mmu) as u16;
+        let hi = self.fetch8(mmu) as u16;
+        (hi << 8) | lo
+    }
+
+    fn push<T: Mmu>(&mut self, mmu: &mut T, value: u16) {
+        self.regs.sp = self.regs.sp.wrapping_sub(1);
+        self.write(mmu, self.regs.sp, (value >> 8) as u8);
+        self.regs.sp = self.regs.sp.wrapping_sub(1);
+        self.write(mmu, self.regs.sp, value as u8);
+    }
+
+    fn pop<T: Mmu>(&mut self, mmu: &mut T) -> u16 {
+        let lo = self.read(mmu, self.regs.sp) as u16;
+        self.regs.sp = self.regs.sp.wrapping_add(1);
+        let hi = self.read(mmu, self.regs.sp) as u16;
+        self.regs.sp = self.regs.sp.wrapping_add(1);
+        (hi << 8) | lo
+    }
+
+    // --- registers ---
+    fn get8(&self, reg: RegType) -> u8 {
+        match reg {
+            RegType::A => self.regs.a,
+            RegType::B => self.regs.b,
+            RegType::C => self.regs.c,
+            RegType::D => self.regs.d,
+            RegType::E => self.regs.e,
+            RegType::H => self.regs.h,
+            RegType::L => self.regs.l,
+            _ => unreachable!("{:?} is not an 8-bit register", reg),
+        }
+    }
+
+    fn set8(&mut self, reg: RegType, value: u8) {
+        match reg {
+            RegType::A => self.regs.a = value,
+            RegType::B => self.regs.b = value,
+            RegType::C => self.regs.c = value,
+            RegType::D => self.regs.d = value,
+            RegType::E => self.regs.e = value,
+            RegType::H => self.regs.h = value,
+            RegType::L => self.regs.l = value,
+            _ => unreachable!("{:?} is not an 8-bit register", reg),
+        }
+    }
+
+    fn get16(&self, reg: RegType) -> u16 {
+        match reg {
+            RegType::AF => self.regs.af(),
+            RegType::BC => self.regs.bc(),
+            RegType::DE => self.regs.de(),
+            RegType::HL => self.regs.hl(),
+            RegType::SP => self.regs.sp,
+            RegType::PC => self.regs.pc,
+            _ => unreachable!("{:?} is not a 16-bit register", reg),
+        }
+    }
+
+    fn set16(&mut self, reg: RegType, value: u16) {
+        match reg {
+            RegType::AF => self.regs.setaf(value),
+            RegType::BC => self.regs.setbc(value),
+            RegType::DE => self.regs.setde(value),
+            RegType::HL => self.regs.sethl(value),
+            RegType::SP => self.regs.sp = value,
+            RegType::PC => self.regs.pc = value,
+            _ => unreachable!("{:?} is not a 16-bit register", reg),
+        }
+    }
+
+    fn is16(reg: RegType) -> bool {
+        matches!(reg, RegType::AF | RegType::BC | RegType::DE | RegType::HL | RegType::SP | RegType::PC)
+    }
+
+    fn flags(&mut self, z: bool, n: bool, h: bool, c: bool) {
+        self.regs.flag(&Z, z);
+        self.regs.flag(&N, n);
+        self.regs.flag(&H, h);
+        self.regs.flag(&C, c);
+    }
+
+    fn condition(&self, cond: ConditionType) -> bool {
+        match cond {
+            ConditionType::NONE => true,
+            ConditionType::NZ => !self.regs.getflag(&Z),
+            ConditionType::Z => self.regs.getflag(&Z),
+            ConditionType::NC => !self.regs.getflag(&C),
+            ConditionType::C => self.regs.getflag(&C),
+        }
+    }
+
+    // (C) is the 0xFF00 page, anything else is a register pair
+    fn pointer(&self, reg: RegType) -> u16 {
+        match reg {
+            RegType::C => 0xFF00 | self.regs.c as u16,
+            _ => self.get16(reg),
+        }
+    }
+
+    // 8-bit operand of the ALU, CB and INC/DEC instructions
+    fn load8<T: Mmu>(&mut self, mmu: &mut T, instr: &Instruction) -> u8 {
+        use AddressingMode as AM;
+        match instr.mode {
+            AM::R_D8 => self.fetch8(mmu),
+            AM::R_R => self.get8(instr.reg_2),
+            AM::R_MR => self.read(mmu, self.pointer(instr.reg_2)),
+            AM::MR => self.read(mmu, self.regs.hl()),
+            _ => self.get8(instr.reg_1),
+        }
+    }
+
+    fn store8<T: Mmu>(&mut self, mmu: &mut T, instr: &Instruction, value: u8) {
+        match instr.mode {
+            AddressingMode::MR => self.write(mmu, self.regs.hl(), value),
+            _ => self.set8(instr.reg_1, value),
+        }
+    }
+
+    // --- execution ---
+    fn execute<T: Mmu>(&mut self, mmu: &mut T, instr: &Instruction, pending: u8) {
+        use InstructionType as IN;
+        use AddressingMode as AM;
+        match instr.instr_type {
+            IN::NOP => {}
+            IN::LD | IN::LDH => self.load(mmu, instr),
+            IN::INC | IN::DEC if Self::is16(instr.reg_1) && instr.mode == AM::R => {
+                let value = self.get16(instr.reg_1);
+                self.idu(mmu, value);
+                let value = match instr.instr_type {
+                    IN::INC => value.wrapping_add(1),
+                    _ => value.wrapping_sub(1),
+                };
+                self.set16(instr.reg_1, value);
+            }
+            IN::INC | IN::DEC => {
+                let value = self.load8(mmu, instr);
+                let (result, h) = match instr.instr_type {
+                    IN::INC => (value.wrapping_add(1), value & 0x0F == 0x0F),
+                    _ => (value.wrapping_sub(1), value & 0x0F == 0x00),
+                };
+                self.store8(mmu, instr, result);
+                self.regs.flag(&Z, result == 0);
+                self.regs.flag(&N, instr.instr_type == IN::DEC);
+                self.regs.flag(&H, h);
+            }
+            IN::ADD if instr.reg_1 == RegType::HL => {
+                let (hl, rr) = (self.regs.hl(), self.get16(instr.reg_2));
+                let (result, c) = hl.overflowing_add(rr);
+                self.tick();
+                self.regs.sethl(result);
+                self.regs.flag(&N, false);
+                self.regs.flag(&H, (hl & 0x0FFF) + (rr & 0x0FFF) > 0x0FFF);
+                self.regs.flag(&C, c);
+            }
+            IN::ADD if instr.reg_1 == RegType::SP => {
+                let result = self.sp_offset(mmu);
+                self.tick();
+                self.tick();
+                self.regs.sp = result;
+            }
+            IN::ADD | IN::ADC | IN::SUB | IN::SBC | IN::AND | IN::XOR | IN::OR | IN::CP => {
+                let value = self.load8(mmu, instr);
+                self.alu(instr.instr_type, value);
+            }
+            IN::JP if instr.mode == AM::JPHL => self.regs.pc = self.regs.hl(),
+            IN::JP => {
+                let addr = self.fetch16(mmu);
+                if self.condition(instr.cond) {
+                    self.tick();
+                    self.regs.pc = addr;
+                }
+            }
+            IN::JR => {
+                let offset = self.fetch8(mmu) as i8;
+                if self.condition(instr.cond) {
+                    self.tick();
+                    self.regs.pc = self.regs.pc.wrapping_add(offset as u16);
+                }
+            }
+            IN::CALL => {
+                let addr = self.fetch16(mmu);
+                if self.condition(instr.cond) {
+                    self.tick();
+                    self.push(mmu, self.regs.pc);
+                    self.regs.pc = addr;
+                }
+            }
+            IN::RET => {
+                if instr.cond != ConditionType::NONE {
+                    self.tick();
+                }
+                if self.condition(instr.cond) {
+                    self.regs.pc = self.pop(mmu);
+                    self.tick();
+                }
+            }
+            IN::RETI => {
+                self.regs.pc = self.pop(mmu);
+                self.tick();
+                self.ime = true;
+            }
+            IN::RST => {
+                self.tick();
+                self.push(mmu, self.regs.pc);
+                self.regs.pc = instr.param.unwrap_or(0) as u16;
+            }
+            IN::PUSH => {
+                self.idu(mmu, self.regs.sp);
+                self.push(mmu, self.get16(instr.reg_1));
+            }
+            IN::POP => {
+                let value = self.pop(mmu);
+                self.set16(instr.reg_1, value);
+            }
+            IN::RLCA | IN::RRCA | IN::RLA | IN::RRA => {
+                let op = match instr.instr_type {
+                    IN::RLCA => IN::RLC,
+                    IN::RRCA => IN::RRC,
+                    IN::RLA => IN::RL,
+                    _ => IN::RR,
+                };
+                self.regs.a = self.shift(op, self.regs.a);
+                self.regs.flag(&Z, false);
+            }
+            IN::RLC | IN::RRC | IN::RL | IN::RR | IN::SLA | IN::SRA | IN::SWAP | IN::SRL => {
+                let value = self.load8(mmu, instr);
+                let result = self.shift(instr.instr_type, value);
+                self.store8(mmu, instr, result);
+            }
+            IN::BIT => {
+                let value = self.load8(mmu, instr);
+                self.regs.flag(&Z, value & (1 << instr.param.unwrap_or(0)) == 0);
+                self.regs.flag(&N, false);
+                self.regs.flag(&H, true);
+            }
+            IN::RES | IN::SET => {
+                let value = self.load8(mmu, instr);
+                let mask = 1 << instr.param.unwrap_or(0);
+                let result = if instr.instr_type == IN::SET { value | mask } else { value & !mask };
+                self.store8(mmu, instr, result);
+            }
+            IN::DAA => self.daa(),
+            IN::CPL => {
+                self.regs.a = !self.regs.a;
+                self.regs.flag(&N, true);
+                self.regs.flag(&H, true);
+            }
+            IN::SCF | IN::CCF => {
+                let c = instr.instr_type == IN::SCF || !self.regs.getflag(&C);
+                self.regs.flag(&N, false);
+                self.regs.flag(&H, false);
+                self.regs.flag(&C, c);
+            }
+            IN::HALT => match !self.ime && pending != 0 {
+                true => self.halt_bug = true,
+                false => self.halted = true,
+            },
+            // skips the padding byte and sleeps until an interrupt; there is no joypad or speed
+            // switch to wake it otherwise
+            IN::STOP => {
+                self.regs.pc = self.regs.pc.wrapping_add(1);
+                self.halted = true;
+            }
+            IN::DI => {
+                self.ime = false;
+                self.ei_delay = false;
+            }
+            IN::EI => self.ei_delay = true,
+            IN::ERR | IN::NONE | IN::CB | IN::JPHL => self.locked = true,
+        }
+    }
+
+    fn load<T: Mmu>(&mut self, mmu: &mut T, instr: &Instruction) {
+        use AddressingMode as AM;
+        match instr.mode {
+            AM::R_D16 => {
+                let value = self.fetch16(mmu);
+                self.set16(instr.reg_1, value);
+            }
+            AM::R_R if instr.reg_1 == RegType::SP => {
+                self.tick();
+                self.regs.sp = self.regs.hl();
+            }
+            AM::R_R => self.set8(instr.reg_1, self.get8(instr.reg_2)),
+            AM::R_D8 | AM::R_MR => {
+                let value = self.load8(mmu, instr);
+                self.set8(instr.reg_1, value);
+            }
+            AM::MR_R => self.write(mmu, self.pointer(instr.reg_1), self.get8(instr.reg_2)),
+            AM::MR_D8 => {
+                let value = self.fetch8(mmu);
+                self.write(mmu, self.regs.hl(), value);
+            }
+            AM::A16_R if instr.reg_2 == RegType::SP => {
+                let addr = self.fetch16(mmu);
+                self.write(mmu, addr, self.regs.sp as u8);
+                self.write(mmu, addr.wrapping_add(1), (self.regs.sp >> 8) as u8);
+            }
+            AM::A16_R => {
+                let addr = self.fetch16(mmu);
+                self.write(mmu, addr, self.get8(instr.reg_2));
+            }
+            AM::R_A16 => {
+                let addr = self.fetch16(mmu);
+                let value = self.read(mmu, addr);
+                self.set8(instr.reg_1, value);
+            }
+            AM::A8_R => {
+                let addr = 0xFF00 | self.fetch8(mmu) as u16;
+                self.write(mmu, addr, self.get8(instr.reg_2));
+            }
+            AM::R_A8 => {
+                let addr = 0xFF00 | self.fetch8(mmu) as u16;
+                let value = self.read(mmu, addr);
+                self.set8(instr.reg_1, value);
+            }
+            AM::HLI_R | AM::HLD_R => {
+                let addr = if instr.mode == AM::HLI_R { self.regs.hli() } else { self.regs.hld() };
+                self.write(mmu, addr, self.get8(instr.reg_2));
+            }
+            AM::R_HLI | AM::R_HLD => {
+                let addr = if instr.mode == AM::R_HLI { self.regs.hli() } else { self.regs.hld() };
+                let value = self.read_as(mmu, addr, BusAccess::ReadIdu);
+                self.set8(instr.reg_1, value);
+            }
+            AM::HL_SPR => {
+                let result = self.sp_offset(mmu);
+                self.tick();
+                self.regs.sethl(result);
+            }
+            _ => unreachable!("LD with {:?}", instr.mode),
+        }
+    }
+
+    // SP + e8 for ADD SP and LD HL, SP+e8: H and C come from the unsigned low byte addition
+    fn sp_offset<T: Mmu>(&mut self, mmu: &mut T) -> u16 {
+        let offset = self.fetch8(mmu);
+        let sp = self.regs.sp;
+        self.flags(false, false, (sp & 0x0F) + (offset as u16 & 0x0F) > 0x0F, (sp & 0xFF) + offset as u16 > 0xFF);
+        sp.wrapping_add(offset as i8 as u16)
+    }
+
+    fn alu(&mut self, op: InstructionType, value: u8) {
+        use InstructionType as IN;
+        let a = self.regs.a;
+        let carry = self.regs.getflag(&C) as u8;
+        match op {
+            IN::ADD | IN::ADC => {
+                let carry = if op == IN::ADC { carry } else { 0 };
+                let result = a as u16 + value as u16 + carry as u16;
+                self.regs.a = result as u8;
+                self.flags(result as u8 == 0, false, (a & 0x0F) + (value & 0x0F) + carry > 0x0F, result > 0xFF);
+            }
+            IN::SUB | IN::SBC | IN::CP => {
+                let carry = if op == IN::SBC { carry } else { 0 };
+                let result = (a as i16) - (value as i16) - (carry as i16);
+                let h = ((a & 0x0F) as i16) - ((value & 0x0F) as i16) - (carry as i16) < 0;
+                if op != IN::CP {
+                    self.regs.a = result as u8;
+                }
+                self.flags(result as u8 == 0, true, h, result < 0);
+            }
+            IN::AND => {
+                self.regs.a = a & value;
+                self.flags(self.regs.a == 0, false, true, false);
+            }
+            IN::XOR => {
+                self.regs.a = a ^ value;
+                self.flags(self.regs.a == 0, false, false, false);
+            }
+            _ => {
+                self.regs.a = a | value;
+                self.flags(self.regs.a == 0, false, false, false);
+            }
+        }
+    }
+
+    // rotates and shifts of the CB page, also used by RLCA/RRCA/RLA/RRA
+    fn shift(&mut self, op: InstructionType, value: u8) -> u8 {
+        use InstructionType as IN;
+        let carry = self.regs.getflag(&C) as u8;
+        let (result, c) = match op {
+            IN::RLC => (value.rotate_left(1), value & 0x80 != 0),
+            IN::RRC => (value.rotate_right(1), value & 0x01 != 0),
+            IN::RL => ((value << 1) | carry, value & 0x80 != 0),
+            IN::RR => ((value >> 1) | (carry << 7), value & 0x01 != 0),
+            IN::SLA => (value << 1, value & 0x80 != 0),
+            IN::SRA => ((value >> 1) | (value & 0x80), value & 0x01 != 0),
+            IN::SWAP => (value.rotate_left(4), false),
+            _ => (value >> 1, value & 0x01 != 0),
+        };
+        self.flags(result == 0, false, false, c);
+        result
+    }
+
+    fn daa(&mut self) {
+        let mut a = self.regs.a;
+        let mut c = self.regs.getflag(&C);
+        match self.regs.getflag(&N) {
+            false => {
+                if c || a > 0x99 {
+                    a = a.wrapping_add(0x60);
+                    c = true;
+                }
+                if self.regs.getflag(&H) || a & 0x0F > 0x09 {
+                    a = a.wrapping_add(0x06);
+                }
+            }
+            true => {
+                if c {
+                    a = a.wrapping_sub(0x60);
+                }
+                if self.regs.getflag(&H) {
+                    a = a.wrapping_sub(0x06);
+                }
+            }
+        }
+        self.regs.a = a;
+        self.regs.flag(&Z, a == 0);
+        self.regs.flag(&H, false);
+        self.regs.flag(&C, c);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    fn run(code: &[u8], steps: usize) -> (Cpu, FlatMmu) {
+        let mut mmu = FlatMmu::new();
+        mmu.load(0x0100, code);
+        let mut cpu = Cpu::default();
+        for _ in 0..steps {
+            cpu.step(&mut mmu);
+        }
+        (cpu, mmu)
+    }
+
+    #[test]
+    fn arithmetic_and_flags() {
+        let (cpu, _) = run(&crate::asm!("ld a, $15\nadd a, $27\ndaa\nld b, a\nsub a, $43"), 5);
+        assert_eq!(cpu.regs.b, 0x42);
+        assert_eq!(cpu.regs.a, 0xFF);
+        assert!(cpu.regs.getflag(&N) && cpu.regs.getflag(&C) && cpu.regs.getflag(&H));
+    }
+
+    #[test]
+    fn calls_and_timing() {
+        // 0x100: call $0110 (24), 0x103: halt; 0x110: push bc (16), pop de (12), ret (16)
+        let mut code = crate::asm!("call $0110\nhalt");
+        code.resize(0x10, 0);
+        code.extend(crate::asm!("ld bc, $1234\npush bc\npop de\nret"));
+        let (cpu, mmu) = run(&code, 5);
+        assert_eq!(cpu.regs.de(), 0x1234);
+        assert_eq!(cpu.regs.pc, 0x0103);
+        assert_eq!(cpu.regs.sp, 0xFFFE);
+        assert_eq!(mmu.read(0xFFFD), 0x01);
+        assert_eq!(cpu.cycles, 24 + 12 + 16 + 12 + 16);
+    }
+
+    #[test]
+    fn interrupts() {
+        let mut mmu = FlatMmu::new();
+        mmu.load(0x0100, &crate::asm!("ei\nnop\nnop"));
+        mmu.write(IE, 0x04);
+        mmu.write(IF, 0x04);
+        let mut cpu = Cpu::default();
+        cpu.step(&mut mmu); // ei
+        cpu.step(&mut mmu); // nop runs before the interrupt
+        assert_eq!(cpu.regs.pc, 0x0102);
+        assert_eq!(cpu.step(&mut mmu), 20);
+        assert_eq!(cpu.regs.pc, 0x0050);
+        assert_eq!(mmu.read(IF), 0x00);
+        assert!(!cpu.ime);
+    }
+
+    #[test]
+    fn halt_bug() {
+        // IME=0 with an interrupt pending: HALT falls through and the next byte is read twice
+        let mut mmu = FlatMmu::new();
+        mmu.load(0x0100, &crate::asm!("halt\ninc a"));
+        mmu.write(IE, 0x01);
+        mmu.write(IF, 0x01);
+        let mut cpu = Cpu::default();
+        cpu.regs.a = 0;
+        for _ in 0..3 {
+            cpu.step(&mut mmu);
+        }
+        assert!(!cpu.halted);
+        assert_eq!(cpu.regs.a, 2);
+        assert_eq!(cpu.regs.pc, 0x0102);
+    }
+}
@@ -1,10 +1,13 @@
 //===== REGISTERS =====
+use serde::{Deserialize, Serialize};
+use crate::model::Model;
+
 pub enum GbSpeed{
     Single = 1,
     Double = 2,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Registers{
     pub a: u8,
     pub b: u8,
@@ -26,31 +29,53 @@ pub enum CpuFlag {
     C = 0x10, // carry flag from the most significant bit (bit 7)
 }
 
+impl Default for Registers {
+    fn default() -> Self { Registers::new() }
+}
+
 impl Registers {
     pub fn new() -> Registers{
+        Registers::for_model(Model::DMG)
+    }
+
+    // values left by the boot ROM of each model, games read A (and B) to detect the hardware
+    pub fn for_model(model: Model) -> Registers{
         use CpuFlag::*;
+        let (a, f, b, c, d, e, h, l) = match model {
+            Model::DMG0 => (0x01, 0, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03),
+            // H and C are only clear when the header checksum is 0x00
+            Model::DMG => (0x01, Z as u8 | H as u8 | C as u8, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            Model::MGB => (0xFF, Z as u8 | H as u8 | C as u8, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            Model::SGB => (0x01, 0, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            Model::SGB2 => (0xFF, 0, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            Model::CGB => (0x11, Z as u8, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D),
+            // the AGB boot ROM ends with an extra INC B, which also clears Z
+            Model::AGB => (0x11, 0, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D),
+        };
         Registers{
-                a: 0x01,
-                f: C as u8 | H as u8 | Z as u8,
-                b: 0x00,
-                c: 0x13,
-                d: 0x00,
-                e: 0xD8,
-                h: 0x01,
-                l: 0x4D,
+                a, f, b, c, d, e, h, l,
                 pc: 0x0100, // program counter starts after bios 
                 sp: 0xFFFE,
         }
     }
+
+    // power-on state when a boot ROM is mapped: everything is zero and the boot ROM sets the rest
+    pub fn power_on() -> Registers{
+        Registers{
+                a: 0, f: 0, b: 0, c: 0, d: 0, e: 0, h: 0, l: 0,
+                pc: 0x0000,
+                sp: 0x0000,
+        }
+    }
     // get 
     pub fn af(&self) -> u16 {
         ((self.a as u16) << 8) | ((self.f & 0xF0) as u16)
     }
     pub fn bc(&self) -> u16 {
-        ((self.b as u16) << 8) | ((self.c as u16))
+        ((self.b as u16) << 8) | (self.c as u16)
     }
     pub fn de(&self) -> u16 {
-        ((self.d as u16) << 8) | ((self.e as u16))
+        ((self.d as u16) << 8) | (self.e as u16)
     }
     pub fn hl(&self) -> u16 {
         ((self.h as u16) << 8) | (self.l as u16)
@@ -145,16 +170,30 @@ mod test {
         assert_eq!(reg.f & 0x0f, 0);
 
         reg.setf(0x00);
-        for i in 0..4{
-            let mask = flags[i];
-            assert_eq!(reg.getflag(&mask), false);
+        for mask in flags{
+            assert!(!reg.getflag(&mask));
             reg.flag(&mask, true);
-            assert_eq!(reg.getflag(&mask), true);
+            assert!(reg.getflag(&mask));
             reg.flag(&mask, false);
-            assert_eq!(reg.getflag(&mask), false); 
+            assert!(!reg.getflag(&mask));
         }
     }
 
+    #[test]
+    fn post_boot_values(){
+        use crate::model::Model;
+        let reg = Registers::for_model(Model::CGB);
+        assert_eq!((reg.a, reg.b), (0x11, 0x00));
+        assert!(reg.getflag(&Z));
+        // AGB is told apart from CGB by B and by Z
+        let reg = Registers::for_model(Model::AGB);
+        assert_eq!((reg.a, reg.b), (0x11, 0x01));
+        assert!(!reg.getflag(&Z));
+        assert_eq!(Registers::for_model(Model::MGB).a, 0xFF);
+        assert_eq!(Registers::new().af(), 0x01B0);
+        assert_eq!(Registers::power_on().pc, 0x0000);
+    }
+
     #[test]
     fn hl_special(){
         let mut reg = Registers::new();
@@ -0,0 +1,265 @@
+//===== REWIND =====
+// Snapshots of the machine every `interval` frames, in a ring that stays under a byte budget,
+// with the buttons held in every frame. The newest snapshot is kept whole and every older one
+// as the XOR against its newer neighbour, run-length encoded, so a snapshot costs about as much
+// as what changed since. Going back restores the nearest snapshot at or before the target and
+// runs the machine forward from there with the same input, which lands on the same instruction
+// boundary as the original run.
+use std::collections::VecDeque;
+use crate::machine::GameBoy;
+use crate::processor::FRAME_CYCLES;
+use crate::savestate::SaveState;
+
+pub const DEFAULT_INTERVAL: u64 = 10;
+pub const DEFAULT_BUDGET: usize = 32 << 20;
+
+fn push_len(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_len(data: &[u8], pos: &mut usize) -> usize {
+    let mut n = 0;
+    let mut shift = 0;
+    while let Some(&byte) = data.get(*pos) {
+        *pos += 1;
+        n |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    n
+}
+
+// `old` as a delta against `new`: its length, then (zero run, literal count, literals) of old ^ new
+pub fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let xor: Vec<u8> = old.iter().enumerate().map(|(i, &b)| b ^ new.get(i).copied().unwrap_or(0)).collect();
+    let mut out = Vec::new();
+    push_len(&mut out, old.len());
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|&&b| b == 0).count();
+        let literal = xor[i + zeros..].iter().take_while(|&&b| b != 0).count();
+        push_len(&mut out, zeros);
+        push_len(&mut out, literal);
+        out.extend_from_slice(&xor[i + zeros..i + zeros + literal]);
+        i += zeros + literal;
+    }
+    out
+}
+
+pub fn decode_delta(delta: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_len(delta, &mut pos);
+    let mut old: Vec<u8> = (0..len).map(|i| new.get(i).copied().unwrap_or(0)).collect();
+    let mut i = 0;
+    while pos < delta.len() && i < len {
+        i += read_len(delta, &mut pos);
+        let literal = read_len(delta, &mut pos);
+        for (byte, &x) in old[i..i + literal].iter_mut().zip(&delta[pos..pos + literal]) {
+            *byte ^= x;
+        }
+        pos += literal;
+        i += literal;
+    }
+    old
+}
+
+// a snapshot and the buttons held in each frame from its own up to the next snapshot
+struct Snapshot {
+    cycles: u64,
+    state: Vec<u8>, // whole for the newest, a delta against the next one for the others
+    inputs: Vec<u8>,
+}
+
+impl Snapshot {
+    fn frame(&self) -> u64 {
+        self.cycles / FRAME_CYCLES
+    }
+
+    fn size(&self) -> usize {
+        self.state.len() + self.inputs.len()
+    }
+}
+
+pub struct Rewind {
+    interval: u64,
+    budget: usize,
+    latest: Option<Snapshot>,
+    older: VecDeque<Snapshot>, // oldest first
+}
+
+impl Default for Rewind {
+    fn default() -> Self { Rewind::new(DEFAULT_INTERVAL, DEFAULT_BUDGET) }
+}
+
+impl Rewind {
+    pub fn new(interval: u64, budget: usize) -> Rewind {
+        Rewind { interval: interval.max(1), budget, latest: None, older: VecDeque::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.older.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    // bytes held by all snapshots
+    pub fn size(&self) -> usize {
+        self.latest.as_ref().map_or(0, Snapshot::size) + self.older.iter().map(Snapshot::size).sum::<usize>()
+    }
+
+    // first frame that can still be reached
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.older.front().or(self.latest.as_ref()).map(|snapshot| snapshot.cycles.div_ceil(FRAME_CYCLES))
+    }
+
+    // call at the start of every frame, once the frame's buttons are set: notes them down and
+    // takes a snapshot when `interval` frames have passed since the last one
+    pub fn record(&mut self, gb: &GameBoy) {
+        let frame = gb.cpu.cycles / FRAME_CYCLES;
+        let buttons = gb.bus.buttons();
+        if let Some(latest) = &mut self.latest && frame < latest.frame() + self.interval {
+            let held = latest.inputs.last().copied().unwrap_or(buttons);
+            latest.inputs.resize(frame.saturating_sub(latest.frame()) as usize, held);
+            latest.inputs.push(buttons);
+            return;
+        }
+        let state = bincode::serialize(&gb.save_state()).expect("save states always serialize");
+        let snapshot = Snapshot { cycles: gb.cpu.cycles, state, inputs: vec![buttons] };
+        if let Some(mut previous) = self.latest.replace(snapshot) {
+            previous.state = encode_delta(&previous.state, &self.latest.as_ref().unwrap().state);
+            self.older.push_back(previous);
+        }
+        while self.size() > self.budget && self.older.pop_front().is_some() {}
+    }
+
+    // restores the machine at the start of `frame` and returns false when that is further back
+    // than the oldest snapshot. The frames after the snapshot run again through GameBoy::step
+    // with the buttons recorded for them. Snapshots after it are dropped, the run continues
+    // from there.
+    pub fn seek(&mut self, frame: u64, gb: &mut GameBoy) -> bool {
+        let target = frame * FRAME_CYCLES;
+        let Some(mut latest) = self.latest.take() else { return false };
+        while latest.cycles > target {
+            let Some(mut older) = self.older.pop_back() else {
+                self.latest = Some(latest);
+                return false;
+            };
+            older.state = decode_delta(&older.state, &latest.state);
+            latest = older;
+        }
+        let snapshot: SaveState = bincode::deserialize(&latest.state).expect("snapshots are taken by record");
+        gb.load_state(&snapshot).expect("snapshots are taken on this machine");
+        while gb.cpu.cycles < target {
+            let now = gb.cpu.cycles / FRAME_CYCLES;
+            if let Some(&buttons) = latest.inputs.get((now - latest.frame()) as usize) {
+                gb.bus.set_buttons(buttons);
+            }
+            gb.run_until(((now + 1) * FRAME_CYCLES).min(target), |_| false);
+        }
+        latest.inputs.truncate((frame - latest.frame()) as usize);
+        self.latest = Some(latest);
+        true
+    }
+
+    // goes back `frames` frames from the one running now
+    pub fn step_back(&mut self, frames: u64, gb: &mut GameBoy) -> bool {
+        let current = gb.cpu.cycles / FRAME_CYCLES;
+        frames <= current && self.seek(current - frames, gb)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::joypad;
+    use crate::mmu::Mmu;
+    use crate::model::Model;
+    use crate::processor::Cpu;
+
+    // counts frames in WRAM, sums up the buttons read from P1 and scribbles over a few more bytes
+    fn machine() -> GameBoy {
+        let rom = crate::asm!("SECTION \"main\", ROM0[$100]\nld a, $20\nldh [$00], a\nld hl, $C000\n\
+            .loop: inc [hl]\nldh a, [$00]\nld [$D000], a\nadd a, b\nld b, a\njr .loop");
+        GameBoy::new(rom, Model::DMG)
+    }
+
+    // sets the buttons of the frame about to run, records and runs it
+    fn run_frame(gb: &mut GameBoy, rewind: &mut Rewind) {
+        let frame = gb.cpu.cycles / FRAME_CYCLES;
+        gb.bus.set_buttons(if frame % 3 == 1 { joypad::RIGHT | joypad::UP } else { 0 });
+        rewind.record(gb);
+        gb.run_frame();
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let old = vec![1, 2, 3, 0, 0, 0, 7, 8];
+        let new = vec![1, 2, 4, 0, 0, 0, 7, 9, 10];
+        let delta = encode_delta(&old, &new);
+        assert_eq!(decode_delta(&delta, &new), old);
+        assert_eq!(decode_delta(&encode_delta(&new, &new), &new), new);
+        let big = vec![0x55; 0x10000];
+        assert!(encode_delta(&big, &big).len() < 8);
+    }
+
+    #[test]
+    fn rewinds_frame_exactly() {
+        let mut gb = machine();
+        let whole = bincode::serialized_size(&gb.save_state()).unwrap() as usize;
+        let mut rewind = Rewind::new(4, DEFAULT_BUDGET);
+        let mut history: Vec<(Cpu, Vec<u8>)> = Vec::new();
+        for _ in 0..30 {
+            history.push((gb.cpu, gb.memory().mem.clone()));
+            run_frame(&mut gb, &mut rewind);
+        }
+        history.push((gb.cpu, gb.memory().mem.clone()));
+        assert_eq!(rewind.len(), 8);
+        // deltas of a few changed bytes, not a whole state each
+        assert!(rewind.size() < whole + 8 * 64);
+        // frame 1 ran with Right and Up held
+        assert_eq!((history[1].1[0xD000] & 0x0F, history[2].1[0xD000] & 0x0F), (0x0F, 0x0A));
+
+        for frame in [27, 13, 13, 2, 0] {
+            assert!(rewind.seek(frame, &mut gb));
+            assert_eq!(gb.cpu, history[frame as usize].0);
+            assert!(gb.memory().mem == history[frame as usize].1);
+        }
+        assert!(!rewind.step_back(1, &mut gb));
+
+        // history after the target is dropped, recording continues from there
+        let mut gb = machine();
+        let mut rewind = Rewind::new(1, DEFAULT_BUDGET);
+        for _ in 0..6 {
+            run_frame(&mut gb, &mut rewind);
+        }
+        assert!(rewind.step_back(2, &mut gb));
+        assert_eq!(gb.cpu, history[4].0);
+        assert!(rewind.len() < 6);
+        assert_eq!(gb.bus.read(0xD000), history[4].1[0xD000]);
+        run_frame(&mut gb, &mut rewind);
+        assert_eq!(gb.cpu, history[5].0);
+    }
+
+    #[test]
+    fn budget_drops_the_oldest() {
+        let mut gb = machine();
+        let budget = bincode::serialized_size(&gb.save_state()).unwrap() as usize + 0x400;
+        let mut rewind = Rewind::new(1, budget);
+        for _ in 0..200 {
+            run_frame(&mut gb, &mut rewind);
+        }
+        assert!(rewind.size() <= budget);
+        let oldest = rewind.oldest_frame().unwrap();
+        assert!(oldest > 1 && oldest < 200);
+        assert!(!rewind.seek(oldest - 1, &mut gb));
+        assert!(rewind.seek(oldest, &mut gb));
+    }
+}
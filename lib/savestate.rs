@@ -0,0 +1,315 @@
+//===== SAVE STATES =====
+// Layout: "GBSS", version (u16 LE), CRC-32 of the ROM (u32 LE), then the bincode body of that
+// version. A state only loads with the ROM it was taken on. Bodies of older versions are kept
+// as frozen structs and migrated forward one version at a time, so old files keep loading.
+// The state holds everything in a machine::GameBoy that changes while running: the whole CPU
+// (registers, IME, the EI delay, HALT and the HALT bug, cycle count), the 64KB bus, whether the
+// boot ROM is still mapped, the buttons held, the cheats, the APU's channels, the SGB, the CGB's
+// VRAM bank 1 and palette RAM, and the LCD's screen and window line, so a state taken mid-frame
+// continues exactly like the original run. Version 1 held only the CPU and the bus, version 2
+// had no CGB VRAM. The CPU is stored as a frozen CpuV1 rather than the live Cpu, so a field
+// added to Cpu needs a new CPU layout and version here instead of breaking old files. Mapper,
+// timer and DMA go in here as new versions once they exist.
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::apu::{Apu, ApuState};
+use crate::cheats::Cheat;
+use crate::mmu::FlatMmu;
+use crate::model::Model;
+use crate::processor::Cpu;
+use crate::registers::Registers;
+use crate::sgb::{Sgb, FRAME_H, FRAME_W, SCREEN_H, SCREEN_W};
+use crate::vram::{CgbVram, VRAM_SIZE};
+
+pub const MAGIC: [u8; 4] = *b"GBSS";
+pub const VERSION: u16 = 3;
+const HEADER_SIZE: usize = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum SaveStateError {
+    BadMagic,
+    Version(u16), // newer than this build
+    RomMismatch { expected: u32, actual: u32 },
+    Corrupt(String),
+    ModelMismatch { expected: Model, actual: Model },
+    NoBootRom, // taken while a boot ROM ran, loaded on a machine without one
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a save state"),
+            SaveStateError::Version(v) => write!(f, "save state version {} is newer than {}", v, VERSION),
+            SaveStateError::RomMismatch { expected, actual } =>
+                write!(f, "save state was taken on ROM {:08X}, this ROM is {:08X}", expected, actual),
+            SaveStateError::Corrupt(e) => write!(f, "corrupt save state: {}", e),
+            SaveStateError::ModelMismatch { expected, actual } =>
+                write!(f, "save state was taken on {:?}, this is {:?}", expected, actual),
+            SaveStateError::NoBootRom => write!(f, "save state was taken while the boot ROM ran, there is none now"),
+        }
+    }
+}
+
+// CRC-32 (IEEE), as printed by most ROM tools
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg()))
+    })
+}
+
+// the CPU as versions 1 to 3 store it, frozen
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CpuV1 {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub f: u8,
+    pub pc: u16,
+    pub sp: u16,
+    pub ime: bool,
+    pub ei_delay: bool,
+    pub halted: bool,
+    pub halt_bug: bool,
+    pub locked: bool,
+    pub cycles: u64,
+}
+
+impl From<&Cpu> for CpuV1 {
+    fn from(cpu: &Cpu) -> CpuV1 {
+        let r = &cpu.regs;
+        CpuV1 {
+            a: r.a, b: r.b, c: r.c, d: r.d, e: r.e, h: r.h, l: r.l, f: r.af() as u8, pc: r.pc, sp: r.sp,
+            ime: cpu.ime, ei_delay: cpu.ei_delay, halted: cpu.halted, halt_bug: cpu.halt_bug, locked: cpu.locked, cycles: cpu.cycles,
+        }
+    }
+}
+
+impl From<CpuV1> for Cpu {
+    fn from(v1: CpuV1) -> Cpu {
+        let mut regs = Registers::new();
+        regs.setaf((v1.a as u16) << 8 | v1.f as u16);
+        (regs.b, regs.c, regs.d, regs.e, regs.h, regs.l, regs.pc, regs.sp) = (v1.b, v1.c, v1.d, v1.e, v1.h, v1.l, v1.pc, v1.sp);
+        let mut cpu = Cpu::new(regs);
+        (cpu.ime, cpu.ei_delay, cpu.halted, cpu.halt_bug, cpu.locked, cpu.cycles) = (v1.ime, v1.ei_delay, v1.halted, v1.halt_bug, v1.locked, v1.cycles);
+        cpu
+    }
+}
+
+// taken and loaded by GameBoy::save_state and GameBoy::load_state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveState {
+    pub model: Option<Model>, // None in states from version 1, which load on any model
+    pub cpu: CpuV1,
+    pub memory: Vec<u8>,
+    pub boot_rom_mapped: bool,
+    pub buttons: u8,
+    pub cheats: Vec<Cheat>,
+    pub apu: ApuState,
+    pub sgb: Option<Sgb>,
+    pub cgb: Option<CgbVram>, // on the CGB models, None in states before version 3
+    pub screen: Vec<u8>,    // SCREEN_W * SCREEN_H colour numbers
+    pub sgb_frame: Vec<u32>, // FRAME_W * FRAME_H with an SGB, else empty
+    pub window_line: u8,
+}
+
+// version 1, frozen
+#[derive(Deserialize)]
+struct SaveStateV1 {
+    cpu: CpuV1,
+    memory: Vec<u8>,
+}
+
+// version 2, frozen
+#[derive(Deserialize)]
+struct SaveStateV2 {
+    model: Option<Model>,
+    cpu: CpuV1,
+    memory: Vec<u8>,
+    boot_rom_mapped: bool,
+    buttons: u8,
+    cheats: Vec<Cheat>,
+    apu: ApuState,
+    sgb: Option<Sgb>,
+    screen: Vec<u8>,
+    sgb_frame: Vec<u32>,
+    window_line: u8,
+}
+
+impl From<SaveStateV1> for SaveStateV2 {
+    // the channels come from the sound registers, like after a boot ROM; the screen is redrawn
+    // from the next line on
+    fn from(v1: SaveStateV1) -> SaveStateV2 {
+        let apu = Apu::new(FlatMmu { mem: v1.memory.clone(), read_only: 0 }).state();
+        SaveStateV2 {
+            model: None, cpu: v1.cpu, memory: v1.memory, boot_rom_mapped: false, buttons: 0, cheats: Vec::new(), apu,
+            sgb: None, screen: vec![0; SCREEN_W * SCREEN_H], sgb_frame: Vec::new(), window_line: 0,
+        }
+    }
+}
+
+impl From<SaveStateV2> for SaveState {
+    // VRAM bank 1 and the palettes start cleared, as at power on
+    fn from(v2: SaveStateV2) -> SaveState {
+        SaveState {
+            model: v2.model, cpu: v2.cpu, memory: v2.memory, boot_rom_mapped: v2.boot_rom_mapped, buttons: v2.buttons,
+            cheats: v2.cheats, apu: v2.apu, sgb: v2.sgb, cgb: None, screen: v2.screen, sgb_frame: v2.sgb_frame,
+            window_line: v2.window_line,
+        }
+    }
+}
+
+impl SaveState {
+    // CRC-32 of the whole state, for checking that two runs ended up in the same place
+    pub fn hash(&self) -> u32 {
+        crc32(&bincode::serialize(self).expect("save states always serialize"))
+    }
+
+    pub fn to_bytes(&self, rom: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + self.memory.len() + self.screen.len() + 4 * self.sgb_frame.len() + 256);
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&crc32(rom).to_le_bytes());
+        bincode::serialize_into(&mut out, self).expect("save states always serialize");
+        out
+    }
+
+    pub fn from_bytes(data: &[u8], rom: &[u8]) -> Result<SaveState, SaveStateError> {
+        if data.len() < HEADER_SIZE || data[..4] != MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        let expected = u32::from_le_bytes([data[6], data[7], data[8], data[9]]);
+        let actual = crc32(rom);
+        if expected != actual {
+            return Err(SaveStateError::RomMismatch { expected, actual });
+        }
+        let body = &data[HEADER_SIZE..];
+        let corrupt = |e: bincode::Error| SaveStateError::Corrupt(e.to_string());
+        // older versions: deserialize the frozen body and migrate it up to the current one
+        let state: SaveState = match version {
+            1 => SaveStateV2::from(bincode::deserialize::<SaveStateV1>(body).map_err(corrupt)?).into(),
+            2 => bincode::deserialize::<SaveStateV2>(body).map_err(corrupt)?.into(),
+            VERSION => bincode::deserialize(body).map_err(corrupt)?,
+            v => return Err(SaveStateError::Version(v)),
+        };
+        if state.memory.len() != 0x10000 {
+            return Err(SaveStateError::Corrupt(format!("{} bytes of memory", state.memory.len())));
+        }
+        let frame = if state.sgb.is_some() { FRAME_W * FRAME_H } else { 0 };
+        if state.screen.len() != SCREEN_W * SCREEN_H || state.sgb_frame.len() != frame {
+            return Err(SaveStateError::Corrupt(format!("{} pixels of screen, {} of SGB frame", state.screen.len(), state.sgb_frame.len())));
+        }
+        if let Some(cgb) = &state.cgb
+            && (cgb.bank1.len() != VRAM_SIZE || cgb.bg_palettes.len() != 64 || cgb.obj_palettes.len() != 64) {
+            return Err(SaveStateError::Corrupt(format!("{} bytes of VRAM bank 1", cgb.bank1.len())));
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::joypad;
+    use crate::machine::GameBoy;
+    use crate::mmu::Mmu;
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    // plays a note, counts VBlanks and draws the count into the tile map
+    fn rom() -> Vec<u8> {
+        crate::asm!("SECTION \"main\", ROM0[$100]\n\
+            ld a, $F0\nldh [$12], a\nld a, $87\nldh [$14], a\nld a, $01\nldh [$FF], a\nei\nld hl, $C000\n\
+            .loop: halt\ninc [hl]\nld a, [hl]\nld [$9800], a\nadd a, b\nld b, a\njr .loop\n\
+            SECTION \"vblank\", ROM0[$40]\nreti")
+    }
+
+    // a state taken in the middle of a frame continues exactly like the run itself
+    #[test]
+    fn round_trip_mid_frame() {
+        let rom = rom();
+        let mut gb = GameBoy::new(rom.clone(), Model::SGB);
+        gb.cheats().add(Cheat::new("01AA00C1", "").unwrap());
+        gb.bus.set_buttons(joypad::A);
+        gb.run_frame();
+        gb.run_until(gb.cpu.cycles + 30_000, |_| false);
+        let data = gb.save_state().to_bytes(&rom);
+
+        let run = |gb: &mut GameBoy| {
+            (0..5).map(|_| {
+                gb.run_frame();
+                (gb.cpu, gb.memory().mem.clone(), gb.screen.clone(), gb.frame().pixels)
+            }).collect::<Vec<_>>()
+        };
+        let original = run(&mut gb);
+        let mut restored = GameBoy::new(rom.clone(), Model::SGB);
+        restored.load_state(&SaveState::from_bytes(&data, &rom).unwrap()).unwrap();
+        assert_eq!(restored.bus.buttons(), joypad::A);
+        assert_eq!(restored.cheats().list().len(), 1);
+        assert!(run(&mut restored) == original);
+        assert_eq!(restored.save_state(), gb.save_state());
+        assert_eq!(restored.bus.read(0xC100), 0xAA);
+    }
+
+    #[test]
+    fn rejects_other_roms_models_and_versions() {
+        let gb = GameBoy::new(b"rom a".to_vec(), Model::DMG);
+        let state = gb.save_state();
+        let mut data = state.to_bytes(b"rom a");
+        assert_eq!(SaveState::from_bytes(&data, b"rom a"), Ok(state.clone()));
+        assert!(matches!(SaveState::from_bytes(&data, b"rom b"), Err(SaveStateError::RomMismatch { .. })));
+        assert_eq!(SaveState::from_bytes(b"GBS", b"rom a"), Err(SaveStateError::BadMagic));
+        let mut cgb = GameBoy::new(b"rom a".to_vec(), Model::CGB);
+        assert_eq!(cgb.load_state(&state), Err(SaveStateError::ModelMismatch { expected: Model::DMG, actual: Model::CGB }));
+        let booting = GameBoy::with_boot_rom(b"rom a".to_vec(), Model::DMG, Some(vec![0; 0x100])).unwrap().save_state();
+        assert_eq!(GameBoy::new(b"rom a".to_vec(), Model::DMG).load_state(&booting), Err(SaveStateError::NoBootRom));
+        data[4] = 9;
+        assert_eq!(SaveState::from_bytes(&data, b"rom a"), Err(SaveStateError::Version(9)));
+        data[4] = VERSION as u8;
+        data.truncate(100);
+        assert!(matches!(SaveState::from_bytes(&data, b"rom a"), Err(SaveStateError::Corrupt(_))));
+    }
+
+    // the CPU's bytes stay where versions 1 to 3 put them whatever happens to Cpu
+    #[test]
+    fn cpu_layout_is_frozen() {
+        let mut cpu = Cpu::new(Registers::power_on());
+        (cpu.ime, cpu.halt_bug, cpu.cycles) = (true, true, 0x0102);
+        let bytes = bincode::serialize(&CpuV1::from(&cpu)).unwrap();
+        let r = Registers::power_on();
+        let mut expected = vec![r.a, r.b, r.c, r.d, r.e, r.h, r.l, r.af() as u8];
+        expected.extend(r.pc.to_le_bytes());
+        expected.extend(r.sp.to_le_bytes());
+        expected.extend([1, 0, 0, 1, 0, 0x02, 0x01, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bytes, expected);
+        assert_eq!(Cpu::from(CpuV1::from(&cpu)), cpu);
+    }
+
+    // version 1 held the CPU and the bus, the channels are picked up from the registers
+    #[test]
+    fn migrates_version_1() {
+        let rom = rom();
+        let mut gb = GameBoy::new(rom.clone(), Model::MGB);
+        gb.run_frame();
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&crc32(&rom).to_le_bytes());
+        bincode::serialize_into(&mut data, &(CpuV1::from(&gb.cpu), gb.memory().mem.clone())).unwrap();
+
+        let state = SaveState::from_bytes(&data, &rom).unwrap();
+        assert_eq!(state.model, None);
+        assert!(state.apu.channels[0].dac);
+        let mut restored = GameBoy::new(rom, Model::MGB);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.cpu, gb.cpu);
+        assert!(restored.memory().mem == gb.memory().mem);
+    }
+}
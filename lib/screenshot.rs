@@ -0,0 +1,290 @@
+//===== SCREENSHOTS =====
+// Reference image tests for the image-based PPU suites (dmg-acid2, cgb-acid2, mealybug): run
+// a ROM for some frames, compare the screen with a reference PNG and write a diff image when
+// they differ. Pixels are 0xRRGGBB like the SGB output. References taken on other palettes
+// (the acid2 ones use plain greys) are matched through a colour mapping applied first.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use crate::sgb::{SCREEN_H, SCREEN_W};
+
+// diff image: matching pixels dimmed to grey, mismatches in red
+const DIFF_MISMATCH: u32 = 0xFF0000;
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    Io(io::Error),
+    Png(String),
+    Size { actual: (usize, usize), reference: (usize, usize) },
+    Mismatch { pixels: usize, first: (usize, usize) },
+}
+
+impl fmt::Display for ScreenshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScreenshotError::Io(e) => write!(f, "{}", e),
+            ScreenshotError::Png(e) => write!(f, "bad PNG: {}", e),
+            ScreenshotError::Size { actual, reference } =>
+                write!(f, "frame is {}x{}, reference is {}x{}", actual.0, actual.1, reference.0, reference.1),
+            ScreenshotError::Mismatch { pixels, first } =>
+                write!(f, "{} pixels differ, the first at ({}, {})", pixels, first.0, first.1),
+        }
+    }
+}
+
+impl From<io::Error> for ScreenshotError {
+    fn from(e: io::Error) -> Self { ScreenshotError::Io(e) }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<u32>) -> Image {
+        assert_eq!(pixels.len(), width * height);
+        Image { width, height, pixels }
+    }
+
+    // 160x144 DMG colour numbers through a palette, e.g. Model::dmg_palette
+    pub fn from_shades(screen: &[u8], palette: [u32; 4]) -> Image {
+        Image::new(SCREEN_W, SCREEN_H, screen.iter().map(|&c| palette[(c & 0x03) as usize]).collect())
+    }
+
+    // any 8 or 16-bit grey, RGB or indexed PNG, alpha is dropped
+    pub fn load(path: &Path) -> Result<Image, ScreenshotError> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(|e| ScreenshotError::Png(e.to_string()))?;
+        let mut buf = vec![0; reader.output_buffer_size().ok_or(ScreenshotError::Png("image too large".to_string()))?];
+        let info = reader.next_frame(&mut buf).map_err(|e| ScreenshotError::Png(e.to_string()))?;
+        let rgb = |r: u8, g: u8, b: u8| (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        let data = &buf[..info.buffer_size()];
+        let pixels = match info.color_type {
+            png::ColorType::Grayscale => data.iter().map(|&v| rgb(v, v, v)).collect(),
+            png::ColorType::GrayscaleAlpha => data.chunks(2).map(|p| rgb(p[0], p[0], p[0])).collect(),
+            png::ColorType::Rgb => data.chunks(3).map(|p| rgb(p[0], p[1], p[2])).collect(),
+            png::ColorType::Rgba => data.chunks(4).map(|p| rgb(p[0], p[1], p[2])).collect(),
+            png::ColorType::Indexed => return Err(ScreenshotError::Png("palette was not expanded".to_string())),
+        };
+        Ok(Image::new(info.width as usize, info.height as usize, pixels))
+    }
+
+    // R, G, B bytes row by row
+    pub fn rgb(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|&p| [(p >> 16) as u8, (p >> 8) as u8, p as u8]).collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ScreenshotError> {
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let data = self.rgb();
+        let mut writer = encoder.write_header().map_err(|e| ScreenshotError::Png(e.to_string()))?;
+        writer.write_image_data(&data).map_err(|e| ScreenshotError::Png(e.to_string()))?;
+        writer.finish().map_err(|e| ScreenshotError::Png(e.to_string()))
+    }
+
+    // replaces every `from` colour with its `to`, other colours are kept
+    pub fn map_colors(&self, mapping: &[(u32, u32)]) -> Image {
+        let map = |p: u32| mapping.iter().find(|(from, _)| *from == p).map_or(p, |(_, to)| *to);
+        Image::new(self.width, self.height, self.pixels.iter().map(|&p| map(p)).collect())
+    }
+}
+
+// colour n of one palette to colour n of the other
+pub fn palette_mapping(from: [u32; 4], to: [u32; 4]) -> Vec<(u32, u32)> {
+    from.into_iter().zip(to).collect()
+}
+
+pub struct Diff {
+    pub pixels: usize,
+    pub first: (usize, usize),
+    pub image: Image,
+}
+
+// None when the images match
+pub fn diff(actual: &Image, reference: &Image) -> Result<Option<Diff>, ScreenshotError> {
+    if (actual.width, actual.height) != (reference.width, reference.height) {
+        return Err(ScreenshotError::Size { actual: (actual.width, actual.height), reference: (reference.width, reference.height) });
+    }
+    let mut first = None;
+    let mut count = 0;
+    let pixels = actual.pixels.iter().zip(&reference.pixels).enumerate()
+        .map(|(i, (&a, &r))| {
+            if a == r {
+                let luma = ((a >> 16 & 0xFF) * 3 + (a >> 8 & 0xFF) * 6 + (a & 0xFF)) / 10;
+                let grey = 0x80 + luma / 2;
+                return grey << 16 | grey << 8 | grey;
+            }
+            count += 1;
+            first.get_or_insert((i % actual.width, i / actual.width));
+            DIFF_MISMATCH
+        })
+        .collect();
+    Ok(first.map(|first| Diff { pixels: count, first, image: Image::new(actual.width, actual.height, pixels) }))
+}
+
+// compares `actual` (after `mapping`) with the reference PNG and writes the diff image to
+// `diff_path` when they differ
+pub fn compare(actual: &Image, reference: &Path, mapping: &[(u32, u32)], diff_path: &Path) -> Result<(), ScreenshotError> {
+    let reference = Image::load(reference)?;
+    match diff(&actual.map_colors(mapping), &reference)? {
+        None => Ok(()),
+        Some(diff) => {
+            diff.image.save(diff_path)?;
+            Err(ScreenshotError::Mismatch { pixels: diff.pixels, first: diff.first })
+        }
+    }
+}
+
+// anything that runs a frame at a time and shows it
+pub trait Screen {
+    fn run_frame(&mut self);
+    fn screen(&self) -> Image;
+}
+
+// runs `frames` frames and compares the last one with the reference
+pub fn check<S: Screen>(machine: &mut S, frames: usize, reference: &Path, mapping: &[(u32, u32)], diff_path: &Path) -> Result<(), ScreenshotError> {
+    for _ in 0..frames {
+        machine.run_frame();
+    }
+    compare(&machine.screen(), reference, mapping, diff_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use crate::cartridge::Header;
+    use crate::machine::GameBoy;
+    use crate::mmu::Mmu;
+    use crate::model::Model;
+    use crate::processor::FRAME_CYCLES;
+
+    const LD_B_B: u8 = 0x40;
+    const GREYS: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+
+    // a ramp of the four shades, one per column
+    struct Ramp {
+        frames: usize,
+    }
+
+    impl Screen for Ramp {
+        fn run_frame(&mut self) {
+            self.frames += 1;
+        }
+        fn screen(&self) -> Image {
+            let shades: Vec<u8> = (0..SCREEN_W * SCREEN_H).map(|i| ((i % SCREEN_W + self.frames) % 4) as u8).collect();
+            Image::from_shades(&shades, Model::DMG.dmg_palette())
+        }
+    }
+
+    #[test]
+    fn reference_with_palette_mapping() {
+        let dir = std::env::temp_dir().join(format!("gb-screenshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (reference, diff_path) = (dir.join("ramp.png"), dir.join("ramp-diff.png"));
+
+        // the reference is in greys, two frames in
+        let grey = Model::CGB.dmg_palette();
+        let expected = Ramp { frames: 2 }.screen().map_colors(&palette_mapping(Model::DMG.dmg_palette(), grey));
+        expected.save(&reference).unwrap();
+        assert_eq!(Image::load(&reference).unwrap(), expected);
+
+        let mapping = palette_mapping(Model::DMG.dmg_palette(), grey);
+        check(&mut Ramp { frames: 0 }, 2, &reference, &mapping, &diff_path).unwrap();
+        assert!(!diff_path.exists());
+
+        match check(&mut Ramp { frames: 0 }, 1, &reference, &mapping, &diff_path) {
+            Err(ScreenshotError::Mismatch { pixels, first }) => assert_eq!((pixels, first), (SCREEN_W * SCREEN_H, (0, 0))),
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+        let diff_image = Image::load(&diff_path).unwrap();
+        assert!(diff_image.pixels.iter().all(|&p| p == DIFF_MISMATCH));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    // runs `rom` on a GameBoy until it executes LD B,B, which the acid2 and mealybug ROMs do once
+    // the picture is set up, finishes the frame and compares it with a reference in greys. None
+    // when the header asks for what is not emulated.
+    fn run_reference(rom_path: &Path, model: Model, reference: &Path) -> Option<Result<(), ScreenshotError>> {
+        let rom = fs::read(rom_path).unwrap_or_else(|e| panic!("{}: {}", rom_path.display(), e));
+        if let Ok(header) = Header::parse(&rom)
+            && let Some(problem) = header.unsupported().into_iter().next()
+        {
+            eprintln!("{}: skipped, {}", rom_path.display(), problem);
+            return None;
+        }
+        let mut gb = GameBoy::new(rom, model);
+        gb.run_until(600 * FRAME_CYCLES, |gb| gb.bus.read(gb.cpu.regs.pc) == LD_B_B);
+        gb.run_frame();
+        let name = rom_path.file_stem().unwrap_or_default().to_string_lossy();
+        let diff_path = std::env::temp_dir().join(format!("{}-diff.png", name));
+        Some(compare(&gb.image(), reference, &palette_mapping(model.dmg_palette(), GREYS), &diff_path))
+    }
+
+    // ACID2_DIR holds dmg-acid2.gb with its reference as dmg-acid2.png, skipped when it is not
+    // set. cgb-acid2 is not covered: it is CGB only and the screen is not drawn in colour.
+    #[test]
+    fn acid2() {
+        let Ok(dir) = std::env::var("ACID2_DIR") else {
+            eprintln!("ACID2_DIR is not set, skipping dmg-acid2");
+            return;
+        };
+        let dir = Path::new(&dir);
+        match run_reference(&dir.join("dmg-acid2.gb"), Model::DMG, &dir.join("dmg-acid2.png")) {
+            Some(Ok(())) => {}
+            Some(Err(e)) => panic!("dmg-acid2.gb: {}", e),
+            None => panic!("dmg-acid2.gb was skipped, it needs nothing this machine lacks"),
+        }
+    }
+
+    // every *.gb under MEALYBUG_DIR against name.png beside it or expected/DMG-blob/name.png as in
+    // the release, skipped when it is not set
+    #[test]
+    fn mealybug() {
+        let Ok(dir) = std::env::var("MEALYBUG_DIR") else {
+            eprintln!("MEALYBUG_DIR is not set, skipping the mealybug tearoom tests");
+            return;
+        };
+        let dir = Path::new(&dir);
+        let mut roms: Vec<_> = fs::read_dir(dir).expect("MEALYBUG_DIR is not a directory")
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "gb"))
+            .collect();
+        roms.sort();
+
+        let (mut passed, mut failures) = (0, Vec::new());
+        for rom in &roms {
+            let png = rom.with_extension("png");
+            let png = png.file_name().expect("ROM paths have a file name");
+            let Some(reference) = [dir.join(png), dir.join("expected/DMG-blob").join(png)].into_iter().find(|p| p.exists()) else {
+                eprintln!("{}: skipped, no reference", rom.display());
+                continue;
+            };
+            match run_reference(rom, Model::DMG, &reference) {
+                Some(Ok(())) => passed += 1,
+                Some(Err(e)) => failures.push(format!("{}: {}", rom.display(), e)),
+                None => {}
+            }
+        }
+        eprintln!("{} ROMs, {} passed, {} failed", roms.len(), passed, failures.len());
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn diff_marks_mismatches() {
+        let reference = Image::new(2, 2, vec![0xFFFFFF, 0, 0, 0]);
+        let actual = Image::new(2, 2, vec![0xFFFFFF, 0, 0xFFFFFF, 0]);
+        let result = diff(&actual, &reference).unwrap().unwrap();
+        assert_eq!((result.pixels, result.first), (1, (0, 1)));
+        assert_eq!(result.image.pixels, vec![0xFFFFFF, 0x808080, DIFF_MISMATCH, 0x808080]);
+        assert!(diff(&reference, &reference).unwrap().is_none());
+        assert!(matches!(diff(&actual, &Image::new(1, 4, vec![0; 4])), Err(ScreenshotError::Size { .. })));
+    }
+}
@@ -0,0 +1,208 @@
+//===== RAM SEARCH =====
+// Finding where a game keeps a number: snapshot cartridge RAM, WRAM and HRAM, then keep the
+// addresses whose value compares as asked with the last snapshot (or with a given number), pass
+// after pass, until few are left. Values are bytes or 16-bit words in either byte order, a word
+// at addr covers addr and addr + 1. Found addresses can be frozen with GameShark cheats.
+use std::fmt;
+use std::ops::RangeInclusive;
+use crate::cheats::Cheat;
+use crate::mmu::Mmu;
+
+pub const REGIONS: [(&str, RangeInclusive<u16>); 3] = [
+    ("cartridge RAM", 0xA000..=0xBFFF),
+    ("WRAM", 0xC000..=0xDFFF),
+    ("HRAM", 0xFF80..=0xFFFE),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+    Byte,
+    WordLe,
+    WordBe,
+}
+
+impl Size {
+    pub fn bytes(self) -> u16 {
+        if self == Size::Byte { 1 } else { 2 }
+    }
+
+    pub fn mask(self) -> u16 {
+        if self == Size::Byte { 0xFF } else { 0xFFFF }
+    }
+
+    // the value at `addr` out of `read`
+    pub fn value(self, addr: u16, read: impl Fn(u16) -> u8) -> u16 {
+        let (a, b) = (read(addr) as u16, read(addr.wrapping_add(1)) as u16);
+        match self {
+            Size::Byte => a,
+            Size::WordLe => b << 8 | a,
+            Size::WordBe => a << 8 | b,
+        }
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Size::Byte => "8-bit",
+            Size::WordLe => "16-bit LE",
+            Size::WordBe => "16-bit BE",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal, // to the last snapshot
+    Changed,
+    Increased,
+    Decreased,
+    ChangedBy(i32), // new - old, wrapping at the value size
+    Value(u16),
+}
+
+impl Comparison {
+    fn holds(self, old: u16, new: u16, size: Size) -> bool {
+        match self {
+            Comparison::Equal => new == old,
+            Comparison::Changed => new != old,
+            Comparison::Increased => new > old,
+            Comparison::Decreased => new < old,
+            Comparison::ChangedBy(delta) => new == (old as i32).wrapping_add(delta) as u16 & size.mask(),
+            Comparison::Value(value) => new == value & size.mask(),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Comparison::Equal => write!(f, "equal"),
+            Comparison::Changed => write!(f, "changed"),
+            Comparison::Increased => write!(f, "increased"),
+            Comparison::Decreased => write!(f, "decreased"),
+            Comparison::ChangedBy(delta) => write!(f, "changed by {}", delta),
+            Comparison::Value(value) => write!(f, "= {}", value),
+        }
+    }
+}
+
+pub struct Search {
+    pub size: Size,
+    pub passes: usize,
+    snapshot: Vec<u8>, // all 64KB, only the regions are filled
+    candidates: Vec<u16>,
+}
+
+impl Search {
+    // every address of the regions is a candidate at first
+    pub fn new<T: Mmu>(mmu: &T, size: Size) -> Search {
+        let mut candidates = Vec::new();
+        for (_, range) in &REGIONS {
+            let last = range.end() + 1 - size.bytes();
+            candidates.extend(*range.start()..=last);
+        }
+        let mut search = Search { size, passes: 0, snapshot: vec![0; 0x10000], candidates };
+        search.snapshot(mmu);
+        search
+    }
+
+    fn snapshot<T: Mmu>(&mut self, mmu: &T) {
+        for (_, range) in &REGIONS {
+            for addr in range.clone() {
+                self.snapshot[addr as usize] = mmu.read(addr);
+            }
+        }
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    // the value at the last snapshot
+    pub fn previous(&self, addr: u16) -> u16 {
+        self.size.value(addr, |a| self.snapshot[a as usize])
+    }
+
+    pub fn current<T: Mmu>(&self, mmu: &T, addr: u16) -> u16 {
+        self.size.value(addr, |a| mmu.read(a))
+    }
+
+    // keeps the candidates for which `comparison` holds between the snapshot and now, then
+    // takes a new snapshot
+    pub fn filter<T: Mmu>(&mut self, mmu: &T, comparison: Comparison) -> usize {
+        let (size, snapshot) = (self.size, &self.snapshot);
+        self.candidates.retain(|&addr| {
+            let old = size.value(addr, |a| snapshot[a as usize]);
+            comparison.holds(old, size.value(addr, |a| mmu.read(a)), size)
+        });
+        self.snapshot(mmu);
+        self.passes += 1;
+        self.candidates.len()
+    }
+}
+
+// GameShark codes holding `value` at `addr`, one per byte
+pub fn freeze(size: Size, addr: u16, value: u16) -> Vec<Cheat> {
+    let bytes = match size {
+        Size::Byte => vec![value as u8],
+        Size::WordLe => value.to_le_bytes().to_vec(),
+        Size::WordBe => value.to_be_bytes().to_vec(),
+    };
+    bytes.into_iter().enumerate().map(|(i, byte)| {
+        let at = addr.wrapping_add(i as u16);
+        let code = format!("01{:02X}{:02X}{:02X}", byte, at as u8, at >> 8);
+        Cheat::new(&code, &format!("freeze {:04X}", addr)).expect("a GameShark code")
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cheats::Code;
+    use crate::mmu::FlatMmu;
+
+    #[test]
+    fn narrows_down_over_passes() {
+        let mut mmu = FlatMmu::new();
+        mmu.write(0xC123, 3); // lives
+        mmu.write(0xFF90, 3);
+        let mut search = Search::new(&mmu, Size::Byte);
+        assert_eq!(search.candidates().len(), 0x2000 + 0x2000 + 0x7F);
+        assert_eq!(search.filter(&mmu, Comparison::Value(3)), 2);
+
+        mmu.write(0xC123, 2);
+        mmu.write(0xA000, 9); // not a candidate any more
+        assert_eq!(search.filter(&mmu, Comparison::Decreased), 1);
+        assert_eq!(search.candidates(), [0xC123]);
+        assert_eq!(search.filter(&mmu, Comparison::Equal), 1);
+        mmu.write(0xC123, 0);
+        assert_eq!(search.filter(&mmu, Comparison::ChangedBy(-1)), 0);
+        assert_eq!(search.passes, 4);
+    }
+
+    #[test]
+    fn words_in_either_order() {
+        let mut mmu = FlatMmu::new();
+        mmu.load(0xD000, &[0xFF, 0x00]); // 255 little-endian
+        mmu.load(0xD010, &[0x01, 0x2C]); // 300 big-endian
+        let mut le = Search::new(&mmu, Size::WordLe);
+        let mut be = Search::new(&mmu, Size::WordBe);
+        assert_eq!(*le.candidates().last().unwrap(), 0xFFFD);
+        mmu.load(0xD000, &[0x00, 0x01]); // 256: the low byte wrapped
+        mmu.load(0xD010, &[0x01, 0x2D]);
+        le.filter(&mmu, Comparison::ChangedBy(1));
+        be.filter(&mmu, Comparison::Increased);
+        // D001 went from 0000 to 0001 and D011 from 002C to 002D as well
+        assert_eq!(le.candidates(), [0xD000, 0xD001, 0xD011]);
+        assert_eq!(be.candidates(), [0xD001, 0xD010, 0xD011]);
+        assert_eq!((be.previous(0xD010), be.current(&mmu, 0xD010)), (301, 301));
+
+        let codes = freeze(Size::WordBe, 0xD010, 999);
+        assert_eq!(codes.iter().map(|c| c.code).collect::<Vec<_>>(), [
+            Code::GameShark { kind: 1, value: 0x03, addr: 0xD010 },
+            Code::GameShark { kind: 1, value: 0xE7, addr: 0xD011 },
+        ]);
+        assert_eq!(codes[0].name, "freeze D010");
+    }
+}
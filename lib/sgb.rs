@@ -0,0 +1,476 @@
+//===== SUPER GAME BOY =====
+// Command packets sent through the joypad register, palettes/attributes applied to the
+// 160x144 game screen and the border around it, composited into a 256x224 frame.
+use serde::{Deserialize, Serialize};
+
+pub const SCREEN_W: usize = 160;
+pub const SCREEN_H: usize = 144;
+pub const FRAME_W: usize = 256;
+pub const FRAME_H: usize = 224;
+
+// game screen position inside the frame
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+// attribute map: one palette number per 8x8 cell of the game screen
+const ATTR_W: usize = 20;
+const ATTR_H: usize = 18;
+
+// command codes (first byte of the first packet >> 3)
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+// MASK_EN modes
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Mask {
+    None,
+    Freeze, // keep showing the last frame
+    Black,
+    Color0, // fill with colour 0 of palette 0
+}
+
+// VRAM transfer requested by CHR_TRN/PCT_TRN, done with the next displayed frame
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Transfer {
+    BorderTiles(bool), // false: tiles 0x00..0x7F, true: 0x80..0xFF
+    BorderMap,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sgb {
+    // packet receiver
+    bits: Option<usize>, // bits received since the reset pulse, None outside a packet
+    ready: bool,         // both lines went high since the last pulse
+    packet: [u8; 16],
+    command: Vec<u8>,    // packets of the command being received
+    p1: u8,
+
+    pub palettes: [[u16; 4]; 4], // BGR555, colour 0 is shared
+    pub attributes: Vec<u8>, // ATTR_W * ATTR_H
+    pub mask: Mask,
+    pub pending: Option<Transfer>,
+
+    // border: 256 SNES 4bpp tiles, 32x32 map entries, palettes 4..7
+    border_tiles: Vec<u8>,
+    border_map: Vec<u8>,
+    border_palettes: [[u16; 16]; 4],
+
+    players: u8,
+    player: u8,
+    last_screen: Vec<u8>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self { Sgb::new() }
+}
+
+impl Sgb {
+    pub fn new() -> Sgb {
+        Sgb {
+            bits: None,
+            ready: false,
+            packet: [0; 16],
+            command: Vec::new(),
+            p1: 0x30,
+            // SGB palette 0 before the game sets its own
+            palettes: [[0x67BF, 0x265B, 0x10B5, 0x2866]; 4],
+            attributes: vec![0; ATTR_W * ATTR_H],
+            mask: Mask::None,
+            pending: None,
+            border_tiles: vec![0; 256 * 32],
+            border_map: vec![0; 0x800],
+            border_palettes: [[0; 16]; 4],
+            players: 1,
+            player: 0,
+            last_screen: vec![0; SCREEN_W * SCREEN_H],
+        }
+    }
+
+    // --- joypad register ---
+    // P14 and P15 (bits 4-5) low together is the reset pulse that starts a packet, then each
+    // bit is one line pulled low (P14 low: 0, P15 low: 1) followed by both lines high.
+    pub fn write_p1(&mut self, value: u8) {
+        let lines = (value >> 4) & 0x03;
+        let p15_rising = self.p1 & 0x20 == 0 && value & 0x20 != 0;
+        self.p1 = value & 0x30;
+        match lines {
+            0 => {
+                self.bits = Some(0);
+                self.packet = [0; 16];
+                self.ready = false;
+            }
+            1 | 2 if self.ready => {
+                self.ready = false;
+                if let Some(bit) = self.bits {
+                    self.receive_bit(bit, lines == 1);
+                }
+            }
+            3 => {
+                self.ready = true;
+                // multiplayer: every joypad poll moves on to the next controller
+                if p15_rising && self.players > 1 && self.bits.is_none() {
+                    self.player = (self.player + 1) % self.players;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // low nibble of P1 while both lines are high: 0xF minus the selected controller
+    pub fn read_p1_id(&self) -> Option<u8> {
+        match self.players > 1 && self.p1 == 0x30 {
+            true => Some(0x0F - self.player),
+            false => None,
+        }
+    }
+
+    pub fn current_player(&self) -> u8 {
+        self.player
+    }
+
+    fn receive_bit(&mut self, bit: usize, one: bool) {
+        if bit == 128 {
+            // stop bit
+            self.bits = None;
+            self.receive_packet();
+            return;
+        }
+        if one {
+            self.packet[bit / 8] |= 1 << (bit % 8);
+        }
+        self.bits = Some(bit + 1);
+    }
+
+    fn receive_packet(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+        let packets = (self.command[0] & 0x07).max(1) as usize;
+        if self.command.len() >= packets * 16 {
+            let data = std::mem::take(&mut self.command);
+            self.execute(&data);
+        }
+    }
+
+    // --- commands ---
+    fn execute(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palettes(0, 1, data),
+            PAL23 => self.set_palettes(2, 3, data),
+            PAL03 => self.set_palettes(0, 3, data),
+            PAL12 => self.set_palettes(1, 2, data),
+            ATTR_BLK => self.attr_blk(data),
+            ATTR_LIN => self.attr_lin(data),
+            ATTR_DIV => self.attr_div(data),
+            ATTR_CHR => self.attr_chr(data),
+            MLT_REQ => {
+                self.players = match data[1] & 0x03 {
+                    0 => 1,
+                    3 => 4,
+                    _ => 2,
+                };
+                self.player = 0;
+            }
+            CHR_TRN => self.pending = Some(Transfer::BorderTiles(data[1] & 0x01 != 0)),
+            PCT_TRN => self.pending = Some(Transfer::BorderMap),
+            MASK_EN => {
+                self.mask = match data[1] & 0x03 {
+                    0 => Mask::None,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                }
+            }
+            _ => {} // sound, SNES memory and system palette commands are not emulated
+        }
+    }
+
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |i: usize| u16::from_le_bytes([data[1 + 2 * i], data[2 + 2 * i]]) & 0x7FFF;
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    fn attr_blk(&mut self, data: &[u8]) {
+        let sets = (data[1] as usize).min(18);
+        for set in data[2..].chunks_exact(6).take(sets) {
+            let (control, pals) = (set[0] & 0x07, set[1]);
+            let (x1, y1, x2, y2) = (set[2] as usize, set[3] as usize, set[4] as usize, set[5] as usize);
+            let inside = pals & 0x03;
+            let mut border = (pals >> 2) & 0x03;
+            let outside = (pals >> 4) & 0x03;
+            // with only one of inside/outside selected, the border takes that palette
+            let change_border = control & 0x02 != 0 || control == 1 || control == 4;
+            match control {
+                1 => border = inside,
+                4 => border = outside,
+                _ => {}
+            }
+            for y in 0..ATTR_H {
+                for x in 0..ATTR_W {
+                    let in_x = x >= x1 && x <= x2;
+                    let in_y = y >= y1 && y <= y2;
+                    let on_edge = in_x && in_y && (x == x1 || x == x2 || y == y1 || y == y2);
+                    let pal = if on_edge {
+                        change_border.then_some(border)
+                    } else if in_x && in_y {
+                        (control & 0x01 != 0).then_some(inside)
+                    } else {
+                        (control & 0x04 != 0).then_some(outside)
+                    };
+                    if let Some(pal) = pal {
+                        self.attributes[y * ATTR_W + x] = pal;
+                    }
+                }
+            }
+        }
+    }
+
+    fn attr_lin(&mut self, data: &[u8]) {
+        let sets = (data[1] as usize).min(110);
+        for &line in data[2..].iter().take(sets) {
+            let (pos, pal) = ((line & 0x1F) as usize, (line >> 5) & 0x03);
+            match line & 0x80 != 0 {
+                true if pos < ATTR_H => (0..ATTR_W).for_each(|x| self.attributes[pos * ATTR_W + x] = pal),
+                false if pos < ATTR_W => (0..ATTR_H).for_each(|y| self.attributes[y * ATTR_W + pos] = pal),
+                _ => {}
+            }
+        }
+    }
+
+    fn attr_div(&mut self, data: &[u8]) {
+        let (pals, pos) = (data[1], data[2] as usize);
+        let (after, before, on) = (pals & 0x03, (pals >> 2) & 0x03, (pals >> 4) & 0x03);
+        let horizontal = pals & 0x40 != 0;
+        for y in 0..ATTR_H {
+            for x in 0..ATTR_W {
+                let coord = if horizontal { y } else { x };
+                self.attributes[y * ATTR_W + x] = match coord.cmp(&pos) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on,
+                    std::cmp::Ordering::Greater => after,
+                };
+            }
+        }
+    }
+
+    fn attr_chr(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+        let count = (u16::from_le_bytes([data[3], data[4]]) as usize).min(ATTR_W * ATTR_H);
+        let vertical = data[5] & 0x01 != 0;
+        for i in 0..count {
+            let Some(byte) = data.get(6 + i / 4) else { break };
+            if x >= ATTR_W || y >= ATTR_H {
+                break;
+            }
+            self.attributes[y * ATTR_W + x] = (byte >> (6 - 2 * (i % 4))) & 0x03;
+            match vertical {
+                false => { x += 1; if x == ATTR_W { x = 0; y += 1; } }
+                true => { y += 1; if y == ATTR_H { y = 0; x += 1; } }
+            }
+        }
+    }
+
+    // completes CHR_TRN/PCT_TRN with the 4KB the game put on screen (the tile data shown by
+    // the background map, in map order)
+    pub fn vram_transfer(&mut self, data: &[u8]) {
+        let Some(transfer) = self.pending.take() else { return };
+        match transfer {
+            Transfer::BorderTiles(high) => {
+                let start = if high { 0x1000 } else { 0 };
+                let len = data.len().min(0x1000);
+                self.border_tiles[start..start + len].copy_from_slice(&data[..len]);
+            }
+            Transfer::BorderMap => {
+                let len = data.len().min(0x800);
+                self.border_map[..len].copy_from_slice(&data[..len]);
+                for (i, color) in data.get(0x800..0x880).unwrap_or(&[]).chunks_exact(2).enumerate() {
+                    self.border_palettes[i / 16][i % 16] = u16::from_le_bytes([color[0], color[1]]) & 0x7FFF;
+                }
+            }
+        }
+    }
+
+    // --- output ---
+    // `screen` holds the DMG colour numbers (after BGP/OBP) of the 160x144 game screen,
+    // `out` receives the 256x224 frame as 0xRRGGBB
+    pub fn render(&mut self, screen: &[u8], out: &mut [u32]) {
+        assert_eq!(screen.len(), SCREEN_W * SCREEN_H);
+        assert_eq!(out.len(), FRAME_W * FRAME_H);
+
+        if self.mask != Mask::Freeze {
+            self.last_screen.copy_from_slice(screen);
+        }
+        let backdrop = rgb(self.palettes[0][0]);
+        out.fill(backdrop);
+
+        for y in 0..SCREEN_H {
+            for x in 0..SCREEN_W {
+                let color = match self.mask {
+                    Mask::Black => 0,
+                    Mask::Color0 => backdrop,
+                    Mask::None | Mask::Freeze => {
+                        let pal = self.attributes[(y / 8) * ATTR_W + x / 8] as usize;
+                        rgb(self.palettes[pal][(self.last_screen[y * SCREEN_W + x] & 0x03) as usize])
+                    }
+                };
+                out[(y + SCREEN_Y) * FRAME_W + x + SCREEN_X] = color;
+            }
+        }
+
+        // border on top, colour 0 is transparent
+        for ty in 0..FRAME_H / 8 {
+            for tx in 0..FRAME_W / 8 {
+                let i = 2 * (ty * 32 + tx);
+                let entry = u16::from_le_bytes([self.border_map[i], self.border_map[i + 1]]);
+                let tile = &self.border_tiles[(entry & 0xFF) as usize * 32..][..32];
+                let pal = ((entry >> 10) & 0x03) as usize;
+                for row in 0..8 {
+                    let src_row = if entry & 0x8000 != 0 { 7 - row } else { row };
+                    for col in 0..8 {
+                        let bit = if entry & 0x4000 != 0 { col } else { 7 - col };
+                        let index = border_pixel(tile, src_row, bit);
+                        if index != 0 {
+                            out[(ty * 8 + row) * FRAME_W + tx * 8 + col] = rgb(self.border_palettes[pal][index]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// SNES 4bpp tile: planes 0/1 interleaved in the first 16 bytes, planes 2/3 in the last 16
+fn border_pixel(tile: &[u8], row: usize, bit: usize) -> usize {
+    let plane = |offset: usize| ((tile[offset] >> bit) & 1) as usize;
+    plane(row * 2) | plane(row * 2 + 1) << 1 | plane(16 + row * 2) << 2 | plane(16 + row * 2 + 1) << 3
+}
+
+// BGR555 -> 0xRRGGBB
+fn rgb(color: u16) -> u32 {
+    let channel = |shift: u16| {
+        let c = ((color >> shift) & 0x1F) as u32;
+        (c << 3) | (c >> 2)
+    };
+    (channel(0) << 16) | (channel(5) << 8) | channel(10)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn send(sgb: &mut Sgb, data: &[u8]) {
+        for packet in data.chunks(16) {
+            sgb.write_p1(0x00);
+            sgb.write_p1(0x30);
+            for i in 0..129 {
+                let one = i < 128 && packet.get(i / 8).is_some_and(|b| b & (1 << (i % 8)) != 0);
+                sgb.write_p1(if one { 0x10 } else { 0x20 });
+                sgb.write_p1(0x30);
+            }
+        }
+    }
+
+    fn command(cmd: u8, packets: u8, args: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 16 * packets as usize];
+        data[0] = cmd << 3 | packets;
+        data[1..1 + args.len()].copy_from_slice(args);
+        data
+    }
+
+    #[test]
+    fn pal01_packet() {
+        let mut sgb = Sgb::new();
+        // joypad polling outside a packet is ignored
+        sgb.write_p1(0x20);
+        sgb.write_p1(0x30);
+        send(&mut sgb, &command(PAL01, 1, &[0x1F, 0x00, 0xE0, 0x03, 0, 0, 0, 0, 0, 0, 0x00, 0x7C]));
+        assert_eq!(sgb.palettes[0][0], 0x001F);
+        assert_eq!(sgb.palettes[3][0], 0x001F);
+        assert_eq!(sgb.palettes[0][1], 0x03E0);
+        assert_eq!(sgb.palettes[1][2], 0x7C00);
+        assert_eq!(rgb(0x001F), 0xFF0000);
+    }
+
+    #[test]
+    fn attribute_commands() {
+        let mut sgb = Sgb::new();
+        // inside palette 1, border palette 2, for the block (2,2)-(5,5)
+        send(&mut sgb, &command(ATTR_BLK, 1, &[1, 0x03, 0b1001, 2, 2, 5, 5]));
+        assert_eq!(sgb.attributes[2 * ATTR_W + 2], 2);
+        assert_eq!(sgb.attributes[3 * ATTR_W + 3], 1);
+        assert_eq!(sgb.attributes[6 * ATTR_W + 6], 0);
+
+        // horizontal division at row 9: 1 above, 2 on it, 3 below
+        send(&mut sgb, &command(ATTR_DIV, 1, &[0x40 | 0x20 | 0x04 | 0x03, 9]));
+        assert_eq!(sgb.attributes[0], 1);
+        assert_eq!(sgb.attributes[9 * ATTR_W + 4], 2);
+        assert_eq!(sgb.attributes[17 * ATTR_W], 3);
+
+        // column 4 in palette 0
+        send(&mut sgb, &command(ATTR_LIN, 1, &[1, 0x04]));
+        assert_eq!(sgb.attributes[4], 0);
+        assert_eq!(sgb.attributes[17 * ATTR_W + 4], 0);
+
+        // four cells from (18,0) left to right, wrapping to the next row
+        send(&mut sgb, &command(ATTR_CHR, 1, &[18, 0, 4, 0, 0, 0b11100100]));
+        assert_eq!(&sgb.attributes[18..20], &[3, 2]);
+        assert_eq!(&sgb.attributes[ATTR_W..ATTR_W + 2], &[1, 0]);
+    }
+
+    #[test]
+    fn multiplayer_ids() {
+        let mut sgb = Sgb::new();
+        assert_eq!(sgb.read_p1_id(), None);
+        send(&mut sgb, &command(MLT_REQ, 1, &[0x01]));
+        assert_eq!(sgb.read_p1_id(), Some(0x0F));
+        sgb.write_p1(0x10);
+        sgb.write_p1(0x30);
+        assert_eq!(sgb.read_p1_id(), Some(0x0E));
+        sgb.write_p1(0x10);
+        sgb.write_p1(0x30);
+        assert_eq!(sgb.current_player(), 0);
+    }
+
+    #[test]
+    fn composited_frame() {
+        let mut sgb = Sgb::new();
+        send(&mut sgb, &command(PAL01, 1, &[0, 0, 0x1F, 0, 0x1F, 0, 0x1F, 0]));
+        let screen = vec![1; SCREEN_W * SCREEN_H];
+        let mut out = vec![0; FRAME_W * FRAME_H];
+
+        // border tile 1 filled with colour 1, at the top-left map entry with palette 4
+        send(&mut sgb, &command(CHR_TRN, 1, &[0]));
+        assert_eq!(sgb.pending, Some(Transfer::BorderTiles(false)));
+        let mut tiles = vec![0; 0x1000];
+        tiles[32..48].iter_mut().step_by(2).for_each(|b| *b = 0xFF);
+        sgb.vram_transfer(&tiles);
+        send(&mut sgb, &command(PCT_TRN, 1, &[]));
+        let mut map = vec![0; 0x1000];
+        map[0..2].copy_from_slice(&(0x1000u16 | 1).to_le_bytes());
+        map[0x802..0x804].copy_from_slice(&0x03E0u16.to_le_bytes());
+        sgb.vram_transfer(&map);
+
+        sgb.render(&screen, &mut out);
+        assert_eq!(out[SCREEN_Y * FRAME_W + SCREEN_X], 0xFF0000);
+        assert_eq!(out[0], 0x00FF00);
+        assert_eq!(out[FRAME_W - 1], 0x000000);
+
+        send(&mut sgb, &command(MASK_EN, 1, &[2]));
+        sgb.render(&screen, &mut out);
+        assert_eq!(out[SCREEN_Y * FRAME_W + SCREEN_X], 0);
+    }
+}
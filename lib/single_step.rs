@@ -0,0 +1,278 @@
+//===== SINGLE STEP TESTS =====
+// Conformance harness for the SingleStepTests/sm83 JSON cases: every case sets up registers
+// and RAM, runs one step on a flat bus that records what the CPU does in each M-cycle through
+// Mmu::access, then checks the registers, RAM and the bus activity cycle by cycle: a read or
+// write with its address and value, or an idle cycle. An idle cycle where the 16-bit
+// incrementer drives the bus (INC rr, PUSH) must show the case's address; purely internal
+// cycles put no address out and only have to be idle.
+//   SM83_TESTS=/path/to/sm83/v1 cargo test sm83 -- --nocapture
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde_json::Value;
+use crate::mmu::{BusAccess, FlatMmu, Mmu};
+use crate::processor::Cpu;
+use crate::registers::Registers;
+
+// failures printed per file, the rest are only counted
+const SHOWN: usize = 3;
+
+// one M-cycle on the bus: 'r', 'w' or '-' for idle, with the address and value when known
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cycle {
+    kind: char,
+    addr: Option<u16>,
+    value: Option<u8>,
+}
+
+const IDLE: Cycle = Cycle { kind: '-', addr: None, value: None };
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(addr) = self.addr {
+            write!(f, ":{:04X}", addr)?;
+        }
+        if let Some(value) = self.value {
+            write!(f, "={:02X}", value)?;
+        }
+        Ok(())
+    }
+}
+
+// the CPU reports each access and IDU cycle before making it, values are filled in by read and
+// write, which follow their report
+struct TestMmu {
+    mem: FlatMmu,
+    log: RefCell<Vec<(u64, Cycle)>>, // T-cycle the M-cycle starts at
+}
+
+impl TestMmu {
+    fn set_value(&self, value: u8) {
+        if let Some((_, cycle)) = self.log.borrow_mut().last_mut() {
+            cycle.value = Some(value);
+        }
+    }
+
+    // the M-cycles of a step that started at T-cycle `start` and took `cycles`
+    fn timeline(&self, start: u64, cycles: u32) -> Vec<Cycle> {
+        let mut timeline = vec![IDLE; cycles as usize / 4];
+        for &(at, cycle) in self.log.borrow().iter() {
+            if let Some(slot) = timeline.get_mut(((at - start) / 4) as usize) {
+                *slot = cycle;
+            }
+        }
+        timeline
+    }
+}
+
+impl Mmu for TestMmu {
+    fn read(&self, addr: u16) -> u8 {
+        let value = self.mem.read(addr);
+        self.set_value(value);
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.set_value(value);
+        self.mem.write(addr, value);
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        let kind = match kind {
+            BusAccess::Read | BusAccess::ReadIdu => 'r',
+            BusAccess::Write => 'w',
+            BusAccess::Idu => '-',
+        };
+        self.log.get_mut().push((cycle, Cycle { kind, addr: Some(addr), value: None }));
+    }
+
+    // single instructions never take an interrupt
+    fn pending_interrupts(&self) -> u8 {
+        0
+    }
+}
+
+fn field(state: &Value, name: &str) -> Result<u16, String> {
+    state[name].as_u64().map(|v| v as u16).ok_or(format!("missing '{}'", name))
+}
+
+fn registers(state: &Value) -> Result<Registers, String> {
+    let mut regs = Registers::power_on();
+    regs.setaf(field(state, "a")? << 8 | field(state, "f")?);
+    regs.b = field(state, "b")? as u8;
+    regs.c = field(state, "c")? as u8;
+    regs.d = field(state, "d")? as u8;
+    regs.e = field(state, "e")? as u8;
+    regs.h = field(state, "h")? as u8;
+    regs.l = field(state, "l")? as u8;
+    regs.sp = field(state, "sp")?;
+    regs.pc = field(state, "pc")?;
+    Ok(regs)
+}
+
+fn ram(state: &Value) -> Result<Vec<(u16, u8)>, String> {
+    let entries = state["ram"].as_array().ok_or("missing 'ram'")?;
+    entries.iter()
+        .map(|e| match (e[0].as_u64(), e[1].as_u64()) {
+            (Some(addr), Some(value)) => Ok((addr as u16, value as u8)),
+            _ => Err(format!("bad ram entry {}", e)),
+        })
+        .collect()
+}
+
+// runs one case and describes the first mismatch
+pub fn run_case(case: &Value) -> Result<(), String> {
+    let (initial, expected) = (&case["initial"], &case["final"]);
+    let mut mmu = TestMmu { mem: FlatMmu::new(), log: RefCell::new(Vec::new()) };
+    for (addr, value) in ram(initial)? {
+        mmu.mem.write(addr, value);
+    }
+    if let Some(ie) = initial["ie"].as_u64() {
+        mmu.mem.write(0xFFFF, ie as u8);
+    }
+    let mut cpu = Cpu::new(registers(initial)?);
+    cpu.ime = initial["ime"].as_u64() == Some(1);
+    let start = cpu.cycles;
+    let cycles = cpu.step(&mut mmu);
+
+    let want = registers(expected)?;
+    let got = &cpu.regs;
+    let pairs = [
+        ("AF", got.af(), want.af()), ("BC", got.bc(), want.bc()), ("DE", got.de(), want.de()),
+        ("HL", got.hl(), want.hl()), ("SP", got.sp, want.sp), ("PC", got.pc, want.pc),
+    ];
+    for (name, got, want) in pairs {
+        if got != want {
+            return Err(format!("{} is {:04X}, expected {:04X}", name, got, want));
+        }
+    }
+    // EI counts as enabled, the case ends before its delay runs out
+    if let Some(ime) = expected["ime"].as_u64() {
+        let got = cpu.ime || cpu.ei_pending();
+        if got != (ime == 1) {
+            return Err(format!("IME is {}, expected {}", got as u8, ime));
+        }
+    }
+    for (addr, value) in ram(expected)? {
+        let got = mmu.mem.read(addr);
+        if got != value {
+            return Err(format!("[{:04X}] is {:02X}, expected {:02X}", addr, got, value));
+        }
+    }
+
+    let expected_cycles = case["cycles"].as_array().ok_or("missing 'cycles'")?;
+    if cycles as usize != expected_cycles.len() * 4 {
+        return Err(format!("took {} M-cycles, expected {}", cycles / 4, expected_cycles.len()));
+    }
+    // [addr, value, "r-m" | "-wm" | "---"] with a null value when idle, or null for a cycle
+    // with nothing on the bus
+    for (n, (got, want)) in mmu.timeline(start, cycles).into_iter().zip(expected_cycles).enumerate() {
+        let want = match want[2].as_str() {
+            Some(kind) => Cycle {
+                kind: if kind.contains('w') { 'w' } else if kind.contains('r') { 'r' } else { '-' },
+                addr: want[0].as_u64().map(|addr| addr as u16),
+                value: want[1].as_u64().map(|value| value as u8),
+            },
+            None => IDLE,
+        };
+        let same = match want.kind {
+            '-' => got.kind == '-' && (got.addr.is_none() || want.addr.is_none() || got.addr == want.addr),
+            _ => got == want,
+        };
+        if !same {
+            return Err(format!("M-cycle {} is {}, expected {}", n + 1, got, want));
+        }
+    }
+    Ok(())
+}
+
+// (cases, failure messages) for one JSON file
+pub fn run_file(path: &Path) -> Result<(usize, Vec<String>), String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let cases: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+    let cases = cases.as_array().ok_or("expected an array of cases")?;
+    let failures = cases.iter()
+        .filter_map(|case| run_case(case).err().map(|e| format!("{}: {}", case["name"].as_str().unwrap_or("?"), e)))
+        .collect();
+    Ok((cases.len(), failures))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn state(pc: u16, a: u8, f: u8, ram: Value) -> Value {
+        json!({"pc": pc, "sp": 0xFFFE, "a": a, "b": 0, "c": 0, "d": 0, "e": 0, "f": f, "h": 0xC0, "l": 0x00, "ime": 0, "ie": 0, "ram": ram})
+    }
+
+    #[test]
+    fn harness_checks_state_and_bus() {
+        // 86: ADD A, (HL) with (HL) = 0x0F
+        let mut case = json!({
+            "name": "86 0000",
+            "initial": state(0x0100, 0x01, 0x00, json!([[0x0100, 0x86], [0xC000, 0x0F]])),
+            "final": state(0x0101, 0x10, 0x20, json!([[0x0100, 0x86], [0xC000, 0x0F]])),
+            "cycles": [[0x0100, 0x86, "r-m"], [0xC000, 0x0F, "r-m"]],
+        });
+        assert_eq!(run_case(&case), Ok(()));
+
+        case["final"]["f"] = json!(0x00);
+        assert_eq!(run_case(&case), Err("AF is 1020, expected 1000".to_string()));
+        case["final"]["f"] = json!(0x20);
+        case["cycles"] = json!([[0x0100, 0x86, "r-m"], null]);
+        assert_eq!(run_case(&case), Err("M-cycle 2 is r:C000=0F, expected -".to_string()));
+        case["cycles"] = json!([[0x0100, 0x86, "r-m"], [0xC001, 0x0F, "r-m"]]);
+        assert_eq!(run_case(&case), Err("M-cycle 2 is r:C000=0F, expected r:C001=0F".to_string()));
+    }
+
+    // the incrementer's address in INC rr's second cycle is checked, so is where the idle falls
+    #[test]
+    fn harness_checks_idle_cycles() {
+        // 03: INC BC with BC = 0x1234
+        let mut initial = state(0x0100, 0x00, 0x00, json!([[0x0100, 0x03]]));
+        (initial["b"], initial["c"]) = (json!(0x12), json!(0x34));
+        let mut expected = state(0x0101, 0x00, 0x00, json!([[0x0100, 0x03]]));
+        (expected["b"], expected["c"]) = (json!(0x12), json!(0x35));
+        let mut case = json!({
+            "name": "03 0000", "initial": initial, "final": expected,
+            "cycles": [[0x0100, 0x03, "r-m"], [0x1234, null, "---"]],
+        });
+        assert_eq!(run_case(&case), Ok(()));
+        case["cycles"][1][0] = json!(0x1235);
+        assert_eq!(run_case(&case), Err("M-cycle 2 is -:1234, expected -:1235".to_string()));
+        case["cycles"] = json!([[0x1234, null, "---"], [0x0100, 0x03, "r-m"]]);
+        assert_eq!(run_case(&case), Err("M-cycle 1 is r:0100=03, expected -:1234".to_string()));
+    }
+
+    // every *.json under SM83_TESTS, skipped when it is not set
+    #[test]
+    fn sm83() {
+        let Ok(dir) = std::env::var("SM83_TESTS") else {
+            eprintln!("SM83_TESTS is not set, skipping the SingleStepTests cases");
+            return;
+        };
+        let mut paths: Vec<_> = fs::read_dir(&dir).expect("SM83_TESTS is not a directory")
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .collect();
+        paths.sort();
+
+        let (mut total, mut failed, mut report) = (0, 0, String::new());
+        for path in &paths {
+            let (cases, failures) = run_file(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            total += cases;
+            failed += failures.len();
+            for failure in failures.iter().take(SHOWN) {
+                report += &format!("{}\n", failure);
+            }
+            if failures.len() > SHOWN {
+                report += &format!("  ... {} more in {}\n", failures.len() - SHOWN, path.display());
+            }
+        }
+        eprintln!("{} files, {} cases, {} failed", paths.len(), total, failed);
+        assert!(failed == 0, "{}", report);
+    }
+}
@@ -0,0 +1,238 @@
+//===== TEST ROMS =====
+// Headless runner for the blargg and mooneye test suites. A ROM runs on a flat bus without a
+// mapper until it reports a result:
+//  - blargg prints "Passed" or "Failed" on the serial port, or leaves the DE B0 61 signature at
+//    0xA001 with the result code at 0xA000 (0x80 while running) and the text from 0xA004
+//  - mooneye executes LD B,B with B,C,D,E,H,L = 3,5,8,13,21,34 on a pass or all 0x42 on a fail
+// Anything else runs until the frame limit. There is no mapper, timer or PPU here, so a ROM
+// whose header asks for a mapper (other than MBC1 on 32KB, which never switches banks), more
+// than 32KB or a CGB is skipped instead of run.
+use std::fmt;
+use crate::cartridge::Header;
+use crate::doctor::DoctorMmu;
+use crate::mmu::{BootRom, FlatMmu, Mmu};
+use crate::model::Model;
+use crate::processor::{Cpu, FRAME_CYCLES, IF};
+use crate::registers::Registers;
+
+// serial data and control
+pub const SB: u16 = 0xFF01;
+pub const SC: u16 = 0xFF02;
+
+// a minute of emulated time, enough for each of the 32KB cpu_instrs ROMs; the combined
+// cpu_instrs.gb needs MBC1 and most mooneye acceptance tests need the timer or the PPU
+pub const DEFAULT_FRAMES: u64 = 60 * 60;
+
+const LD_B_B: u8 = 0x40;
+const FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const RUNNING: u8 = 0x80;
+
+// ROM without a mapper (writes below 0x8000 are dropped). A serial transfer on the internal
+// clock completes at once with nothing on the other end and is kept in `serial`.
+pub struct TestBus {
+    pub mem: FlatMmu,
+    pub serial: Vec<u8>,
+}
+
+impl TestBus {
+    pub fn new(rom: &[u8]) -> TestBus {
+        TestBus { mem: FlatMmu::with_rom(rom), serial: Vec::new() }
+    }
+}
+
+impl Mmu for TestBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.mem.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            SC if value & 0x81 == 0x81 => {
+                self.serial.push(self.mem.read(SB));
+                self.mem.write(SB, 0xFF);
+                self.mem.write(SC, value & 0x7F);
+                let flags = self.mem.read(IF);
+                self.mem.write(IF, flags | 0x08);
+            }
+            _ => self.mem.write(addr, value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Timeout,
+    Skipped(String), // the header asks for hardware that is not emulated
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "pass"),
+            Outcome::Fail(_) => write!(f, "FAIL"),
+            Outcome::Timeout => write!(f, "TIMEOUT"),
+            Outcome::Skipped(_) => write!(f, "skipped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub outcome: Outcome,
+    pub cycles: u64,
+    pub serial: String,
+}
+
+impl Report {
+    pub fn frames(&self) -> u64 {
+        self.cycles / FRAME_CYCLES
+    }
+}
+
+fn mooneye(regs: &Registers) -> Option<Outcome> {
+    let values = [regs.b, regs.c, regs.d, regs.e, regs.h, regs.l];
+    match values {
+        FIBONACCI => Some(Outcome::Pass),
+        [0x42, 0x42, 0x42, 0x42, 0x42, 0x42] => Some(Outcome::Fail("mooneye failure pattern".to_string())),
+        _ => None,
+    }
+}
+
+fn blargg_serial(serial: &str) -> Option<Outcome> {
+    if serial.contains("Passed") {
+        Some(Outcome::Pass)
+    } else if serial.contains("Failed") {
+        Some(Outcome::Fail(serial.trim().to_string()))
+    } else {
+        None
+    }
+}
+
+fn blargg_memory<T: Mmu>(mmu: &T) -> Option<Outcome> {
+    let signature = [mmu.read(0xA001), mmu.read(0xA002), mmu.read(0xA003)];
+    let code = mmu.read(0xA000);
+    if signature != SIGNATURE || code == RUNNING {
+        return None;
+    }
+    let text: Vec<u8> = (0xA004..0xC000u16).map(|a| mmu.read(a)).take_while(|&b| b != 0).collect();
+    match code {
+        0 => Some(Outcome::Pass),
+        _ => Some(Outcome::Fail(format!("result {}: {}", code, String::from_utf8_lossy(&text).trim()))),
+    }
+}
+
+// boots `rom` through `boot_rom`, or in the post-boot state of `model` without one, and runs
+// at most `frames` frames
+pub fn run(rom: &[u8], model: Model, boot_rom: Option<&[u8]>, frames: u64) -> Report {
+    if let Ok(header) = Header::parse(rom)
+        && let Some(problem) = header.unsupported().into_iter().next()
+    {
+        return Report { outcome: Outcome::Skipped(problem), cycles: 0, serial: String::new() };
+    }
+    let mut bus = TestBus::new(rom);
+    let (bus, regs) = match boot_rom {
+        Some(boot) => match BootRom::new(boot.to_vec(), bus) {
+            Ok(bus) => (bus, Registers::power_on()),
+            Err(e) => return Report { outcome: Outcome::Fail(e.to_string()), cycles: 0, serial: String::new() },
+        },
+        None => {
+            model.apply_post_boot(&mut bus, rom.get(0x0104..0x0134).unwrap_or(&[]));
+            (BootRom::skipped(bus), Registers::for_model(model))
+        }
+    };
+    // on the CPU alone, without GameBoy's LCD: DoctorMmu pins LY at 0x90 so the ROMs' waits for
+    // VBlank return at once, as in the gameboy-doctor logs
+    let mut mmu = DoctorMmu { bus };
+    let mut cpu = Cpu::new(regs);
+    let limit = frames * FRAME_CYCLES;
+    let mut next_frame = FRAME_CYCLES;
+
+    let outcome = loop {
+        if cpu.locked {
+            break Outcome::Fail(format!("CPU locked up at {:04X}", cpu.regs.pc));
+        }
+        if cpu.executes(&mmu) && mmu.read(cpu.regs.pc) == LD_B_B
+            && let Some(outcome) = mooneye(&cpu.regs) {
+            break outcome;
+        }
+        cpu.step(&mut mmu);
+        if cpu.cycles >= next_frame {
+            next_frame += FRAME_CYCLES;
+            let serial = String::from_utf8_lossy(&mmu.bus.bus.serial).into_owned();
+            if let Some(outcome) = blargg_serial(&serial).or_else(|| blargg_memory(&mmu)) {
+                break outcome;
+            }
+        }
+        if cpu.cycles >= limit {
+            break Outcome::Timeout;
+        }
+    };
+    Report { outcome, cycles: cpu.cycles, serial: String::from_utf8_lossy(&mmu.bus.bus.serial).into_owned() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn rom(body: &str) -> Vec<u8> {
+        assemble(&format!("SECTION \"main\", ROM0[$100]\n{}", body)).unwrap()
+    }
+
+    #[test]
+    fn mooneye_registers() {
+        // a plain LD B,B on the way does not end the run
+        let pass = rom("ld b,b\nld b,3\nld c,5\nld d,8\nld e,13\nld h,21\nld l,34\nld b,b\n.spin: jr .spin");
+        assert_eq!(run(&pass, Model::DMG, None, 1).outcome, Outcome::Pass);
+        let fail = rom("ld a,$42\nld b,a\nld c,a\nld d,a\nld e,a\nld h,a\nld l,a\nld b,b\n.spin: jr .spin");
+        assert!(matches!(run(&fail, Model::DMG, None, 1).outcome, Outcome::Fail(_)));
+    }
+
+    #[test]
+    fn blargg_serial_and_memory() {
+        let print = "ld hl, text\n.next: ld a,[hl+]\nor a\njr z, .done\nldh [$01],a\nld a,$81\nldh [$02],a\njr .next\n.done: jr .done\n";
+        let report = run(&rom(&format!("{}text: db \"cpu_instrs\\n\\nPassed\", 0", print)), Model::DMG, None, 10);
+        assert_eq!((report.outcome, report.serial.as_str()), (Outcome::Pass, "cpu_instrs\n\nPassed"));
+
+        let memory = "ld hl, $A000\nld [hl], $80\ninc hl\nld [hl], $DE\ninc hl\nld [hl], $B0\ninc hl\nld [hl], $61\n\
+                      inc hl\nld [hl], 'X'\ninc hl\nld [hl], 0\nld a, 3\nld [$A000], a\n.spin: jr .spin";
+        assert_eq!(run(&rom(memory), Model::DMG, None, 10).outcome, Outcome::Fail("result 3: X".to_string()));
+    }
+
+    #[test]
+    fn timeout_and_lock_up() {
+        let report = run(&rom(".spin: jr .spin"), Model::DMG, None, 3);
+        assert_eq!((report.frames(), report.outcome), (3, Outcome::Timeout));
+        assert_eq!(run(&rom("db $D3"), Model::DMG, None, 3).outcome, Outcome::Fail("CPU locked up at 0101".to_string()));
+    }
+
+    #[test]
+    fn skips_unsupported_cartridges() {
+        // 32KB MBC1, as blargg's single cpu_instrs ROMs, runs
+        let mut mbc1 = rom(".spin: jr .spin");
+        mbc1.resize(0x8000, 0);
+        mbc1[0x0147] = 0x01;
+        mbc1[0x014D] = crate::cartridge::header_checksum(&mbc1);
+        assert_eq!(run(&mbc1, Model::DMG, None, 3).outcome, Outcome::Timeout);
+        // 64KB MBC1 would switch banks
+        let mut banked = mbc1;
+        banked.resize(0x10000, 0);
+        banked[0x0148] = 0x01;
+        banked[0x014D] = crate::cartridge::header_checksum(&banked);
+        assert!(matches!(run(&banked, Model::DMG, None, 3).outcome, Outcome::Skipped(_)));
+    }
+
+    #[test]
+    fn through_a_boot_rom() {
+        let mut boot = vec![0x00; 0x100];
+        boot[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]); // ld a, 1 / ldh [$50], a
+        let pass = rom("ld b,3\nld c,5\nld d,8\nld e,13\nld h,21\nld l,34\nld b,b");
+        let report = run(&pass, Model::DMG, Some(&boot), 1);
+        assert_eq!(report.outcome, Outcome::Pass);
+        assert!(report.cycles > 0xFC * 4);
+        assert!(matches!(run(&pass, Model::DMG, Some(&boot[..0x80]), 1).outcome, Outcome::Fail(_)));
+    }
+}
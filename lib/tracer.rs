@@ -0,0 +1,256 @@
+//===== TRACER =====
+// Follows control flow through a ROM image to tell code from data, bank by bank, and prints
+// the result as an RGBDS source file that assembles back to the same bytes.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use crate::disassembler::*;
+use crate::instructions::*;
+
+pub const BANK_SIZE: usize = 0x4000;
+
+// entry points every ROM has: RST and interrupt vectors, then the cartridge entry
+const VECTORS: [(u16, &str); 14] = [
+    (0x0000, "RST_00"), (0x0008, "RST_08"), (0x0010, "RST_10"), (0x0018, "RST_18"),
+    (0x0020, "RST_20"), (0x0028, "RST_28"), (0x0030, "RST_30"), (0x0038, "RST_38"),
+    (0x0040, "VBlankInterrupt"), (0x0048, "LCDCInterrupt"), (0x0050, "TimerOverflowInterrupt"),
+    (0x0058, "SerialTransferCompleteInterrupt"), (0x0060, "JoypadTransitionInterrupt"),
+    (0x0100, "Boot"),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ByteKind {
+    Data,
+    Code,    // first byte of an instruction
+    Operand, // following bytes of an instruction
+}
+
+pub struct RomMap<'a> {
+    rom: &'a [u8],
+    pub kinds: Vec<ByteKind>,
+    pub labels: BTreeMap<usize, String>, // by file offset
+}
+
+// file offset of a bank/address pair
+pub fn rom_offset(bank: usize, addr: u16) -> usize {
+    match addr < 0x4000 {
+        true => addr as usize,
+        false => bank * BANK_SIZE + (addr as usize - 0x4000),
+    }
+}
+
+// bank and CPU address of a file offset
+pub fn rom_location(offset: usize) -> (usize, u16) {
+    let bank = offset / BANK_SIZE;
+    match bank {
+        0 => (0, offset as u16),
+        _ => (bank, 0x4000 + (offset % BANK_SIZE) as u16),
+    }
+}
+
+impl<'a> RomMap<'a> {
+    // `entries` are extra (bank, address) starting points on top of the vectors
+    pub fn trace(rom: &'a [u8], entries: &[(usize, u16)]) -> RomMap<'a> {
+        let mut map = RomMap { rom, kinds: vec![ByteKind::Data; rom.len()], labels: BTreeMap::new() };
+        let mut work = Vec::new();
+        for (addr, name) in VECTORS {
+            if (addr as usize) < rom.len() {
+                map.labels.insert(addr as usize, name.to_string());
+                work.push(addr as usize);
+            }
+        }
+        for &(bank, addr) in entries {
+            let offset = rom_offset(bank, addr);
+            if offset < rom.len() {
+                map.labels.entry(offset).or_insert_with(|| label_name("Entry", offset));
+                work.push(offset);
+            }
+        }
+        while let Some(offset) = work.pop() {
+            map.follow(offset, &mut work);
+        }
+        map
+    }
+
+    // a jump from `bank` to `addr`: the switchable bank is only known from inside it, or when
+    // the ROM has no second bank to switch to
+    fn resolve(&self, bank: usize, addr: u16) -> Option<usize> {
+        let offset = match addr {
+            0x0000..=0x3FFF => addr as usize,
+            0x4000..=0x7FFF if bank != 0 => rom_offset(bank, addr),
+            0x4000..=0x7FFF if self.rom.len() <= 2 * BANK_SIZE => rom_offset(1, addr),
+            _ => return None,
+        };
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    fn follow(&mut self, mut offset: usize, work: &mut Vec<usize>) {
+        loop {
+            if offset >= self.rom.len() || self.kinds[offset] != ByteKind::Data {
+                return;
+            }
+            let (bank, addr) = rom_location(offset);
+            let bank_end = self.rom.len().min((bank + 1) * BANK_SIZE);
+            let instr = match self.rom[offset] {
+                0xCB if offset + 1 < bank_end => decode_cb(self.rom[offset + 1]),
+                op => INSTRUCTIONS[op as usize],
+            };
+            let len = instr.size() as usize;
+            if matches!(instr.instr_type, InstructionType::ERR)
+                || offset + len > bank_end
+                || self.kinds[offset..offset + len].iter().any(|k| *k != ByteKind::Data)
+            {
+                return;
+            }
+            self.kinds[offset] = ByteKind::Code;
+            self.kinds[offset + 1..offset + len].fill(ByteKind::Operand);
+
+            let line = disassemble(&self.rom[offset..offset + len], addr, Syntax::Rgbds);
+            if let Some(target) = line.target.and_then(|t| self.resolve(bank, t)) {
+                let kind = match instr.instr_type {
+                    InstructionType::CALL | InstructionType::RST => "Call",
+                    _ => "Jump",
+                };
+                self.labels.entry(target).or_insert_with(|| label_name(kind, target));
+                work.push(target);
+            }
+
+            use InstructionType as IN;
+            let unconditional = matches!(instr.cond, ConditionType::NONE);
+            match instr.instr_type {
+                IN::JP | IN::JR | IN::RET if unconditional => return,
+                IN::RETI => return,
+                _ => offset += len,
+            }
+        }
+    }
+
+    // a label can only be written where a line starts
+    fn label_at(&self, offset: usize) -> Option<&String> {
+        match self.kinds.get(offset) {
+            Some(ByteKind::Operand) | None => None,
+            _ => self.labels.get(&offset),
+        }
+    }
+
+    pub fn code_bytes(&self) -> usize {
+        self.kinds.iter().filter(|k| **k != ByteKind::Data).count()
+    }
+
+    pub fn to_asm(&self) -> String {
+        let mut out = String::new();
+        for bank in 0..self.rom.len().div_ceil(BANK_SIZE) {
+            match bank {
+                0 => writeln!(out, "SECTION \"ROM Bank $000\", ROM0[$0000]").unwrap(),
+                _ => writeln!(out, "\nSECTION \"ROM Bank ${:03X}\", ROMX[$4000], BANK[${:X}]", bank, bank).unwrap(),
+            }
+            let end = self.rom.len().min((bank + 1) * BANK_SIZE);
+            let mut offset = bank * BANK_SIZE;
+            while offset < end {
+                if let Some(label) = self.label_at(offset) {
+                    writeln!(out, "\n{}:", label).unwrap();
+                }
+                offset = match self.kinds[offset] {
+                    ByteKind::Code => self.write_code(&mut out, offset, bank),
+                    _ => self.write_data(&mut out, offset, end),
+                };
+            }
+        }
+        out
+    }
+
+    fn write_code(&self, out: &mut String, offset: usize, bank: usize) -> usize {
+        let (_, addr) = rom_location(offset);
+        let len = self.kinds[offset + 1..].iter().take_while(|k| **k == ByteKind::Operand).count() + 1;
+        let bytes = &self.rom[offset..offset + len];
+        // RGBDS always pads STOP with 0x00, anything else has to stay raw
+        let text = match bytes {
+            [0x10, pad] if *pad != 0x00 => format!("db $10, ${:02x}", pad),
+            _ => {
+                let label = |target: u16| self.resolve(bank, target).and_then(|t| self.label_at(t)).cloned();
+                disassemble_with(bytes, addr, Syntax::Rgbds, label).text
+            }
+        };
+        writeln!(out, "    {:<24}; ${:04x}", text, addr).unwrap();
+        offset + len
+    }
+
+    // up to 8 bytes per line, broken at labels and code
+    fn write_data(&self, out: &mut String, offset: usize, end: usize) -> usize {
+        let mut stop = offset + 1;
+        while stop < end && stop - offset < 8 && self.kinds[stop] == ByteKind::Data && !self.labels.contains_key(&stop) {
+            stop += 1;
+        }
+        let bytes: Vec<String> = self.rom[offset..stop].iter().map(|b| format!("${:02x}", b)).collect();
+        writeln!(out, "    db {}", bytes.join(", ")).unwrap();
+        stop
+    }
+}
+
+fn label_name(kind: &str, offset: usize) -> String {
+    let (bank, addr) = rom_location(offset);
+    format!("{}_{:03X}_{:04X}", kind, bank, addr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 0x0100: nop / jp $0150, 0x0150: call $0160 / jr $0150, 0x0160: ret, 0x0161: data
+    fn test_rom() -> Vec<u8> {
+        let mut rom = vec![0xFF; 2 * BANK_SIZE];
+        rom[0x0000] = 0xC9;
+        rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x0150..0x0155].copy_from_slice(&[0xCD, 0x60, 0x01, 0x18, 0xFB]);
+        rom[0x0160] = 0xC9;
+        rom[0x0161..0x0165].copy_from_slice(&[0x12, 0x34, 0xD3, 0x00]);
+        rom
+    }
+
+    #[test]
+    fn offsets_and_locations() {
+        assert_eq!(rom_offset(0, 0x0150), 0x0150);
+        assert_eq!(rom_offset(3, 0x4010), 0xC010);
+        assert_eq!(rom_location(0xC010), (3, 0x4010));
+        assert_eq!(rom_location(0x3FFF), (0, 0x3FFF));
+    }
+
+    #[test]
+    fn code_and_data() {
+        let rom = test_rom();
+        let map = RomMap::trace(&rom, &[]);
+        assert_eq!(map.kinds[0x0101], ByteKind::Code);
+        assert_eq!(map.kinds[0x0102], ByteKind::Operand);
+        assert_eq!(map.kinds[0x0104], ByteKind::Data); // header after the jump
+        assert_eq!(map.kinds[0x0153], ByteKind::Code);
+        assert_eq!(map.kinds[0x0161], ByteKind::Data);
+        assert_eq!(map.labels[&0x0150], "Jump_000_0150");
+        assert_eq!(map.labels[&0x0160], "Call_000_0160");
+    }
+
+    #[test]
+    fn switchable_bank_targets() {
+        let mut rom = vec![0x00; 4 * BANK_SIZE];
+        // bank 0 cannot know which bank is mapped, bank 2 jumps within itself
+        rom[0x0100..0x0103].copy_from_slice(&[0xC3, 0x00, 0x40]);
+        rom[2 * BANK_SIZE..2 * BANK_SIZE + 3].copy_from_slice(&[0xC3, 0x10, 0x40]);
+        rom[2 * BANK_SIZE + 0x10] = 0xC9;
+        let map = RomMap::trace(&rom, &[(2, 0x4000)]);
+        assert_eq!(map.kinds[BANK_SIZE], ByteKind::Data);
+        assert_eq!(map.kinds[3 * BANK_SIZE], ByteKind::Data);
+        assert_eq!(map.labels[&(2 * BANK_SIZE + 0x10)], "Jump_002_4010");
+        assert_eq!(map.labels[&(2 * BANK_SIZE)], "Entry_002_4000");
+    }
+
+    #[test]
+    fn asm_output() {
+        let rom = test_rom();
+        let asm = RomMap::trace(&rom, &[]).to_asm();
+        assert!(asm.starts_with("SECTION \"ROM Bank $000\", ROM0[$0000]\n"));
+        assert!(asm.contains("\nBoot:\n    nop"));
+        assert!(asm.contains("    jp Jump_000_0150"));
+        assert!(asm.contains("\nJump_000_0150:\n    call Call_000_0160"));
+        assert!(asm.contains("    jr Jump_000_0150"));
+        assert!(asm.contains("    db $12, $34, $d3, $00, $ff, $ff, $ff, $ff\n"));
+        assert!(asm.contains("SECTION \"ROM Bank $001\", ROMX[$4000], BANK[$1]"));
+    }
+}
@@ -0,0 +1,404 @@
+//===== VRAM =====
+// What the graphics debugger shows: tile data of each VRAM bank, the BG and window maps with the
+// part that is on screen outlined, OAM decoded and the palettes, each as an Image. A Vram is a
+// copy of the bus at one moment. The DMG bus has one VRAM bank and no colour palettes; on the CGB
+// models ModelBus keeps a CgbVram with bank 1 (tile attributes, more tiles) behind VBK and the
+// palette RAM behind BCPS/BCPD and OCPS/OCPD, which the CGB renderer will draw from.
+use serde::{Deserialize, Serialize};
+use crate::lcd::{BGP, LCDC, OAM, OBP0, OBP1, SCX, SCY, WX, WY, bg_tile_addr};
+use crate::mmu::Mmu;
+use crate::screenshot::Image;
+use crate::sgb::{SCREEN_H, SCREEN_W};
+
+pub const VRAM: u16 = 0x8000;
+pub const VRAM_SIZE: usize = 0x2000;
+pub const TILES: usize = 384;
+// tile sheets are 16 tiles wide
+pub const SHEET_COLUMNS: usize = 16;
+pub const MAP_SIZE: usize = 256;
+// the outline of the visible area in tilemap images
+pub const VIEWPORT_COLOR: u32 = 0xFF0000;
+// OAM images: 8 sprites a row, each in an 8x16 cell
+pub const OAM_COLUMNS: usize = 8;
+// palette images: one 8x8 swatch per colour, one palette per row
+pub const SWATCH: usize = 8;
+
+pub const VBK: u16 = 0xFF4F;
+pub const BCPS: u16 = 0xFF68;
+pub const BCPD: u16 = 0xFF69;
+pub const OCPS: u16 = 0xFF6A;
+pub const OCPD: u16 = 0xFF6B;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layer {
+    Background,
+    Window,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+    pub index: usize,
+    pub y: u8, // screen Y + 16
+    pub x: u8, // screen X + 8
+    pub tile: u8,
+    pub flags: u8,
+}
+
+impl Sprite {
+    pub fn behind_bg(&self) -> bool { self.flags & 0x80 != 0 }
+    pub fn y_flip(&self) -> bool { self.flags & 0x40 != 0 }
+    pub fn x_flip(&self) -> bool { self.flags & 0x20 != 0 }
+    pub fn dmg_palette(&self) -> usize { (self.flags >> 4 & 1) as usize }
+    pub fn bank(&self) -> usize { (self.flags >> 3 & 1) as usize }
+    pub fn cgb_palette(&self) -> usize { (self.flags & 0x07) as usize }
+
+    pub fn visible(&self) -> bool {
+        (1..160).contains(&self.y) && (1..168).contains(&self.x)
+    }
+
+    pub fn describe(&self) -> String {
+        format!("#{} at {:04X}: X {} Y {} tile {:02X} flags {:02X}{}{}{} OBP{} CGB bank {} palette {}{}",
+            self.index, OAM + self.index as u16 * 4, self.x as i16 - 8, self.y as i16 - 16, self.tile, self.flags,
+            if self.behind_bg() { " behind-BG" } else { "" }, if self.x_flip() { " X-flip" } else { "" },
+            if self.y_flip() { " Y-flip" } else { "" }, self.dmg_palette(), self.bank(), self.cgb_palette(),
+            if self.visible() { "" } else { " (off screen)" })
+    }
+}
+
+// 0bbbbbgggggrrrrr, little-endian, to 0xRRGGBB
+pub fn cgb_color(lo: u8, hi: u8) -> u32 {
+    let value = u16::from_le_bytes([lo, hi]) as u32;
+    let expand = |c: u32| (c << 3) | (c >> 2);
+    expand(value & 0x1F) << 16 | expand(value >> 5 & 0x1F) << 8 | expand(value >> 10 & 0x1F)
+}
+
+// 8 palettes of 4 colours out of 64 bytes of palette RAM
+pub fn cgb_palettes(ram: &[u8; 64]) -> [[u32; 4]; 8] {
+    let mut palettes = [[0; 4]; 8];
+    for (i, pair) in ram.chunks(2).enumerate() {
+        palettes[i / 4][i % 4] = cgb_color(pair[0], pair[1]);
+    }
+    palettes
+}
+
+// The CGB's VRAM bank 1 and palette RAM. VBK selects the bank 0x8000..0x9FFF reads and writes
+// go to (bank 0 stays in the flat memory); BCPS/OCPS hold the index into the 64 bytes of BG/OBJ
+// palette RAM in bits 0-5, moved on by each BCPD/OCPD write when bit 7 is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CgbVram {
+    pub vbk: u8,
+    pub bank1: Vec<u8>, // VRAM_SIZE bytes
+    pub bcps: u8,
+    pub ocps: u8,
+    pub bg_palettes: Vec<u8>, // 64 bytes each
+    pub obj_palettes: Vec<u8>,
+}
+
+impl Default for CgbVram {
+    fn default() -> CgbVram {
+        CgbVram { vbk: 0, bank1: vec![0; VRAM_SIZE], bcps: 0, ocps: 0, bg_palettes: vec![0; 64], obj_palettes: vec![0; 64] }
+    }
+}
+
+// writes palette RAM at the index in `spec`, then moves it on if auto-increment is set
+fn write_palette(ram: &mut [u8], spec: &mut u8, value: u8) {
+    ram[(*spec & 0x3F) as usize] = value;
+    if *spec & 0x80 != 0 {
+        *spec = 0x80 | (*spec + 1) & 0x3F;
+    }
+}
+
+impl CgbVram {
+    // None for the addresses that are not the CGB's
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            VBK => Some(0xFE | self.vbk),
+            0x8000..=0x9FFF if self.vbk == 1 => Some(self.bank1[(addr - VRAM) as usize]),
+            BCPS => Some(self.bcps | 0x40),
+            BCPD => Some(self.bg_palettes[(self.bcps & 0x3F) as usize]),
+            OCPS => Some(self.ocps | 0x40),
+            OCPD => Some(self.obj_palettes[(self.ocps & 0x3F) as usize]),
+            _ => None,
+        }
+    }
+
+    // false for the addresses that are not the CGB's
+    pub fn write(&mut self, addr: u16, value: u8) -> bool {
+        match addr {
+            VBK => self.vbk = value & 0x01,
+            0x8000..=0x9FFF if self.vbk == 1 => self.bank1[(addr - VRAM) as usize] = value,
+            BCPS => self.bcps = value & 0xBF,
+            BCPD => write_palette(&mut self.bg_palettes, &mut self.bcps, value),
+            OCPS => self.ocps = value & 0xBF,
+            OCPD => write_palette(&mut self.obj_palettes, &mut self.ocps, value),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vram {
+    pub banks: Vec<Vec<u8>>, // VRAM_SIZE bytes each, bank 1 holds CGB map attributes
+    pub oam: Vec<u8>,
+    pub lcdc: u8,
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8,
+    pub bgp: u8,
+    pub obp: [u8; 2],
+    pub bg_palettes: Option<[u8; 64]>,  // CGB palette RAM
+    pub obj_palettes: Option<[u8; 64]>,
+}
+
+impl Vram {
+    // bank 0 and the registers from `mmu`, which must not have VBK in front of it, bank 1 and the
+    // colour palettes from `cgb` on a CGB
+    pub fn from_bus<T: Mmu>(mmu: &T, cgb: Option<&CgbVram>) -> Vram {
+        let mut banks = vec![(0..VRAM_SIZE as u16).map(|i| mmu.read(VRAM + i)).collect()];
+        banks.extend(cgb.map(|cgb| cgb.bank1.clone()));
+        let palettes = |ram: &Vec<u8>| ram.as_slice().try_into().expect("CgbVram palette RAM is 64 bytes");
+        Vram {
+            banks,
+            oam: (0..0xA0).map(|i| mmu.read(OAM + i)).collect(),
+            lcdc: mmu.read(LCDC),
+            scx: mmu.read(SCX),
+            scy: mmu.read(SCY),
+            wx: mmu.read(WX),
+            wy: mmu.read(WY),
+            bgp: mmu.read(BGP),
+            obp: [mmu.read(OBP0), mmu.read(OBP1)],
+            bg_palettes: cgb.map(|cgb| palettes(&cgb.bg_palettes)),
+            obj_palettes: cgb.map(|cgb| palettes(&cgb.obj_palettes)),
+        }
+    }
+
+    // colour number of pixel (x, y) of tile data at `addr` (0x8000..0x97FF) in `bank`
+    fn pixel(&self, bank: usize, addr: u16, x: u8, y: u8) -> u8 {
+        let data = &self.banks[bank];
+        let at = (addr - VRAM) as usize + y as usize * 2;
+        let bit = 7 - x;
+        ((data[at + 1] >> bit) & 1) << 1 | ((data[at] >> bit) & 1)
+    }
+
+    pub fn map_addr(&self, layer: Layer) -> u16 {
+        let bit = match layer {
+            Layer::Background => 0x08,
+            Layer::Window => 0x40,
+        };
+        if self.lcdc & bit != 0 { 0x9C00 } else { 0x9800 }
+    }
+
+    // the tile index at map cell (column, row), its tile data address and the CGB attributes
+    pub fn map_entry(&self, layer: Layer, column: usize, row: usize) -> (u16, u8, u16, Option<u8>) {
+        let addr = self.map_addr(layer) + (row * 32 + column) as u16;
+        let offset = (addr - VRAM) as usize;
+        let tile = self.banks[0][offset];
+        (addr, tile, bg_tile_addr(self.lcdc, tile), self.banks.get(1).map(|bank| bank[offset]))
+    }
+
+    // all 384 tiles of `bank` as raw colour numbers through `palette`, 16 a row
+    pub fn tiles(&self, bank: usize, palette: [u32; 4]) -> Image {
+        let (width, height) = (SHEET_COLUMNS * 8, TILES / SHEET_COLUMNS * 8);
+        let mut pixels = vec![0; width * height];
+        for tile in 0..TILES {
+            let addr = VRAM + tile as u16 * 16;
+            let (left, top) = (tile % SHEET_COLUMNS * 8, tile / SHEET_COLUMNS * 8);
+            for y in 0..8 {
+                for x in 0..8 {
+                    pixels[(top + y) * width + left + x] = palette[self.pixel(bank, addr, x as u8, y as u8) as usize];
+                }
+            }
+        }
+        Image::new(width, height, pixels)
+    }
+
+    // the 256x256 map of `layer` through BGP with the visible area outlined: SCX/SCY for the
+    // background (wrapping around), the part of the window left of and below WX/WY
+    pub fn tilemap(&self, layer: Layer, palette: [u32; 4]) -> Image {
+        let mut pixels = vec![0; MAP_SIZE * MAP_SIZE];
+        for row in 0..32 {
+            for column in 0..32 {
+                let (_, _, data, attributes) = self.map_entry(layer, column, row);
+                let attributes = attributes.unwrap_or(0);
+                let bank = (attributes >> 3 & 1) as usize;
+                for y in 0..8u8 {
+                    for x in 0..8u8 {
+                        let (tx, ty) = (if attributes & 0x20 != 0 { 7 - x } else { x }, if attributes & 0x40 != 0 { 7 - y } else { y });
+                        let color = crate::lcd::shade(self.bgp, self.pixel(bank.min(self.banks.len() - 1), data, tx, ty));
+                        pixels[(row * 8 + y as usize) * MAP_SIZE + column * 8 + x as usize] = palette[color as usize];
+                    }
+                }
+            }
+        }
+
+        let (left, top, width, height) = match layer {
+            Layer::Background => (self.scx as usize, self.scy as usize, SCREEN_W, SCREEN_H),
+            Layer::Window => (0, 0, SCREEN_W.saturating_sub((self.wx as usize).saturating_sub(7)), SCREEN_H.saturating_sub(self.wy as usize)),
+        };
+        if width > 0 && height > 0 {
+            let mut plot = |x: usize, y: usize| pixels[(y % MAP_SIZE) * MAP_SIZE + x % MAP_SIZE] = VIEWPORT_COLOR;
+            for x in left..left + width {
+                plot(x, top);
+                plot(x, top + height - 1);
+            }
+            for y in top..top + height {
+                plot(left, y);
+                plot(left + width - 1, y);
+            }
+        }
+        Image::new(MAP_SIZE, MAP_SIZE, pixels)
+    }
+
+    pub fn sprites(&self) -> Vec<Sprite> {
+        self.oam.chunks(4).enumerate()
+            .map(|(index, entry)| Sprite { index, y: entry[0], x: entry[1], tile: entry[2], flags: entry[3] })
+            .collect()
+    }
+
+    pub fn tall_sprites(&self) -> bool {
+        self.lcdc & 0x04 != 0
+    }
+
+    // the 40 sprites in OAM order through OBP0/OBP1, 8x16 cells whatever the sprite size,
+    // transparent pixels in palette colour 0
+    pub fn oam_image(&self, palette: [u32; 4]) -> Image {
+        let (width, height) = (OAM_COLUMNS * 8, 40 / OAM_COLUMNS * 16);
+        let mut pixels = vec![palette[0]; width * height];
+        let rows = if self.tall_sprites() { 16 } else { 8 };
+        for sprite in self.sprites() {
+            let (left, top) = (sprite.index % OAM_COLUMNS * 8, sprite.index / OAM_COLUMNS * 16);
+            let tile = if rows == 16 { sprite.tile & 0xFE } else { sprite.tile };
+            let bank = sprite.bank().min(self.banks.len() - 1);
+            for y in 0..rows {
+                let row = if sprite.y_flip() { rows - 1 - y } else { y };
+                let addr = VRAM + tile as u16 * 16 + (row / 8) as u16 * 16;
+                for x in 0..8u8 {
+                    let color = self.pixel(bank, addr, if sprite.x_flip() { 7 - x } else { x }, row % 8);
+                    if color != 0 {
+                        let shade = crate::lcd::shade(self.obp[sprite.dmg_palette()], color);
+                        pixels[(top + y as usize) * width + left + x as usize] = palette[shade as usize];
+                    }
+                }
+            }
+        }
+        Image::new(width, height, pixels)
+    }
+
+    // rows of 4 colours: BGP, OBP0, OBP1 through `palette`, then the 8 CGB BG and 8 OBJ palettes
+    pub fn palettes(&self, palette: [u32; 4]) -> Vec<[u32; 4]> {
+        let dmg = [self.bgp, self.obp[0], self.obp[1]];
+        let mut rows: Vec<[u32; 4]> = dmg.iter().map(|&p| [0, 1, 2, 3].map(|c| palette[crate::lcd::shade(p, c) as usize])).collect();
+        for ram in [&self.bg_palettes, &self.obj_palettes].into_iter().flatten() {
+            rows.extend(cgb_palettes(ram));
+        }
+        rows
+    }
+
+    pub fn palette_image(&self, palette: [u32; 4]) -> Image {
+        let rows = self.palettes(palette);
+        let width = 4 * SWATCH;
+        let pixels = rows.iter()
+            .flat_map(|row| (0..SWATCH).flat_map(move |_| row.iter().flat_map(|&color| [color; SWATCH])))
+            .collect();
+        Image::new(width, rows.len() * SWATCH, pixels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    const GREYS: [u32; 4] = [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000];
+
+    fn bus() -> FlatMmu {
+        let mut mmu = FlatMmu::new();
+        mmu.write(LCDC, 0x80 | 0x10 | 0x01);
+        mmu.write(BGP, 0xE4);
+        mmu.write(OBP1, 0x1B); // reversed
+        // tile 1: colour 3 on the top row, colour 1 in the left column
+        mmu.write(0x8010, 0xFF);
+        mmu.write(0x8011, 0xFF);
+        for row in 1..8 {
+            mmu.write(0x8010 + row * 2, 0x80);
+        }
+        mmu
+    }
+
+    #[test]
+    fn tiles_and_maps() {
+        let mut mmu = bus();
+        mmu.write(0x9800 + 32 + 2, 1); // map cell (2, 1)
+        mmu.write(SCX, 250);
+        mmu.write(SCY, 4);
+        let vram = Vram::from_bus(&mmu, None);
+
+        let tiles = vram.tiles(0, GREYS);
+        assert_eq!((tiles.width, tiles.height), (128, 192));
+        assert_eq!((tiles.pixels[8], tiles.pixels[8 + 128], tiles.pixels[9 + 128]), (GREYS[3], GREYS[1], GREYS[0]));
+
+        assert_eq!(vram.map_entry(Layer::Background, 2, 1), (0x9822, 1, 0x8010, None));
+        let map = vram.tilemap(Layer::Background, GREYS);
+        assert_eq!(map.pixels[8 * MAP_SIZE + 17], GREYS[3]);
+        // the outline wraps around from X 250 to 250 + 159
+        assert_eq!(map.pixels[4 * MAP_SIZE + 250], VIEWPORT_COLOR);
+        assert_eq!(map.pixels[4 * MAP_SIZE + 3], VIEWPORT_COLOR);
+        assert_eq!(map.pixels[100 * MAP_SIZE + (250 + 159) % 256], VIEWPORT_COLOR);
+        assert_eq!(map.pixels[100 * MAP_SIZE + 100], GREYS[0]);
+    }
+
+    #[test]
+    fn sprites_and_palettes() {
+        let mut mmu = bus();
+        mmu.load(OAM + 4, &[16, 8, 1, 0x30]); // sprite 1 at the top left, X-flipped, OBP1
+        let vram = Vram::from_bus(&mmu, None);
+        let sprite = vram.sprites()[1];
+        assert!(sprite.visible() && sprite.x_flip() && !sprite.y_flip());
+        assert_eq!(sprite.dmg_palette(), 1);
+        assert!(!vram.sprites()[0].visible());
+
+        let image = vram.oam_image(GREYS);
+        // colour 3 through the reversed OBP1, colour 1 now in the right column
+        assert_eq!(image.pixels[8], GREYS[0]);
+        assert_eq!((image.pixels[64 + 15], image.pixels[64 + 8]), (GREYS[2], GREYS[0]));
+
+        assert_eq!(vram.palettes(GREYS)[2], [GREYS[3], GREYS[2], GREYS[1], GREYS[0]]);
+        assert_eq!(vram.palette_image(GREYS).height, 3 * SWATCH);
+        assert_eq!(cgb_color(0x1F, 0x00), 0xFF0000);
+        assert_eq!(cgb_color(0x00, 0x7C), 0x0000FF);
+        let mut ram = [0; 64];
+        ram[62..].copy_from_slice(&[0xE0, 0x03]);
+        assert_eq!(cgb_palettes(&ram)[7][3], 0x00FF00);
+    }
+
+    #[test]
+    fn cgb_bank_and_palettes() {
+        let mut cgb = CgbVram::default();
+        assert!(!cgb.write(0x8010, 0x12));
+        assert!(cgb.write(VBK, 0xFF));
+        assert!(cgb.write(0x9822, 0x28)); // attributes of map cell (2, 1): bank 1, X-flip
+        assert!(cgb.write(0x8010, 0xFF));
+        assert_eq!((cgb.read(VBK), cgb.read(0x9822), cgb.read(LCDC)), (Some(0xFF), Some(0x28), None));
+        // auto-increment from index 62: red, then wraps to 0
+        cgb.write(BCPS, 0x80 | 62);
+        for value in [0x1F, 0x00, 0xAB] {
+            cgb.write(BCPD, value);
+        }
+        assert_eq!((cgb.read(BCPS), cgb.bg_palettes[0]), (Some(0xC1), 0xAB));
+        cgb.write(OCPS, 5);
+        cgb.write(OCPD, 0x7C);
+        cgb.write(OCPD, 0x03);
+        assert_eq!((cgb.read(OCPS), cgb.obj_palettes[5]), (Some(0x45), 0x03));
+        cgb.write(VBK, 0);
+        assert_eq!(cgb.read(0x9822), None);
+
+        let vram = Vram::from_bus(&bus(), Some(&cgb));
+        assert_eq!(vram.banks.len(), 2);
+        assert_eq!(vram.tiles(1, GREYS).pixels[8], GREYS[1]);
+        assert_eq!(vram.map_entry(Layer::Background, 2, 1), (0x9822, 0, 0x8000, Some(0x28)));
+        let palettes = vram.palettes(GREYS);
+        assert_eq!((palettes.len(), palettes[3 + 7][3]), (3 + 16, 0xFF0000));
+    }
+}
@@ -0,0 +1,270 @@
+//===== WATCHPOINTS =====
+// Read/write/execute watchpoints on addresses and ranges. `Watched` sits in front of the bus
+// for one CPU step and records every access that hits; the debugger only puts it there when
+// at least one watchpoint exists, so plain runs go straight to the Mmu.
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use crate::mmu::{BusAccess, Mmu};
+
+// the hit log keeps the most recent accesses only
+pub const LOG_SIZE: usize = 4096;
+
+// I/O registers by name, so `watch w NR52` works
+pub const IO_REGISTERS: [(&str, u16); 57] = [
+    ("P1", 0xFF00), ("SB", 0xFF01), ("SC", 0xFF02), ("DIV", 0xFF04), ("TIMA", 0xFF05),
+    ("TMA", 0xFF06), ("TAC", 0xFF07), ("IF", 0xFF0F), ("NR10", 0xFF10), ("NR11", 0xFF11),
+    ("NR12", 0xFF12), ("NR13", 0xFF13), ("NR14", 0xFF14), ("NR21", 0xFF16), ("NR22", 0xFF17),
+    ("NR23", 0xFF18), ("NR24", 0xFF19), ("NR30", 0xFF1A), ("NR31", 0xFF1B), ("NR32", 0xFF1C),
+    ("NR33", 0xFF1D), ("NR34", 0xFF1E), ("NR41", 0xFF20), ("NR42", 0xFF21), ("NR43", 0xFF22),
+    ("NR44", 0xFF23), ("NR50", 0xFF24), ("NR51", 0xFF25), ("NR52", 0xFF26), ("LCDC", 0xFF40),
+    ("STAT", 0xFF41), ("SCY", 0xFF42), ("SCX", 0xFF43), ("LY", 0xFF44), ("LYC", 0xFF45),
+    ("DMA", 0xFF46), ("BGP", 0xFF47), ("OBP0", 0xFF48), ("OBP1", 0xFF49), ("WY", 0xFF4A),
+    ("WX", 0xFF4B), ("KEY1", 0xFF4D), ("VBK", 0xFF4F), ("BANK", 0xFF50), ("HDMA1", 0xFF51),
+    ("HDMA2", 0xFF52), ("HDMA3", 0xFF53), ("HDMA4", 0xFF54), ("HDMA5", 0xFF55), ("RP", 0xFF56),
+    ("BCPS", 0xFF68), ("BCPD", 0xFF69), ("OCPS", 0xFF6A), ("OCPD", 0xFF6B), ("OPRI", 0xFF6C),
+    ("SVBK", 0xFF70), ("IE", 0xFFFF),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16, // inclusive
+    pub bank: Option<usize>, // ROM bank, None matches any
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub stop: bool, // false only logs the hits
+}
+
+impl Watchpoint {
+    // "c000", "c000-c0ff", "1:4000-4fff" (hex) or an I/O register name
+    pub fn parse(range: &str, kinds: &str, stop: bool) -> Option<Watchpoint> {
+        let hex = |t: &str| u16::from_str_radix(t.trim_start_matches('$').trim_start_matches("0x"), 16).ok();
+        let (bank, range) = match range.split_once(':') {
+            Some((bank, range)) => (Some(usize::from_str_radix(bank, 16).ok()?), range),
+            None => (None, range),
+        };
+        let (start, end) = match IO_REGISTERS.iter().find(|(name, _)| name.eq_ignore_ascii_case(range)) {
+            Some((_, addr)) => (*addr, *addr),
+            None => match range.split_once('-') {
+                Some((start, end)) => (hex(start)?, hex(end)?),
+                None => (hex(range)?, hex(range)?),
+            },
+        };
+        if start > end || kinds.is_empty() || kinds.chars().any(|k| !"rwx".contains(k)) {
+            return None;
+        }
+        let has = |k| kinds.contains(k);
+        Some(Watchpoint { start, end, bank, read: has('r'), write: has('w'), execute: has('x'), stop })
+    }
+
+    fn matches<T: Mmu>(&self, mmu: &T, access: Access, addr: u16) -> bool {
+        let kind = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        };
+        // writes below 0x8000 go to the mapper, there is no bank to tell apart
+        let rom = addr < 0x8000 && access != Access::Write;
+        kind && (self.start..=self.end).contains(&addr) && (!rom || self.bank.is_none_or(|b| mmu.rom_bank(addr) == b))
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(bank) = self.bank {
+            write!(f, "{:02X}:", bank)?;
+        }
+        write!(f, "{:04X}", self.start)?;
+        if self.end != self.start {
+            write!(f, "-{:04X}", self.end)?;
+        }
+        let kinds: String = [(self.read, 'r'), (self.write, 'w'), (self.execute, 'x')]
+            .iter().filter(|(on, _)| *on).map(|(_, k)| *k).collect();
+        write!(f, " {} {}", kinds, if self.stop { "stop" } else { "log" })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hit {
+    pub id: usize,
+    pub access: Access,
+    pub addr: u16,
+    pub value: u8,
+    pub pc: u16,    // start of the instruction that made the access
+    pub cycle: u64, // CPU cycle count when that instruction started
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => 'R',
+            Access::Write => 'W',
+            Access::Execute => 'X',
+        };
+        write!(f, "#{} PC={:04X} {} {:04X}={:02X} cycle={}", self.id, self.pc, access, self.addr, self.value, self.cycle)
+    }
+}
+
+#[derive(Default)]
+pub struct Watchpoints {
+    points: BTreeMap<usize, Watchpoint>,
+    next_id: usize,
+    hot: Vec<u64>, // one bit per address covered by any watchpoint
+    log: RefCell<VecDeque<Hit>>,
+    stop: Cell<Option<usize>>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Watchpoints {
+        Watchpoints::default()
+    }
+
+    pub fn add(&mut self, point: Watchpoint) -> usize {
+        self.next_id += 1;
+        self.points.insert(self.next_id, point);
+        self.rebuild();
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let removed = self.points.remove(&id).is_some();
+        self.rebuild();
+        removed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&usize, &Watchpoint)> {
+        self.points.iter()
+    }
+
+    fn rebuild(&mut self) {
+        self.hot = vec![0; 0x10000 / 64];
+        for point in self.points.values() {
+            for addr in point.start..=point.end {
+                self.hot[addr as usize / 64] |= 1 << (addr % 64);
+            }
+        }
+    }
+
+    fn is_hot(&self, addr: u16) -> bool {
+        self.hot.get(addr as usize / 64).is_some_and(|bits| bits & (1 << (addr % 64)) != 0)
+    }
+
+    // logs the access against every watchpoint it hits and remembers the first one that stops
+    pub fn check<T: Mmu>(&self, mmu: &T, access: Access, addr: u16, value: u8, pc: u16, cycle: u64) {
+        if !self.is_hot(addr) {
+            return;
+        }
+        let mut log = self.log.borrow_mut();
+        for (&id, point) in self.points.iter().filter(|(_, p)| p.matches(mmu, access, addr)) {
+            if log.len() == LOG_SIZE {
+                log.pop_front();
+            }
+            log.push_back(Hit { id, access, addr, value, pc, cycle });
+            if point.stop && self.stop.get().is_none() {
+                self.stop.set(Some(id));
+            }
+        }
+    }
+
+    // id of the stopping watchpoint hit since the last call
+    pub fn take_stop(&self) -> Option<usize> {
+        self.stop.take()
+    }
+
+    pub fn hits(&self) -> Vec<Hit> {
+        self.log.borrow().iter().copied().collect()
+    }
+
+    pub fn clear_hits(&self) {
+        self.log.borrow_mut().clear();
+    }
+
+    // the bus as seen by one instruction starting at `pc` on `cycle`
+    pub fn attach<'a, T: Mmu>(&'a self, bus: &'a mut T, pc: u16, cycle: u64) -> Watched<'a, T> {
+        Watched { bus, watch: self, pc, cycle }
+    }
+}
+
+pub struct Watched<'a, T: Mmu> {
+    bus: &'a mut T,
+    watch: &'a Watchpoints,
+    pc: u16,
+    cycle: u64,
+}
+
+impl<T: Mmu> Mmu for Watched<'_, T> {
+    fn read(&self, addr: u16) -> u8 {
+        let value = self.bus.read(addr);
+        self.watch.check(self.bus, Access::Read, addr, value, self.pc, self.cycle);
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.watch.check(self.bus, Access::Write, addr, value, self.pc, self.cycle);
+        self.bus.write(addr, value);
+    }
+
+    fn rom_bank(&self, addr: u16) -> usize {
+        self.bus.rom_bank(addr)
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.bus.pending_interrupts()
+    }
+
+    fn access(&mut self, addr: u16, kind: BusAccess, cycle: u64) {
+        self.bus.access(addr, kind, cycle);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mmu::FlatMmu;
+
+    #[test]
+    fn parse() {
+        let point = Watchpoint::parse("nr52", "w", true).unwrap();
+        assert_eq!((point.start, point.end, point.write, point.read), (0xFF26, 0xFF26, true, false));
+        let point = Watchpoint::parse("1:4000-4fff", "rx", false).unwrap();
+        assert_eq!((point.bank, point.start, point.end), (Some(1), 0x4000, 0x4FFF));
+        assert_eq!(point.to_string(), "01:4000-4FFF rx log");
+        assert!(Watchpoint::parse("c0ff-c000", "r", true).is_none());
+        assert!(Watchpoint::parse("c000", "q", true).is_none());
+    }
+
+    #[test]
+    fn hits() {
+        let mut watch = Watchpoints::new();
+        let oam = watch.add(Watchpoint::parse("fe00-fe9f", "w", false).unwrap());
+        let rom = watch.add(Watchpoint::parse("0:0150", "r", true).unwrap());
+        watch.add(Watchpoint::parse("1:0150", "r", true).unwrap()); // bank 0 is always mapped there
+
+        let mut mmu = FlatMmu::new();
+        let mut bus = watch.attach(&mut mmu, 0x0200, 96);
+        bus.write(0xFE04, 0x12);
+        bus.write(0xC000, 0x34);
+        assert_eq!(watch.take_stop(), None);
+        bus.read(0x0150);
+        assert_eq!(watch.take_stop(), Some(rom));
+        assert_eq!(watch.take_stop(), None);
+
+        let hits = watch.hits();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0], Hit { id: oam, access: Access::Write, addr: 0xFE04, value: 0x12, pc: 0x0200, cycle: 96 });
+        assert_eq!(hits[0].to_string(), "#1 PC=0200 W FE04=12 cycle=96");
+    }
+}
@@ -0,0 +1,41 @@
+//===== WAV =====
+// 16-bit PCM WAV files for audio dumps. Samples are -1..1 floats, interleaved when there is
+// more than one channel.
+use std::io::{self, Write};
+
+pub fn write<W: Write>(out: &mut W, rate: u32, channels: u16, samples: &[f32]) -> io::Result<()> {
+    let data_size = samples.len() as u32 * 2;
+    let block_align = channels * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_size).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&rate.to_le_bytes())?;
+    out.write_all(&(rate * block_align as u32).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        out.write_all(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header_and_samples() {
+        let mut out = Vec::new();
+        write(&mut out, 44100, 2, &[0.0, 1.0, -1.0, 2.0]).unwrap();
+        assert_eq!(out.len(), 44 + 8);
+        assert_eq!(&out[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(out[4..8].try_into().unwrap()), 44);
+        assert_eq!(u32::from_le_bytes(out[28..32].try_into().unwrap()), 44100 * 4);
+        assert_eq!(&out[44..], &[0, 0, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+    }
+}
@@ -0,0 +1,115 @@
+//===== ZIP =====
+// Just enough of the zip format to read files out of small archives: BizHawk movies and zipped
+// ROMs. Entries are found through the central directory and are either stored or deflated.
+use std::fmt;
+use std::io::Read;
+
+#[derive(Debug, PartialEq)]
+pub struct ZipError(pub String);
+
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+struct Entry {
+    name: String,
+    method: usize,
+    size: usize,
+    local: usize, // offset of the local header
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Option<usize> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?) as usize)
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?) as usize)
+}
+
+fn directory(data: &[u8]) -> Result<Vec<Entry>, ZipError> {
+    let bad = || ZipError("not a zip archive".to_string());
+    let end = (0..data.len().saturating_sub(21)).rev().take(0x10000 + 22)
+        .find(|&i| data[i..i + 4] == [0x50, 0x4B, 0x05, 0x06]).ok_or_else(bad)?;
+    let count = u16_at(data, end + 10).ok_or_else(bad)?;
+    let mut pos = u32_at(data, end + 16).ok_or_else(bad)?;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if data.get(pos..pos + 4) != Some(&[0x50, 0x4B, 0x01, 0x02]) {
+            return Err(bad());
+        }
+        let name_len = u16_at(data, pos + 28).ok_or_else(bad)?;
+        let name = data.get(pos + 46..pos + 46 + name_len).ok_or_else(bad)?;
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(data, pos + 10).ok_or_else(bad)?,
+            size: u32_at(data, pos + 20).ok_or_else(bad)?,
+            local: u32_at(data, pos + 42).ok_or_else(bad)?,
+        });
+        pos += 46 + name_len + u16_at(data, pos + 30).ok_or_else(bad)? + u16_at(data, pos + 32).ok_or_else(bad)?;
+    }
+    Ok(entries)
+}
+
+// names of the files in the archive, in directory order
+pub fn names(data: &[u8]) -> Result<Vec<String>, ZipError> {
+    Ok(directory(data)?.into_iter().map(|entry| entry.name).collect())
+}
+
+// the contents of file `name`, None when there is no such file
+pub fn read(data: &[u8], name: &str) -> Result<Option<Vec<u8>>, ZipError> {
+    let Some(entry) = directory(data)?.into_iter().find(|entry| entry.name == name) else {
+        return Ok(None);
+    };
+    let bad = || ZipError(format!("{}: truncated", name));
+    let local = entry.local;
+    let start = local + 30 + u16_at(data, local + 26).ok_or_else(bad)? + u16_at(data, local + 28).ok_or_else(bad)?;
+    let raw = data.get(start..start + entry.size).ok_or_else(bad)?;
+    match entry.method {
+        0 => Ok(Some(raw.to_vec())),
+        8 => {
+            let mut out = Vec::new();
+            flate2::read::DeflateDecoder::new(raw).read_to_end(&mut out).map_err(|e| ZipError(format!("{}: {}", name, e)))?;
+            Ok(Some(out))
+        }
+        m => Err(ZipError(format!("{}: unsupported compression method {}", name, m))),
+    }
+}
+
+// an archive of (name, method, already compressed data) files, for tests
+#[cfg(test)]
+pub fn build(files: &[(&str, u16, Vec<u8>)]) -> Vec<u8> {
+    let (mut zip, mut central) = (Vec::new(), Vec::new());
+    for (name, method, data) in files {
+        let offset = zip.len() as u32;
+        zip.extend_from_slice(&[0x50, 0x4B, 0x03, 0x04, 20, 0, 0, 0]);
+        zip.extend_from_slice(&method.to_le_bytes());
+        zip.extend_from_slice(&[0; 8]); // time, date, crc
+        zip.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&[0; 4]);
+        zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&[0, 0]);
+        zip.extend_from_slice(name.as_bytes());
+        zip.extend_from_slice(data);
+
+        central.extend_from_slice(&[0x50, 0x4B, 0x01, 0x02, 20, 0, 20, 0, 0, 0]);
+        central.extend_from_slice(&method.to_le_bytes());
+        central.extend_from_slice(&[0; 8]);
+        central.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        central.extend_from_slice(&[0; 4]);
+        central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        central.extend_from_slice(&[0; 12]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let start = zip.len() as u32;
+    zip.extend_from_slice(&central);
+    zip.extend_from_slice(&[0x50, 0x4B, 0x05, 0x06, 0, 0, 0, 0]);
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&start.to_le_bytes());
+    zip.extend_from_slice(&[0, 0]);
+    zip
+}
@@ -0,0 +1,127 @@
+// Terminal debugger: runs a ROM on the whole machine in the post-boot state of a model. With a
+// trace log LY reads 0x90 throughout, as in the gameboy-doctor reference logs.
+// --boot runs a boot ROM dump from 0x0000 instead.
+//   debug <rom.gb> [-m model] [-t trace.log] [--gdb port] [--boot boot.bin]
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use cpu::debugger::Debugger;
+use cpu::doctor::{DoctorMmu, TraceLog};
+use cpu::gdb;
+use cpu::machine::GameBoy;
+use cpu::mmu::Mmu;
+use cpu::model::Model;
+use cpu::rewind::Rewind;
+use cpu::savestate::SaveState;
+
+fn usage() -> ! {
+    eprintln!("usage: debug <rom.gb> [-m dmg|mgb|sgb|cgb|...] [-t trace.log] [--gdb port] [--boot boot.bin]");
+    process::exit(2);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut rom_path = None;
+    let mut model = Model::DMG;
+    let mut gdb_port = None;
+    let mut trace_path = None;
+    let mut boot_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" => model = args.next().and_then(|m| m.parse().ok()).unwrap_or_else(|| usage()),
+            "-t" => trace_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--boot" => boot_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--gdb" => gdb_port = Some(args.next().and_then(|p| p.parse().ok()).unwrap_or_else(|| usage())),
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(rom_path) = rom_path else { usage() };
+    let read = |path: &str| fs::read(path).unwrap_or_else(|e| {
+        eprintln!("debug: cannot read {}: {}", path, e);
+        process::exit(1);
+    });
+    let (rom, boot_rom) = (read(&rom_path), boot_path.as_deref().map(read));
+
+    // no mapper: only the first 32KB are visible
+    let mut gb = GameBoy::with_boot_rom(rom, model, boot_rom).unwrap_or_else(|e| {
+        eprintln!("debug: {}: {}", boot_path.unwrap_or_default(), e);
+        process::exit(1);
+    });
+    let mut dbg = Debugger::new(gb.cpu);
+    match trace_path {
+        Some(path) => {
+            let file = fs::File::create(&path).unwrap_or_else(|e| {
+                eprintln!("debug: cannot create {}: {}", path, e);
+                process::exit(1);
+            });
+            dbg.trace = Some(TraceLog::new(file));
+            // LY reads 0x90 like in the gameboy-doctor reference logs
+            session(&mut dbg, &mut DoctorMmu { bus: gb }, |mmu| &mut mmu.bus, gdb_port);
+        }
+        None => session(&mut dbg, &mut gb, |gb| gb, gdb_port),
+    }
+}
+
+// the gdb stub or the REPL on `mmu`, which is the machine itself or the machine behind a
+// trace's DoctorMmu
+fn session<T: Mmu>(dbg: &mut Debugger, mmu: &mut T, machine: fn(&mut T) -> &mut GameBoy, gdb_port: Option<u16>) {
+    if let Some(port) = gdb_port {
+        eprintln!("debug: waiting for gdb on 127.0.0.1:{}", port);
+        if let Err(e) = gdb::listen(dbg, mmu, port) {
+            eprintln!("debug: gdb session failed: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // snapshots are taken between commands, going back re-runs the frames in between
+    let mut rewind = Rewind::default();
+    let gb = machine(mmu);
+    rewind.record(gb);
+    println!("{}", dbg.status(mmu));
+    let stdin = io::stdin();
+    loop {
+        print!("(gb) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let gb = machine(mmu);
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["q"] | ["quit"] => break,
+            ["save", path] => match fs::write(path, gb.save_state().to_bytes(gb.rom())) {
+                Ok(()) => println!("saved {}", path),
+                Err(e) => println!("cannot write {}: {}", path, e),
+            },
+            ["load", path] => match fs::read(path).map_err(|e| e.to_string())
+                .and_then(|data| SaveState::from_bytes(&data, gb.rom()).map_err(|e| e.to_string()))
+                .and_then(|state| gb.load_state(&state).map_err(|e| e.to_string())) {
+                Ok(()) => {
+                    dbg.cpu = gb.cpu;
+                    rewind = Rewind::default();
+                    rewind.record(gb);
+                    println!("{}", dbg.status(mmu));
+                }
+                Err(e) => println!("cannot load {}: {}", path, e),
+            },
+            ["rewind", ref frames @ ..] if frames.len() <= 1 => match frames.first().map_or(Some(1), |n| n.parse().ok()) {
+                Some(n) => match rewind.step_back(n, gb) {
+                    true => {
+                        dbg.cpu = gb.cpu;
+                        println!("{}", dbg.status(mmu));
+                    }
+                    false => println!("cannot go back further than frame {}", rewind.oldest_frame().unwrap_or(0)),
+                },
+                None => println!("usage: rewind [frames]"),
+            },
+            _ => {
+                println!("{}", dbg.command(mmu, &line));
+                let gb = machine(mmu);
+                rewind.record(gb);
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Tracing ROM disassembler: writes an RGBDS source file that assembles back to the ROM
+//   disasm <rom.gb> [-o out.asm] [-e bank:addr]...
+use std::env;
+use std::fs;
+use std::process;
+use cpu::tracer::RomMap;
+
+fn usage() -> ! {
+    eprintln!("usage: disasm <rom.gb> [-o out.asm] [-e bank:addr]...");
+    process::exit(2);
+}
+
+// "2:4000" -> (2, 0x4000), both in hex
+fn parse_entry(arg: &str) -> Option<(usize, u16)> {
+    let (bank, addr) = arg.split_once(':')?;
+    Some((usize::from_str_radix(bank, 16).ok()?, u16::from_str_radix(addr, 16).ok()?))
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut rom_path = None;
+    let mut out_path = None;
+    let mut entries = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => out_path = Some(args.next().unwrap_or_else(|| usage())),
+            "-e" => match args.next().as_deref().and_then(parse_entry) {
+                Some(entry) => entries.push(entry),
+                None => usage(),
+            },
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(rom_path) = rom_path else { usage() };
+
+    let rom = fs::read(&rom_path).unwrap_or_else(|e| {
+        eprintln!("disasm: cannot read {}: {}", rom_path, e);
+        process::exit(1);
+    });
+    let map = RomMap::trace(&rom, &entries);
+    let asm = format!("; {} - {} of {} bytes traced as code\n\n{}", rom_path, map.code_bytes(), rom.len(), map.to_asm());
+
+    match out_path {
+        Some(path) => fs::write(&path, asm).unwrap_or_else(|e| {
+            eprintln!("disasm: cannot write {}: {}", path, e);
+            process::exit(1);
+        }),
+        None => print!("{}", asm),
+    }
+}
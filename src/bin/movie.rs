@@ -0,0 +1,61 @@
+// Plays back an input movie on the whole machine and checks that it ends on the recorded frame;
+// BizHawk .bk2 files are imported and can be saved as a movie with their end frame filled in.
+//   movie <rom.gb> <movie.gbm|movie.bk2> [-m model] [-o out.gbm]
+use std::env;
+use std::fs;
+use std::process;
+use cpu::model::Model;
+use cpu::movie::{self, Movie};
+
+fn usage() -> ! {
+    eprintln!("usage: movie <rom.gb> <movie.gbm|movie.bk2> [-m dmg|mgb|sgb|cgb|...] [-o out.gbm]");
+    process::exit(2);
+}
+
+fn read(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| {
+        eprintln!("movie: cannot read {}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut paths = Vec::new();
+    let mut model = Model::DMG;
+    let mut out_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" => model = args.next().and_then(|m| m.parse().ok()).unwrap_or_else(|| usage()),
+            "-o" => out_path = Some(args.next().unwrap_or_else(|| usage())),
+            _ => paths.push(arg),
+        }
+    }
+    let [rom_path, movie_path] = &paths[..] else { usage() };
+    let rom = read(rom_path);
+    let data = read(movie_path);
+    let loaded = match movie_path.ends_with(".bk2") {
+        true => Movie::from_bk2(&data, &rom, model),
+        false => Movie::from_bytes(&data),
+    };
+    let mut movie = loaded.unwrap_or_else(|e| {
+        eprintln!("movie: {}: {}", movie_path, e);
+        process::exit(1);
+    });
+
+    let gb = movie.play(&rom).unwrap_or_else(|e| {
+        eprintln!("movie: {}", e);
+        process::exit(1);
+    });
+    let hash = movie::frame_hash(&gb);
+    println!("{} frames, end frame {:08X}{}", movie.frames.len(), hash,
+        if movie.end_hash.is_some() { " (matches the recording)" } else { "" });
+
+    if let Some(path) = out_path {
+        movie.end_hash = Some(hash);
+        fs::write(&path, movie.to_bytes()).unwrap_or_else(|e| {
+            eprintln!("movie: cannot write {}: {}", path, e);
+            process::exit(1);
+        });
+    }
+}
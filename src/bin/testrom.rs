@@ -0,0 +1,87 @@
+// Headless test ROM runner: runs every ROM given, or found under the directories given, and
+// prints a pass/fail table. ROMs that need a mapper or a CGB are listed as skipped. Exits with 1
+// when any ROM that ran did not pass, for CI.
+// --boot runs a boot ROM dump first instead of starting in its post-boot state.
+//   testrom <rom.gb|dir>... [-m model] [-f frames] [--boot boot.bin]
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use cpu::mmu::check_boot_rom;
+use cpu::model::Model;
+use cpu::testrom::{self, Outcome, DEFAULT_FRAMES};
+
+fn usage() -> ! {
+    eprintln!("usage: testrom <rom.gb|dir>... [-m dmg|mgb|sgb|cgb|...] [-f frames] [--boot boot.bin]");
+    process::exit(2);
+}
+
+fn collect(path: &Path, roms: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let Ok(entries) = fs::read_dir(path) else { return };
+        for entry in entries.flatten() {
+            collect(&entry.path(), roms);
+        }
+    } else if path.extension().is_some_and(|e| e == "gb" || e == "gbc") {
+        roms.push(path.to_path_buf());
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut paths = Vec::new();
+    let mut model = Model::DMG;
+    let mut frames = DEFAULT_FRAMES;
+    let mut boot_rom = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" => model = args.next().and_then(|m| m.parse().ok()).unwrap_or_else(|| usage()),
+            "-f" => frames = args.next().and_then(|f| f.parse().ok()).unwrap_or_else(|| usage()),
+            "--boot" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let data = fs::read(&path).map_err(|e| e.to_string())
+                    .and_then(|data| check_boot_rom(&data).map(|()| data).map_err(|e| e.to_string()));
+                boot_rom = Some(data.unwrap_or_else(|e| {
+                    eprintln!("testrom: {}: {}", path, e);
+                    process::exit(1);
+                }));
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    if paths.is_empty() {
+        usage();
+    }
+    let mut roms = Vec::new();
+    for path in &paths {
+        collect(path, &mut roms);
+    }
+    roms.sort();
+
+    let width = roms.iter().map(|r| r.display().to_string().len()).max().unwrap_or(0).max(3);
+    println!("{:width$}  {:7}  {:>6}  detail", "ROM", "result", "frames");
+    let (mut passed, mut skipped) = (0, 0);
+    for path in &roms {
+        let report = match fs::read(path) {
+            Ok(rom) => testrom::run(&rom, model, boot_rom.as_deref(), frames),
+            Err(e) => {
+                println!("{:width$}  {:7}  {:>6}  {}", path.display(), "ERROR", "-", e);
+                continue;
+            }
+        };
+        let detail = match &report.outcome {
+            Outcome::Fail(reason) | Outcome::Skipped(reason) => reason.lines().last().unwrap_or("").to_string(),
+            _ => String::new(),
+        };
+        match report.outcome {
+            Outcome::Pass => passed += 1,
+            Outcome::Skipped(_) => skipped += 1,
+            _ => {}
+        }
+        println!("{:width$}  {:7}  {:>6}  {}", path.display(), report.outcome.to_string(), report.frames(), detail);
+    }
+    println!("\n{}/{} passed, {} skipped", passed, roms.len() - skipped, skipped);
+    if passed + skipped != roms.len() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,105 @@
+// wgpu surface plus the egui platform and renderer. Any adapter will do, including software
+// ones (lavapipe, llvmpipe through GL): WGPU_BACKEND and WGPU_ADAPTER_NAME pick one, otherwise
+// the default adapter is tried first and the fallback adapter after it.
+use std::sync::Arc;
+use std::time::Instant;
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use egui_winit_platform::{Platform, PlatformDescriptor};
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+pub struct Gfx {
+    pub window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    pub platform: Platform,
+    pub adapter: wgpu::AdapterInfo,
+    renderer: RenderPass,
+    start: Instant,
+}
+
+impl Gfx {
+    pub fn new(window: Arc<Window>) -> Result<Gfx, String> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let surface = instance.create_surface(window.clone()).map_err(|e| e.to_string())?;
+        let adapter = pollster::block_on(wgpu::util::initialize_adapter_from_env_or_default(&instance, Some(&surface)))
+            .or_else(|_| pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::LowPower,
+                force_fallback_adapter: true,
+                compatible_surface: Some(&surface),
+            })))
+            .map_err(|e| format!("no graphics adapter: {}", e))?;
+        // GL and old Vulkan drivers only reach the downlevel limits
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: None,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits()),
+            memory_hints: wgpu::MemoryHints::MemoryUsage,
+            trace: wgpu::Trace::Off,
+        })).map_err(|e| e.to_string())?;
+
+        let size = window.inner_size();
+        let config = surface.get_default_config(&adapter, size.width.max(1), size.height.max(1))
+            .ok_or(format!("{} cannot draw to this window", adapter.get_info().name))?;
+        surface.configure(&device, &config);
+        let platform = Platform::new(PlatformDescriptor {
+            physical_width: config.width,
+            physical_height: config.height,
+            scale_factor: window.scale_factor(),
+            ..Default::default()
+        });
+        let renderer = RenderPass::new(&device, config.format, 1);
+        Ok(Gfx { window, surface, device, queue, config, platform, adapter: adapter.get_info(), renderer, start: Instant::now() })
+    }
+
+    // the egui side of a window event; true when egui used it
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        self.platform.handle_event(event);
+        if let WindowEvent::Resized(size) = event {
+            self.resize(size.width, size.height);
+        }
+        self.platform.captures_event(event)
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            (self.config.width, self.config.height) = (width, height);
+            self.surface.configure(&self.device, &self.config);
+        }
+    }
+
+    // runs one egui pass and draws it
+    pub fn render(&mut self, ui: impl FnOnce(&egui::Context)) -> Result<(), String> {
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                self.surface.configure(&self.device, &self.config);
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        };
+        self.platform.update_time(self.start.elapsed().as_secs_f64());
+        self.platform.begin_pass();
+        ui(&self.platform.context());
+        let output = self.platform.end_pass(Some(&self.window));
+        let jobs = self.platform.context().tessellate(output.shapes, output.pixels_per_point);
+
+        let screen = ScreenDescriptor {
+            physical_width: self.config.width,
+            physical_height: self.config.height,
+            scale_factor: output.pixels_per_point,
+        };
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        self.renderer.add_textures(&self.device, &self.queue, &output.textures_delta).map_err(|e| e.to_string())?;
+        self.renderer.update_buffers(&self.device, &self.queue, &jobs, &screen);
+        self.renderer.execute(&mut encoder, &view, &jobs, &screen, Some(wgpu::Color::BLACK)).map_err(|e| e.to_string())?;
+        self.queue.submit([encoder.finish()]);
+        frame.present();
+        self.renderer.remove_textures(output.textures_delta).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+// --headless: runs a ROM with no window or audio device, for CI. It stops after a number of
+// frames or when a debugger condition holds (`--until "pc == $0150 && a == 0"`), plays scripted
+// input (joypad::parse_script text, a .gbm movie or a BizHawk .bk2; a movie runs on its own
+// model from its own start, for its length unless -f says otherwise, and fails on another ROM,
+// -m model or a different last frame), then prints the CRC-32 of the final framebuffer (the
+// 256x224 frame with the border on SGB) and optionally writes it as PNG and the audio as WAV.
+// --vram writes the graphics debugger's views at the end as prefix-tiles0.png (one per VRAM
+// bank), -bg.png, -window.png, -oam.png and -palettes.png; --stems writes each sound channel as
+// prefix-ch1.wav to prefix-ch4.wav. Cheats come from -c code (repeatable) and --cheats game.cht,
+// on top of those in a movie's save state. The ROM is patched with --patch file, or else the
+// .bps, .ups or .ips file beside it. --boot runs a boot ROM dump before the game.
+//   GameBoyEmulator --headless rom.gb [-m model] [-f frames] [--until cond] [-i input]
+//                   [--png out.png] [--wav out.wav] [--vram prefix] [--stems prefix]
+//                   [-c code] [--cheats file] [--patch file] [--boot file]
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::process;
+use cpu::apu;
+use cpu::cartridge;
+use cpu::cheats::{self, Cheat};
+use cpu::debugger::Condition;
+use cpu::joypad;
+use cpu::machine::GameBoy;
+use cpu::model::Model;
+use cpu::movie::{self, Movie, MovieError};
+use cpu::patch;
+use cpu::processor::FRAME_CYCLES;
+use cpu::savestate::crc32;
+use cpu::screenshot::Image;
+use cpu::testrom::DEFAULT_FRAMES;
+use cpu::vram::Layer;
+use cpu::wav;
+
+// frames run when there is neither -f nor --until
+const FRAMES: u64 = 600;
+const SAMPLE_RATE: u32 = 44100;
+
+fn usage() -> ! {
+    eprintln!("usage: GameBoyEmulator --headless <rom.gb> [-m dmg|mgb|sgb|cgb|...] [-f frames] [--until condition]");
+    eprintln!("                       [-i input.txt|movie.gbm|movie.bk2] [--png out.png] [--wav out.wav] [--vram prefix] [--stems prefix]");
+    eprintln!("                       [-c 01vvaaaa|ABC-DEF-GHI ...] [--cheats file.cht] [--patch file.ips|ups|bps] [--boot boot.bin]");
+    process::exit(2);
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("GameBoyEmulator: {}", message);
+    process::exit(1);
+}
+
+fn save(image: &Image, path: &str) {
+    image.save(Path::new(path)).unwrap_or_else(|e| fail(format!("cannot write {}: {}", path, e)));
+}
+
+// 16-bit stereo at SAMPLE_RATE
+fn write_wav(path: &str, samples: &[f32]) {
+    let result = File::create(path).and_then(|file| wav::write(&mut BufWriter::new(file), SAMPLE_RATE, 2, samples));
+    result.unwrap_or_else(|e| fail(format!("cannot write {}: {}", path, e)));
+}
+
+fn read(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| fail(format!("cannot read {}: {}", path, e)))
+}
+
+enum Input {
+    Script(Vec<(u64, u8)>), // (frame, buttons held from then on)
+    Movie(Movie),
+}
+
+fn load_input(path: &str, rom: &[u8], model: Model) -> Input {
+    let movie = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("gbm") => Movie::from_bytes(&read(path)),
+        Some("bk2") => Movie::from_bk2(&read(path), rom, model),
+        _ => {
+            let text = String::from_utf8_lossy(&read(path)).into_owned();
+            return Input::Script(joypad::parse_script(&text).unwrap_or_else(|e| fail(format!("{}: {}", path, e))));
+        }
+    };
+    Input::Movie(movie.unwrap_or_else(|e| fail(format!("{}: {}", path, e))))
+}
+
+// the arguments after --headless
+pub fn main(mut args: impl Iterator<Item = String>) -> ! {
+    let mut rom_path = None;
+    let mut model = None;
+    let (mut frames, mut until, mut input, mut png, mut wav_path) = (None, None, None, None, None);
+    let (mut vram, mut stems, mut cheat_list, mut patch_path, mut boot_path) = (None, None, Vec::new(), None, None);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "-m" => model = Some(value().parse::<Model>().unwrap_or_else(|_| usage())),
+            "-f" => frames = Some(value().parse::<u64>().unwrap_or_else(|_| usage())),
+            "--until" => until = Some(Condition::parse(&value()).unwrap_or_else(|e| fail(e))),
+            "-i" => input = Some(value()),
+            "--png" => png = Some(value()),
+            "--wav" => wav_path = Some(value()),
+            "--vram" => vram = Some(value()),
+            "--stems" => stems = Some(value()),
+            "-c" => cheat_list.push(Cheat::new(&value(), "").unwrap_or_else(|e| fail(e))),
+            "--cheats" => {
+                let path = value();
+                let text = String::from_utf8_lossy(&read(&path)).into_owned();
+                cheat_list.extend(cheats::parse_file(&text).unwrap_or_else(|e| fail(format!("{}: {}", path, e))));
+            }
+            "--patch" => patch_path = Some(value()),
+            "--boot" => boot_path = Some(value()),
+            _ if arg.starts_with('-') || rom_path.is_some() => usage(),
+            _ => rom_path = Some(arg),
+        }
+    }
+    let Some(rom_path) = rom_path else { usage() };
+    let patch_path = patch_path.or_else(|| patch::beside(Path::new(&rom_path)).map(|path| path.to_string_lossy().into_owned()));
+    let patch = patch_path.as_deref().map(read);
+    if let Some(path) = &patch_path {
+        eprintln!("GameBoyEmulator: patching with {}", path);
+    }
+    let (rom, header) = cartridge::load(&read(&rom_path), patch.as_deref()).unwrap_or_else(|e| fail(format!("{}: {}", rom_path, e)));
+    for problem in header.unsupported() {
+        eprintln!("GameBoyEmulator: warning: {}", problem);
+    }
+    // a movie brings its model and starting point, and only plays on the ROM it was made on
+    let input = input.map(|path| {
+        let loaded = load_input(&path, &rom, model.unwrap_or_default());
+        (path, loaded)
+    });
+    let movie = match &input {
+        Some((path, Input::Movie(movie))) => Some((path, movie)),
+        _ => None,
+    };
+    if let Some((path, movie)) = movie {
+        if let Some(model) = model && model != movie.model {
+            fail(format!("{}: the movie was recorded on {:?}, not {:?}", path, movie.model, model));
+        }
+        if boot_path.is_some() {
+            fail(format!("{}: a movie starts from its own state, --boot cannot be used with it", path));
+        }
+    }
+    let changes = match &input {
+        Some((_, Input::Script(changes))) => changes.clone(),
+        Some((_, Input::Movie(movie))) => movie.frames.iter().enumerate().map(|(frame, &buttons)| (frame as u64, buttons)).collect(),
+        None => Vec::new(),
+    };
+    let limit = frames.unwrap_or(match movie {
+        Some((_, movie)) => movie.frames.len() as u64,
+        None if until.is_some() => DEFAULT_FRAMES,
+        None => FRAMES,
+    });
+
+    let mut gb = match movie {
+        Some((path, movie)) => movie.boot(&rom).unwrap_or_else(|e| fail(format!("{}: {}", path, e))),
+        None => {
+            let boot_rom = boot_path.as_deref().map(read);
+            GameBoy::with_boot_rom(rom, model.unwrap_or_default(), boot_rom)
+                .unwrap_or_else(|e| fail(format!("{}: {}", boot_path.unwrap_or_default(), e)))
+        }
+    };
+    // added to what a movie's save state brought along, which it needs to stay in sync
+    for cheat in cheat_list {
+        gb.cheats().add(cheat);
+    }
+    if wav_path.is_some() || stems.is_some() {
+        gb.apu().sample_rate = Some(SAMPLE_RATE);
+    }
+    if stems.is_some() {
+        gb.apu().stems = Some(Vec::new());
+    }
+    let mut samples = Vec::new();
+    let mut next_change = changes.iter().peekable();
+    let mut reached = None;
+    // frames count from where the machine starts, which is not 0 in a movie from a save state
+    let first = gb.cpu.cycles / FRAME_CYCLES;
+    for frame in 0..limit {
+        while let Some(&(_, buttons)) = next_change.next_if(|&&(at, _)| at <= frame) {
+            gb.bus.set_buttons(buttons);
+        }
+        if gb.run_until((first + frame + 1) * FRAME_CYCLES, |gb| until.as_ref().is_some_and(|condition| condition.eval(&gb.cpu.regs))) {
+            reached = Some(frame);
+            break;
+        }
+        samples.extend(gb.apu().take_samples().into_iter().flatten());
+    }
+    samples.extend(gb.apu().take_samples().into_iter().flatten());
+
+    let image = gb.frame();
+    let ran = reached.map_or(limit, |frame| frame + 1);
+    println!("{} frames, framebuffer {:08X}", ran, crc32(&image.rgb()));
+    if let Some(path) = png {
+        save(&image, &path);
+    }
+    if let Some(prefix) = vram {
+        let (vram, palette) = (gb.vram(), gb.model.dmg_palette());
+        for bank in 0..vram.banks.len() {
+            save(&vram.tiles(bank, palette), &format!("{}-tiles{}.png", prefix, bank));
+        }
+        save(&vram.tilemap(Layer::Background, palette), &format!("{}-bg.png", prefix));
+        save(&vram.tilemap(Layer::Window, palette), &format!("{}-window.png", prefix));
+        save(&vram.oam_image(palette), &format!("{}-oam.png", prefix));
+        save(&vram.palette_image(palette), &format!("{}-palettes.png", prefix));
+    }
+    if let Some(path) = wav_path {
+        write_wav(&path, &samples);
+    }
+    if let Some(prefix) = stems {
+        let recorded = gb.apu().stems.take().unwrap_or_default();
+        for n in 0..4 {
+            write_wav(&format!("{}-ch{}.wav", prefix, n + 1), &apu::stem(&recorded, n));
+        }
+    }
+    match (until.is_some(), reached) {
+        (true, None) => fail(format!("condition not reached in {} frames", limit)),
+        (true, Some(frame)) => println!("condition reached in frame {} at PC {:04X}", frame, gb.cpu.regs.pc),
+        _ => {}
+    }
+    // a whole movie has to end on the frame it was recorded with
+    if let Some((path, movie)) = movie
+        && reached.is_none()
+        && ran == movie.frames.len() as u64
+        && let Some(expected) = movie.end_hash
+    {
+        let actual = movie::frame_hash(&gb);
+        if actual != expected {
+            fail(format!("{}: {}", path, MovieError::Desync { expected, actual }));
+        }
+    }
+    process::exit(0);
+}
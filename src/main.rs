@@ -1,3 +1,591 @@
+// Desktop frontend: the screen as a scaled texture under an egui menu bar, the keyboard as the
+// joypad (arrows, X = A, Z = B, Enter = Start, Backspace = Select, P pauses, R resets, F7 steps
+// one instruction while paused, holding ` rewinds, Ctrl+O opens a ROM). The buttons take effect
+// at the start of each frame, so rewinding can replay them. ROMs also open from the recent list
+// or by dropping them on the window. File > Save State and Load State keep the whole machine in
+// a file, game.state by default. The Debug menu opens the debugger panels (panels.rs). Cheats
+// are kept beside the ROM, game.gb's in game.cht, and so are patches: game.bps, game.ups or
+// game.ips is applied on load unless --patch names another. --boot runs a boot ROM dump before
+// each game.
+//   GameBoyEmulator [rom.gb|rom.zip] [-m dmg|mgb|sgb|cgb|...] [--patch file.ips|ups|bps] [--boot boot.bin]
+//   GameBoyEmulator --headless rom.gb ... (see headless.rs)
+mod gfx;
+mod headless;
+mod panels;
+mod recent;
+
+use std::env;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use egui::{Color32, Rect, pos2, vec2};
+use winit::application::ApplicationHandler;
+use winit::dpi::LogicalSize;
+use tinyfiledialogs::{MessageBoxIcon, YesNo};
+use winit::event::{ElementState, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::{Window, WindowId};
+use cpu::apu;
+use cpu::cartridge::{self, Header};
+use cpu::cheats;
+use cpu::joypad;
+use cpu::machine::GameBoy;
+use cpu::mmu::check_boot_rom;
+use cpu::model::Model;
+use cpu::patch;
+use cpu::processor::FRAME_CYCLES;
+use cpu::rewind::Rewind;
+use cpu::savestate::SaveState;
+use cpu::sgb::{FRAME_H, FRAME_W, SCREEN_H, SCREEN_W};
+use cpu::wav;
+use gfx::Gfx;
+use panels::{Debugger, Kind};
+use recent::Recent;
+
+const CLOCK: u64 = 4_194_304;
+const FRAME: Duration = Duration::from_nanos(FRAME_CYCLES * 1_000_000_000 / CLOCK);
+// how far emulation may fall behind before it drops frames instead of catching up
+const MAX_CATCH_UP: u32 = 4;
+const MENU_HEIGHT: u32 = 24;
+
+const KEYS: [(KeyCode, u8); 8] = [
+    (KeyCode::ArrowRight, joypad::RIGHT), (KeyCode::ArrowLeft, joypad::LEFT),
+    (KeyCode::ArrowUp, joypad::UP), (KeyCode::ArrowDown, joypad::DOWN),
+    (KeyCode::KeyX, joypad::A), (KeyCode::KeyZ, joypad::B),
+    (KeyCode::Backspace, joypad::SELECT), (KeyCode::Enter, joypad::START),
+];
+
+fn usage() -> ! {
+    eprintln!("usage: GameBoyEmulator [rom.gb|rom.zip] [-m dmg|mgb|sgb|cgb|...] [--patch file.ips|ups|bps] [--boot boot.bin]");
+    process::exit(2);
+}
+
+// tinyfiledialogs refuses messages with quotes in them
+fn dialog_text(text: &str) -> String {
+    text.replace(['"', '\''], "")
+}
+
+fn cheat_file(rom: &Path) -> PathBuf {
+    rom.with_extension("cht")
+}
+
+// the SGB models show the 256x224 frame with the border
+fn screen_size(model: Model) -> (u32, u32) {
+    match model.is_sgb() {
+        true => (FRAME_W as u32, FRAME_H as u32),
+        false => (SCREEN_W as u32, SCREEN_H as u32),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+// things the UI asks for that open dialogs, run after the frame is drawn
+enum Command {
+    Open,
+    Load(PathBuf),
+    SaveState,
+    LoadState,
+    SaveStems(u32, Vec<[[f32; 2]; 4]>), // sample rate, the stems from the Audio panel
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scaling {
+    Integer, // whole multiples of the screen, sharp on every display
+    Aspect,  // as large as fits, keeping the aspect ratio
+}
+
+struct Emulator {
+    gb: Option<GameBoy>,
+    model: Model,
+    header: Option<Header>,
+    rom_path: Option<PathBuf>,
+    patch: Option<PathBuf>, // from the command line, for the first ROM loaded
+    boot_rom: Option<Vec<u8>>,
+    recent: Recent,
+    command: Option<Command>,
+    title: String,
+    adapter: String, // shown in the Debug menu
+    paused: bool,
+    scaling: Scaling,
+    debugger: Debugger,
+    show_header: bool,
+    buttons: u8,
+    rewind: Rewind,
+    rewinding: bool, // ` held
+    screen: Option<egui::TextureHandle>,
+    next_frame: Instant,
+    window_scale: Option<u32>, // asked for from the View menu
+    quit: bool,
+}
+
+impl Emulator {
+    fn run_command(&mut self, command: Command) {
+        match command {
+            Command::Open => {
+                let dir = self.rom_path.as_ref().or(self.recent.paths.first())
+                    .and_then(|path| path.parent()).map(|dir| format!("{}/", dir.display())).unwrap_or_default();
+                let filter: (&[&str], &str) = (&["*.gb", "*.gbc", "*.sgb", "*.zip"], "Game Boy ROMs");
+                if let Some(path) = tinyfiledialogs::open_file_dialog("Open ROM", &dir, Some(filter)) {
+                    self.load(Path::new(&path));
+                }
+            }
+            Command::Load(path) => self.load(&path),
+            // game.gb's states default to game.state beside it
+            Command::SaveState => {
+                let (Some(gb), Some(rom_path)) = (&self.gb, &self.rom_path) else { return };
+                let default = rom_path.with_extension("state");
+                let Some(path) = tinyfiledialogs::save_file_dialog("Save state", &default.to_string_lossy()) else { return };
+                if let Err(e) = fs::write(&path, gb.save_state().to_bytes(gb.rom())) {
+                    let message = format!("Cannot write {}: {}", path, e);
+                    tinyfiledialogs::message_box_ok("Save state", &dialog_text(&message), MessageBoxIcon::Error);
+                }
+            }
+            Command::LoadState => {
+                let (Some(gb), Some(rom_path)) = (&mut self.gb, &self.rom_path) else { return };
+                let default = rom_path.with_extension("state");
+                let filter: (&[&str], &str) = (&["*.state"], "Save states");
+                let Some(path) = tinyfiledialogs::open_file_dialog("Load state", &default.to_string_lossy(), Some(filter)) else { return };
+                let result = fs::read(&path).map_err(|e| e.to_string())
+                    .and_then(|data| SaveState::from_bytes(&data, gb.rom()).map_err(|e| e.to_string()))
+                    .and_then(|state| gb.load_state(&state).map_err(|e| e.to_string()));
+                match result {
+                    Ok(()) => self.rewind = Rewind::default(),
+                    Err(e) => {
+                        let message = format!("Cannot load {}: {}", path, e);
+                        tinyfiledialogs::message_box_ok("Load state", &dialog_text(&message), MessageBoxIcon::Error);
+                    }
+                }
+            }
+            Command::SaveStems(rate, stems) => {
+                let Some(path) = tinyfiledialogs::save_file_dialog("Save channel stems", "stems.wav") else { return };
+                // stems.wav becomes stems-ch1.wav to stems-ch4.wav
+                let base = path.strip_suffix(".wav").unwrap_or(&path);
+                for n in 0..4 {
+                    let path = format!("{}-ch{}.wav", base, n + 1);
+                    let result = fs::File::create(&path).and_then(|file| wav::write(&mut BufWriter::new(file), rate, 2, &apu::stem(&stems, n)));
+                    if let Err(e) = result {
+                        let message = format!("Cannot write {}: {}", path, e);
+                        tinyfiledialogs::message_box_ok("Save channel stems", &dialog_text(&message), MessageBoxIcon::Error);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    // bad ROMs get an error dialog, ROMs that need what is not emulated yet ask first
+    fn load(&mut self, path: &Path) {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                self.recent.remove(path);
+                let message = format!("Cannot read {}: {}", path.display(), e);
+                tinyfiledialogs::message_box_ok("Open ROM", &dialog_text(&message), MessageBoxIcon::Error);
+                return;
+            }
+        };
+        let patch_path = self.patch.take().or_else(|| patch::beside(path));
+        let patch = match patch_path.as_ref().map(fs::read).transpose() {
+            Ok(patch) => patch,
+            Err(e) => {
+                let message = format!("Cannot read {}: {}", patch_path.unwrap_or_default().display(), e);
+                tinyfiledialogs::message_box_ok("Open ROM", &dialog_text(&message), MessageBoxIcon::Error);
+                return;
+            }
+        };
+        let (rom, header) = match cartridge::load(&data, patch.as_deref()) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                let message = format!("{} is not a ROM that can run: {}", file_name(path), e);
+                tinyfiledialogs::message_box_ok("Open ROM", &dialog_text(&message), MessageBoxIcon::Error);
+                return;
+            }
+        };
+        let problems = header.unsupported();
+        if !problems.is_empty() {
+            let message = format!("{} may not run:\n{}\n\nRun it anyway?", header.title,
+                problems.iter().map(|p| format!("- {}", p)).collect::<Vec<_>>().join("\n"));
+            if tinyfiledialogs::message_box_yes_no("Open ROM", &dialog_text(&message), MessageBoxIcon::Warning, YesNo::No) == YesNo::No {
+                return;
+            }
+        }
+
+        let mut gb = GameBoy::with_boot_rom(rom, self.model, self.boot_rom.clone()).expect("boot ROM checked at startup");
+        if let Ok(text) = fs::read_to_string(cheat_file(path)) {
+            match cheats::parse_file(&text) {
+                Ok(list) => gb.cheats().replace(list),
+                Err(e) => {
+                    let message = format!("The cheats in {} are ignored: {}", cheat_file(path).display(), e);
+                    tinyfiledialogs::message_box_ok("Open ROM", &dialog_text(&message), MessageBoxIcon::Warning);
+                }
+            }
+        }
+        self.gb = Some(gb);
+        self.rewind = Rewind::default();
+        self.title = match &patch_path {
+            Some(patch) => format!("GameBoyEmulator - {} + {}", file_name(path), file_name(patch)),
+            None => format!("GameBoyEmulator - {}", file_name(path)),
+        };
+        self.header = Some(header);
+        self.show_header = true;
+        self.recent.add(path);
+        self.rom_path = Some(path.to_path_buf());
+        self.paused = false;
+    }
+
+    // like the recent list, a file that cannot be written is reported but not worth a dialog
+    fn save_cheats(&mut self) {
+        let (Some(gb), Some(path)) = (&mut self.gb, &self.rom_path) else { return };
+        let (list, file) = (gb.cheats().list(), cheat_file(path));
+        let result = match list.is_empty() {
+            true if !file.exists() => Ok(()),
+            true => fs::remove_file(&file),
+            false => fs::write(&file, cheats::to_file(list)),
+        };
+        if let Err(e) = result {
+            eprintln!("GameBoyEmulator: cannot write {}: {}", file.display(), e);
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Some(gb) = &mut self.gb {
+            gb.reset();
+            self.rewind = Rewind::default();
+        }
+    }
+
+    fn step(&mut self) {
+        if let Some(gb) = self.gb.as_mut().filter(|_| self.paused) {
+            gb.bus.set_buttons(self.buttons);
+            gb.step();
+        }
+    }
+
+    fn key(&mut self, code: KeyCode, pressed: bool, repeat: bool, ctrl: bool) {
+        if let Some(&(_, button)) = KEYS.iter().find(|(key, _)| *key == code) {
+            match pressed {
+                true => self.buttons |= button,
+                false => self.buttons &= !button,
+            }
+            return;
+        }
+        if code == KeyCode::Backquote {
+            self.rewinding = pressed;
+            return;
+        }
+        if !pressed || repeat {
+            return;
+        }
+        match code {
+            KeyCode::KeyO if ctrl => self.command = Some(Command::Open),
+            KeyCode::KeyP => self.paused = !self.paused,
+            KeyCode::KeyR => self.reset(),
+            KeyCode::F7 => self.step(),
+            _ => {}
+        }
+    }
+
+    // runs the frames that are due, or goes back one frame for each while rewinding, pausing at
+    // breakpoints; true when the screen changed
+    fn run_due(&mut self, now: Instant) -> bool {
+        let Some(gb) = self.gb.as_mut().filter(|_| !self.paused) else {
+            self.next_frame = now + FRAME;
+            return false;
+        };
+        self.debugger.hit = None;
+        let mut ran = 0;
+        while self.next_frame <= now && ran < MAX_CATCH_UP {
+            self.next_frame += FRAME;
+            if self.rewinding {
+                if !self.rewind.step_back(1, gb) {
+                    break;
+                }
+                ran += 1;
+                continue;
+            }
+            gb.bus.set_buttons(self.buttons);
+            self.rewind.record(gb);
+            let end = (gb.cpu.cycles / FRAME_CYCLES + 1) * FRAME_CYCLES;
+            if gb.run_until(end, |gb| self.debugger.breaks(gb)) {
+                self.debugger.hit = Some(gb.cpu.regs.pc);
+                self.paused = true;
+                return true;
+            }
+            ran += 1;
+        }
+        if self.next_frame <= now {
+            self.next_frame = now + FRAME;
+        }
+        ran > 0
+    }
+
+    fn ui(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            egui::MenuBar::new().ui(ui, |ui| self.menu_bar(ui));
+        });
+        self.debugger.show(ctx, self.gb.as_mut(), self.paused);
+        if let Some((rate, stems)) = self.debugger.stems.take() {
+            self.command = Some(Command::SaveStems(rate, stems));
+        }
+        if std::mem::take(&mut self.debugger.cheats_changed) {
+            self.save_cheats();
+        }
+        egui::CentralPanel::default()
+            .frame(egui::Frame::NONE.fill(Color32::BLACK))
+            .show(ctx, |ui| self.draw_screen(ui));
+
+        let mut open = self.show_header;
+        if let Some(header) = &self.header {
+            egui::Window::new("Cartridge").open(&mut open).resizable(false).show(ctx, |ui| {
+                ui.monospace(header.to_string());
+                for problem in header.unsupported() {
+                    ui.colored_label(Color32::YELLOW, problem);
+                }
+            });
+        }
+        self.show_header = open;
+    }
+
+    fn menu_bar(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("File", |ui| {
+            if ui.button("Open ROM... (Ctrl+O)").clicked() {
+                self.command = Some(Command::Open);
+                ui.close();
+            }
+            ui.add_enabled_ui(!self.recent.paths.is_empty(), |ui| {
+                ui.menu_button("Open Recent", |ui| {
+                    for path in &self.recent.paths {
+                        if ui.button(file_name(path)).on_hover_text(path.display().to_string()).clicked() {
+                            self.command = Some(Command::Load(path.clone()));
+                            ui.close();
+                        }
+                    }
+                    ui.separator();
+                    if ui.button("Clear").clicked() {
+                        self.recent.clear();
+                        ui.close();
+                    }
+                });
+            });
+            ui.add_enabled_ui(self.header.is_some(), |ui| ui.checkbox(&mut self.show_header, "Cartridge info"));
+            ui.separator();
+            ui.add_enabled_ui(self.gb.is_some(), |ui| {
+                if ui.button("Save State...").clicked() {
+                    self.command = Some(Command::SaveState);
+                    ui.close();
+                }
+                if ui.button("Load State...").clicked() {
+                    self.command = Some(Command::LoadState);
+                    ui.close();
+                }
+            });
+            ui.separator();
+            if ui.button("Quit").clicked() {
+                self.quit = true;
+            }
+        });
+        ui.menu_button("Emulation", |ui| {
+            ui.add_enabled_ui(self.gb.is_some(), |ui| {
+                if ui.checkbox(&mut self.paused, "Pause (P)").clicked() {
+                    ui.close();
+                }
+                if ui.button("Reset (R)").clicked() {
+                    self.reset();
+                    ui.close();
+                }
+                ui.add_enabled_ui(self.paused, |ui| {
+                    if ui.button("Step instruction (F7)").clicked() {
+                        self.step();
+                    }
+                });
+                ui.separator();
+                if let Some(panel) = self.debugger.panels.iter_mut().find(|panel| panel.kind == Kind::Cheats) {
+                    ui.checkbox(&mut panel.open, "Cheats");
+                }
+            });
+        });
+        ui.menu_button("View", |ui| {
+            ui.radio_value(&mut self.scaling, Scaling::Integer, "Integer scaling");
+            ui.radio_value(&mut self.scaling, Scaling::Aspect, "Fit to window");
+            ui.separator();
+            for scale in 1..=4 {
+                if ui.button(format!("Window {}x", scale)).clicked() {
+                    self.window_scale = Some(scale);
+                    ui.close();
+                }
+            }
+        });
+        ui.menu_button("Debug", |ui| {
+            for panel in self.debugger.panels.iter_mut().filter(|panel| panel.kind != Kind::Cheats) {
+                ui.checkbox(&mut panel.open, panel.kind.title());
+            }
+            ui.separator();
+            ui.label(&self.adapter);
+        });
+    }
+
+    fn draw_screen(&mut self, ui: &mut egui::Ui) {
+        let Some(gb) = &self.gb else {
+            ui.centered_and_justified(|ui| {
+                ui.colored_label(Color32::GRAY, "Open a ROM from the File menu or drop one here");
+            });
+            return;
+        };
+        let frame = gb.frame();
+        let image = egui::ColorImage::from_rgb([frame.width, frame.height], &frame.rgb());
+        let texture = match &mut self.screen {
+            Some(texture) => {
+                texture.set(image, egui::TextureOptions::NEAREST);
+                texture
+            }
+            None => self.screen.insert(ui.ctx().load_texture("screen", image, egui::TextureOptions::NEAREST)),
+        };
+
+        // scale in physical pixels so integer scaling stays sharp on HiDPI displays
+        let area = ui.max_rect();
+        let ppp = ui.ctx().pixels_per_point();
+        let (width, height) = (frame.width as f32, frame.height as f32);
+        let fit = (area.width() * ppp / width).min(area.height() * ppp / height);
+        let scale = match self.scaling {
+            Scaling::Integer => fit.floor().max(1.0),
+            Scaling::Aspect => fit,
+        };
+        let size = vec2(width, height) * scale / ppp;
+        let min = ((area.center() - size / 2.0) * ppp).round() / ppp;
+        let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+        ui.painter().image(texture.id(), Rect::from_min_size(min, size), uv, Color32::WHITE);
+    }
+}
+
+struct App {
+    gfx: Option<Gfx>,
+    emulator: Emulator,
+    ctrl: bool,
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.gfx.is_some() {
+            return;
+        }
+        let (width, height) = screen_size(self.emulator.model);
+        let attributes = Window::default_attributes()
+            .with_title(&self.emulator.title)
+            .with_inner_size(LogicalSize::new(width * 3, height * 3 + MENU_HEIGHT))
+            .with_min_inner_size(LogicalSize::new(width, height + MENU_HEIGHT));
+        let gfx = event_loop.create_window(attributes).map_err(|e| e.to_string()).and_then(|window| Gfx::new(Arc::new(window)));
+        match gfx {
+            Ok(gfx) => {
+                let info = &gfx.adapter;
+                self.emulator.adapter = format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type);
+                self.gfx = Some(gfx);
+            }
+            Err(e) => {
+                eprintln!("GameBoyEmulator: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        let Some(gfx) = &mut self.gfx else { return };
+        let used = gfx.handle_event(&event);
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::ModifiersChanged(modifiers) => self.ctrl = modifiers.state().control_key(),
+            WindowEvent::KeyboardInput { event, .. } if !used => {
+                if let PhysicalKey::Code(code) = event.physical_key {
+                    self.emulator.key(code, event.state == ElementState::Pressed, event.repeat, self.ctrl);
+                }
+            }
+            WindowEvent::DroppedFile(path) => self.emulator.command = Some(Command::Load(path)),
+            WindowEvent::RedrawRequested => {
+                let emulator = &mut self.emulator;
+                if let Err(e) = gfx.render(|ctx| emulator.ui(ctx)) {
+                    eprintln!("GameBoyEmulator: {}", e);
+                    event_loop.exit();
+                }
+                if let Some(command) = emulator.command.take() {
+                    emulator.run_command(command);
+                    gfx.window.set_title(&emulator.title);
+                    gfx.window.request_redraw();
+                }
+                if let Some(scale) = emulator.window_scale.take() {
+                    let (width, height) = screen_size(emulator.model);
+                    let _ = gfx.window.request_inner_size(LogicalSize::new(width * scale, height * scale + MENU_HEIGHT));
+                }
+                if emulator.quit {
+                    event_loop.exit();
+                }
+                return;
+            }
+            _ => {}
+        }
+        gfx.window.request_redraw();
+    }
+
+    // paces emulation at the Game Boy's 59.73 frames per second
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let now = Instant::now();
+        if self.emulator.run_due(now)
+            && let Some(gfx) = &self.gfx
+        {
+            gfx.window.request_redraw();
+        }
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.emulator.next_frame));
+    }
+}
+
 fn main() {
-    println!("Hello, world!");
+    let mut args = env::args().skip(1).peekable();
+    if args.next_if_eq("--headless").is_some() {
+        headless::main(args);
+    }
+    let (mut rom_path, mut patch, mut boot_rom) = (None, None, None);
+    let mut model = Model::DMG;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-m" => model = args.next().and_then(|m| m.parse().ok()).unwrap_or_else(|| usage()),
+            "--patch" => patch = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--boot" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let data = fs::read(&path).map_err(|e| e.to_string())
+                    .and_then(|data| check_boot_rom(&data).map(|()| data).map_err(|e| e.to_string()));
+                boot_rom = Some(data.unwrap_or_else(|e| {
+                    eprintln!("GameBoyEmulator: {}: {}", path, e);
+                    process::exit(1);
+                }));
+            }
+            _ if arg.starts_with('-') || rom_path.is_some() => usage(),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    let event_loop = EventLoop::new().unwrap_or_else(|e| {
+        eprintln!("GameBoyEmulator: {}", e);
+        process::exit(1);
+    });
+    let mut app = App {
+        gfx: None,
+        emulator: Emulator {
+            gb: None, model, header: None, rom_path: None, patch, boot_rom, recent: Recent::load(),
+            // the command line ROM loads like any other once the window is up
+            command: rom_path.map(|path| Command::Load(PathBuf::from(path))),
+            title: "GameBoyEmulator".to_string(), adapter: String::new(), paused: false, scaling: Scaling::Integer,
+            debugger: Debugger::new(), show_header: false, buttons: 0, rewind: Rewind::default(), rewinding: false,
+            screen: None, next_frame: Instant::now(),
+            window_scale: None, quit: false,
+        },
+        ctrl: false,
+    };
+    if let Err(e) = event_loop.run_app(&mut app) {
+        eprintln!("GameBoyEmulator: {}", e);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,943 @@
+// Debugger panels in the main window: registers and flags, the disassembly around PC with
+// breakpoint toggles, a hex editor over the bus or one ROM bank, the VRAM viewer (tiles, maps,
+// OAM and palettes from cpu::vram, described under the pointer) and the audio view (each
+// channel's state and scope, mute and solo, the wave RAM, stems) and RAM search with a watch
+// list. The cheat list lives here too though it is opened from the Emulation menu. Each panel
+// docks to a side of the window or floats. Registers can only be edited while paused, memory at
+// any time.
+use std::collections::VecDeque;
+use egui::{Color32, RichText, Sense, Stroke, TextStyle, pos2, vec2};
+use cpu::apu::{Apu, NR10, NR32, NR43, SCOPE_LEN, WAVE_RAM};
+use cpu::cheats::Cheat;
+use cpu::search::{self, Comparison, Search, Size};
+use cpu::debugger::Location;
+use cpu::disassembler::{self, Line, Syntax};
+use cpu::joypad::BUTTONS;
+use cpu::lcd::{LCDC, LY, STAT};
+use cpu::machine::GameBoy;
+use cpu::mmu::Mmu;
+use cpu::processor::{FRAME_CYCLES, IE, IF};
+use cpu::registers::CpuFlag;
+use cpu::screenshot::Image;
+use cpu::vram::{Layer, OAM_COLUMNS, SHEET_COLUMNS, SWATCH, Vram};
+
+const BANK_SIZE: usize = 0x4000;
+// instructions shown before and after the address the disassembly is centred on
+const LINES_BEFORE: usize = 32;
+const LINES_AFTER: usize = 64;
+const CHANNEL_NAMES: [&str; 4] = ["1 Square + sweep", "2 Square", "3 Wave", "4 Noise"];
+// stems are recorded at the output rate, or this one when there is no audio output
+const STEM_RATE: u32 = 44100;
+// scope points drawn, the older half of the history is searched for a rising edge to start at
+const SCOPE_POINTS: usize = SCOPE_LEN / 2;
+const SIZES: [Size; 3] = [Size::Byte, Size::WordLe, Size::WordBe];
+// the last two take the number typed next to them
+const COMPARISONS: [&str; 6] = ["equal", "changed", "increased", "decreased", "changed by", "equal to"];
+// more candidates than this are only counted
+const MAX_LISTED: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Registers,
+    Disassembly,
+    Memory,
+    Vram,
+    Audio,
+    Cheats,
+    Search,
+}
+
+impl Kind {
+    pub fn title(self) -> &'static str {
+        match self {
+            Kind::Registers => "Registers",
+            Kind::Disassembly => "Disassembly",
+            Kind::Memory => "Memory",
+            Kind::Vram => "VRAM",
+            Kind::Audio => "Audio",
+            Kind::Cheats => "Cheats",
+            Kind::Search => "RAM search",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dock {
+    Left,
+    Right,
+    Bottom,
+    Floating,
+}
+
+const DOCKS: [(Dock, &str); 4] = [(Dock::Left, "Left"), (Dock::Right, "Right"), (Dock::Bottom, "Bottom"), (Dock::Floating, "Floating")];
+
+pub struct Panel {
+    pub kind: Kind,
+    pub open: bool,
+    pub dock: Dock,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum View {
+    Tiles(usize), // bank
+    Map(Layer),
+    Oam,
+    Palettes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Region {
+    Bus, // the 64KB the CPU sees, ROM included
+    Rom, // one 16KB bank of the cartridge, read-only
+}
+
+pub struct Debugger {
+    pub panels: [Panel; 7],
+    pub breakpoints: Vec<Location>,
+    pub hit: Option<u16>, // PC of the breakpoint emulation stopped at
+    follow_pc: bool,
+    center: u16, // disassembly address when not following PC
+    last_pc: Option<u16>,
+    jumped: bool, // scroll to `center` once
+    disassembly_goto: String,
+    new_breakpoint: String,
+    region: Region,
+    bank: usize,
+    cursor: Option<usize>, // selected byte, an offset into the region
+    editing: bool,
+    focus: bool,
+    edit: String,
+    goto: String,
+    search: String,
+    status: String,
+    scroll_to: Option<usize>, // memory row
+    view: View,
+    zoom: usize,
+    texture: Option<egui::TextureHandle>,
+    recording: Option<Option<u32>>, // the sample rate before stems were started
+    pub stems: Option<(u32, Vec<[[f32; 2]; 4]>)>, // recorded, to be saved
+    wave_text: String,
+    cheat_code: String,
+    cheat_name: String,
+    cheat_error: String,
+    pub cheats_changed: bool, // to be saved to the ROM's cheat file
+    ram_search: Option<Search>,
+    search_size: Size,
+    comparison: usize,
+    search_value: String,
+    search_status: String,
+    watches: Vec<(u16, Size)>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            panels: [
+                Panel { kind: Kind::Registers, open: false, dock: Dock::Right },
+                Panel { kind: Kind::Disassembly, open: false, dock: Dock::Left },
+                Panel { kind: Kind::Memory, open: false, dock: Dock::Bottom },
+                Panel { kind: Kind::Vram, open: false, dock: Dock::Floating },
+                Panel { kind: Kind::Audio, open: false, dock: Dock::Floating },
+                Panel { kind: Kind::Cheats, open: false, dock: Dock::Floating },
+                Panel { kind: Kind::Search, open: false, dock: Dock::Floating },
+            ],
+            breakpoints: Vec::new(), hit: None, follow_pc: true, center: 0, last_pc: None,
+            jumped: false, disassembly_goto: String::new(),
+            new_breakpoint: String::new(), region: Region::Bus, bank: 1, cursor: None, editing: false,
+            focus: false, edit: String::new(), goto: String::new(), search: String::new(),
+            status: String::new(), scroll_to: None, view: View::Tiles(0), zoom: 2, texture: None,
+            recording: None, stems: None, wave_text: String::new(), cheat_code: String::new(),
+            cheat_name: String::new(), cheat_error: String::new(), cheats_changed: false,
+            ram_search: None, search_size: Size::Byte, comparison: 0, search_value: String::new(),
+            search_status: String::new(), watches: Vec::new(),
+        }
+    }
+
+    // whether emulation should stop before the instruction at PC
+    pub fn breaks(&self, gb: &GameBoy) -> bool {
+        let pc = gb.cpu.regs.pc;
+        self.breakpoints.iter().any(|b| b.matches(&gb.bus, pc))
+    }
+
+    // docked panels must be shown before the central panel
+    pub fn show(&mut self, ctx: &egui::Context, gb: Option<&mut GameBoy>, paused: bool) {
+        let Some(gb) = gb else {
+            for panel in self.panels.iter_mut().filter(|panel| panel.open && panel.dock == Dock::Floating) {
+                egui::Window::new(panel.kind.title()).open(&mut panel.open).show(ctx, |ui| ui.label("No ROM loaded"));
+            }
+            return;
+        };
+        for i in 0..self.panels.len() {
+            let Panel { kind, open, dock } = self.panels[i];
+            if !open {
+                continue;
+            }
+            let (mut close, mut window_open, mut new_dock) = (false, true, dock);
+            let mut contents = |ui: &mut egui::Ui| {
+                ui.horizontal(|ui| {
+                    if dock != Dock::Floating {
+                        ui.strong(kind.title());
+                    }
+                    egui::ComboBox::from_id_salt((kind.title(), "dock"))
+                        .selected_text(DOCKS.iter().find(|(d, _)| *d == dock).map_or("", |(_, name)| *name))
+                        .show_ui(ui, |ui| {
+                            for (d, name) in DOCKS {
+                                ui.selectable_value(&mut new_dock, d, name);
+                            }
+                        });
+                    if dock != Dock::Floating && ui.small_button("x").clicked() {
+                        close = true;
+                    }
+                });
+                ui.separator();
+                match kind {
+                    Kind::Registers => registers(ui, gb, paused),
+                    Kind::Disassembly => self.disassembly(ui, gb),
+                    Kind::Memory => self.memory(ui, gb),
+                    Kind::Vram => self.vram(ui, gb),
+                    Kind::Audio => self.audio(ui, gb),
+                    Kind::Cheats => self.cheats(ui, gb),
+                    Kind::Search => self.ram_search(ui, gb),
+                }
+            };
+            match dock {
+                Dock::Left => { egui::SidePanel::left(kind.title()).resizable(true).show(ctx, |ui| contents(ui)); }
+                Dock::Right => { egui::SidePanel::right(kind.title()).resizable(true).show(ctx, |ui| contents(ui)); }
+                Dock::Bottom => {
+                    egui::TopBottomPanel::bottom(kind.title()).resizable(true).default_height(200.0).show(ctx, |ui| contents(ui));
+                }
+                Dock::Floating => { egui::Window::new(kind.title()).open(&mut window_open).show(ctx, |ui| contents(ui)); }
+            }
+            self.panels[i].open = window_open && !close;
+            self.panels[i].dock = new_dock;
+        }
+    }
+
+    fn location(gb: &GameBoy, addr: u16) -> Location {
+        Location { bank: (addr < 0x8000).then(|| gb.bus.rom_bank(addr)), addr }
+    }
+
+    fn toggle_breakpoint(&mut self, location: Location) {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|b| *b != location);
+        if self.breakpoints.len() == before {
+            self.breakpoints.push(location);
+        }
+    }
+
+    fn disassembly(&mut self, ui: &mut egui::Ui, gb: &mut GameBoy) {
+        let pc = gb.cpu.regs.pc;
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.follow_pc, "Follow PC");
+            let goto = ui.add(egui::TextEdit::singleline(&mut self.disassembly_goto).hint_text("go to").desired_width(60.0));
+            if goto.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                && let Some(location) = Location::parse(self.disassembly_goto.trim())
+            {
+                (self.center, self.follow_pc, self.jumped) = (location.addr, false, true);
+            }
+            if let Some(hit) = self.hit {
+                ui.colored_label(Color32::LIGHT_RED, format!("breakpoint at {:04X}", hit));
+            }
+        });
+        ui.collapsing(format!("Breakpoints ({})", self.breakpoints.len()), |ui| {
+            let mut remove = None;
+            for (i, b) in self.breakpoints.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui.small_button("x").clicked() {
+                        remove = Some(i);
+                    }
+                    match b.bank {
+                        Some(bank) => ui.monospace(format!("{:02X}:{:04X}", bank, b.addr)),
+                        None => ui.monospace(format!("{:04X}", b.addr)),
+                    };
+                });
+            }
+            if let Some(i) = remove {
+                self.breakpoints.remove(i);
+            }
+            ui.horizontal(|ui| {
+                let add = ui.add(egui::TextEdit::singleline(&mut self.new_breakpoint).hint_text("bank:addr").desired_width(80.0));
+                let entered = add.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (entered || ui.button("Add").clicked())
+                    && let Some(location) = Location::parse(self.new_breakpoint.trim())
+                {
+                    self.breakpoints.retain(|b| *b != location);
+                    self.breakpoints.push(location);
+                    self.new_breakpoint.clear();
+                }
+                if ui.button("Clear").clicked() {
+                    self.breakpoints.clear();
+                }
+            });
+        });
+        ui.separator();
+
+        let center = if self.follow_pc { pc } else { self.center };
+        let scroll = (self.follow_pc && self.last_pc != Some(pc)) || std::mem::take(&mut self.jumped);
+        self.last_pc = Some(pc);
+        let lines = lines_around(&gb.bus, center, LINES_BEFORE, LINES_AFTER);
+        egui::ScrollArea::vertical().id_salt("disassembly").auto_shrink(false).show(ui, |ui| {
+            for line in &lines {
+                ui.horizontal(|ui| {
+                    let location = Debugger::location(gb, line.addr);
+                    let set = self.breakpoints.iter().any(|b| b.matches(&gb.bus, line.addr));
+                    let dot = match set {
+                        true => RichText::new("●").color(Color32::RED),
+                        false => RichText::new("○").color(Color32::DARK_GRAY),
+                    };
+                    if ui.add(egui::Button::new(dot).frame(false)).on_hover_text("Toggle breakpoint").clicked() {
+                        self.toggle_breakpoint(location);
+                    }
+                    let bytes: String = (0..line.len).map(|i| format!("{:02X}", gb.bus.read(line.addr.wrapping_add(i)))).collect();
+                    let addr = match location.bank {
+                        Some(bank) => format!("{:02X}:{:04X}", bank, line.addr),
+                        None => format!("   {:04X}", line.addr),
+                    };
+                    let mut text = RichText::new(format!("{} {:<6} {}", addr, bytes, line.text)).monospace();
+                    if line.addr == pc {
+                        text = text.color(Color32::YELLOW);
+                    }
+                    let label = ui.label(text);
+                    if scroll && line.addr == center {
+                        label.scroll_to_me(Some(egui::Align::Center));
+                    }
+                });
+            }
+        });
+    }
+
+    fn region(&self, gb: &GameBoy) -> (usize, String) {
+        match self.region {
+            Region::Bus => (0x10000, "bus".to_string()),
+            Region::Rom => (BANK_SIZE.min(gb.rom().len().saturating_sub(self.bank * BANK_SIZE)), format!("ROM bank {}", self.bank)),
+        }
+    }
+
+    fn byte(&self, gb: &GameBoy, offset: usize) -> u8 {
+        match self.region {
+            Region::Bus => gb.memory().mem[offset],
+            Region::Rom => gb.rom()[self.bank * BANK_SIZE + offset],
+        }
+    }
+
+    // where `offset` shows up on the bus: bank 0 at 0000, the others at 4000
+    fn address(&self, offset: usize) -> String {
+        match self.region {
+            Region::Bus => format!("{:04X}", offset),
+            Region::Rom => format!("{:02X}:{:04X}", self.bank, offset + if self.bank == 0 { 0 } else { BANK_SIZE }),
+        }
+    }
+
+    fn select(&mut self, offset: usize) {
+        (self.cursor, self.editing, self.scroll_to) = (Some(offset), false, Some(offset / 16));
+    }
+
+    fn memory(&mut self, ui: &mut egui::Ui, gb: &mut GameBoy) {
+        let banks = gb.rom().len().div_ceil(BANK_SIZE).max(1);
+        ui.horizontal(|ui| {
+            let region = self.region;
+            egui::ComboBox::from_id_salt("memory region")
+                .selected_text(match region { Region::Bus => "Bus", Region::Rom => "ROM" })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.region, Region::Bus, "Bus");
+                    ui.selectable_value(&mut self.region, Region::Rom, "ROM");
+                });
+            if self.region == Region::Rom {
+                ui.add(egui::DragValue::new(&mut self.bank).range(0..=banks - 1).prefix("bank "));
+            }
+            if self.region != region {
+                self.cursor = None;
+            }
+
+            let goto = ui.add(egui::TextEdit::singleline(&mut self.goto).hint_text("go to").desired_width(60.0));
+            if goto.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                match Location::parse(self.goto.trim()) {
+                    Some(Location { bank: Some(bank), addr }) if bank < banks && addr < 0x8000 => {
+                        (self.region, self.bank) = (Region::Rom, bank);
+                        let start = if bank == 0 { 0 } else { BANK_SIZE };
+                        self.select((addr as usize).saturating_sub(start) % BANK_SIZE);
+                    }
+                    Some(Location { bank: None, addr }) => {
+                        self.region = Region::Bus;
+                        self.select(addr as usize);
+                    }
+                    _ => self.status = format!("bad address {}", self.goto),
+                }
+            }
+
+            let search = ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("3E 01 or \"text\"").desired_width(100.0));
+            if (search.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))) || ui.button("Find next").clicked() {
+                self.find(gb);
+            }
+            ui.label(&self.status);
+        });
+        ui.separator();
+
+        let (len, _) = self.region(gb);
+        let editable = self.region == Region::Bus;
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let mut area = egui::ScrollArea::vertical().id_salt("memory").auto_shrink(false);
+        if let Some(row) = self.scroll_to.take() {
+            area = area.vertical_scroll_offset(row as f32 * (row_height + ui.spacing().item_spacing.y));
+        }
+        area.show_rows(ui, row_height, len.div_ceil(16), |ui, rows| {
+            for row in rows {
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.0;
+                    ui.monospace(self.address(row * 16));
+                    let columns = (row * 16..len.min(row * 16 + 16)).collect::<Vec<_>>();
+                    for &offset in &columns {
+                        self.cell(ui, gb, offset, editable);
+                    }
+                    let ascii: String = columns.iter().map(|&offset| match self.byte(gb, offset) {
+                        b @ 0x20..=0x7E => b as char,
+                        _ => '.',
+                    }).collect();
+                    ui.monospace(ascii);
+                });
+            }
+        });
+    }
+
+    // a byte of the hex view, or its editor while it is being changed
+    fn cell(&mut self, ui: &mut egui::Ui, gb: &mut GameBoy, offset: usize, editable: bool) {
+        let value = self.byte(gb, offset);
+        if self.editing && self.cursor == Some(offset) {
+            let edit = ui.add(egui::TextEdit::singleline(&mut self.edit).font(TextStyle::Monospace).char_limit(2).desired_width(16.0));
+            if std::mem::take(&mut self.focus) {
+                edit.request_focus();
+            }
+            if edit.lost_focus() {
+                if let Ok(value) = u8::from_str_radix(self.edit.trim(), 16) {
+                    gb.memory_mut().mem[offset] = value;
+                }
+                self.editing = false;
+                // Enter moves on to the next byte
+                if ui.input(|i| i.key_pressed(egui::Key::Enter)) && offset < 0xFFFF {
+                    self.cursor = Some(offset + 1);
+                    self.edit = format!("{:02X}", self.byte(gb, offset + 1));
+                    (self.editing, self.focus) = (true, true);
+                }
+            }
+            return;
+        }
+        let mut text = RichText::new(format!("{:02X}", value)).monospace();
+        if self.cursor == Some(offset) {
+            text = text.background_color(ui.visuals().selection.bg_fill);
+        }
+        let label = ui.add(egui::Label::new(text).sense(Sense::click())).on_hover_text(self.address(offset));
+        if label.clicked() {
+            self.cursor = Some(offset);
+            if editable {
+                self.edit = format!("{:02X}", value);
+                (self.editing, self.focus) = (true, true);
+            }
+        }
+    }
+
+    // the next match after the cursor, wrapping around the region
+    fn find(&mut self, gb: &GameBoy) {
+        let pattern = match parse_pattern(&self.search) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                self.status = e;
+                return;
+            }
+        };
+        let (len, name) = self.region(gb);
+        let data: Vec<u8> = (0..len).map(|offset| self.byte(gb, offset)).collect();
+        let from = self.cursor.map_or(0, |offset| offset + 1);
+        let found = (from..len).chain(0..from.min(len)).find(|&start| data[start..].starts_with(&pattern));
+        match found {
+            Some(offset) => {
+                self.status = format!("found at {}", self.address(offset));
+                self.select(offset);
+            }
+            None => self.status = format!("not found in {}", name),
+        }
+    }
+
+    fn vram(&mut self, ui: &mut egui::Ui, gb: &GameBoy) {
+        let (vram, palette) = (gb.vram(), gb.model.dmg_palette());
+        ui.horizontal_wrapped(|ui| {
+            for bank in 0..vram.banks.len() {
+                ui.selectable_value(&mut self.view, View::Tiles(bank), format!("Tiles {}", bank));
+            }
+            ui.selectable_value(&mut self.view, View::Map(Layer::Background), "BG map");
+            ui.selectable_value(&mut self.view, View::Map(Layer::Window), "Window map");
+            ui.selectable_value(&mut self.view, View::Oam, "OAM");
+            ui.selectable_value(&mut self.view, View::Palettes, "Palettes");
+            ui.add(egui::DragValue::new(&mut self.zoom).range(1..=4).suffix("x"));
+        });
+        if matches!(self.view, View::Tiles(bank) if bank >= vram.banks.len()) {
+            self.view = View::Tiles(0);
+        }
+        let image = match self.view {
+            View::Tiles(bank) => vram.tiles(bank, palette),
+            View::Map(layer) => vram.tilemap(layer, palette),
+            View::Oam => vram.oam_image(palette),
+            View::Palettes => vram.palette_image(palette),
+        };
+        match self.view {
+            View::Tiles(_) if vram.banks.len() == 1 => { ui.weak("VRAM bank 1 is only on the CGB models"); }
+            View::Palettes if vram.bg_palettes.is_none() => { ui.weak("BGP, OBP0, OBP1; the colour palette RAM is only on the CGB models"); }
+            _ => {}
+        }
+
+        let pixels = egui::ColorImage::from_rgb([image.width, image.height], &image.rgb());
+        let texture = match &mut self.texture {
+            Some(texture) => {
+                texture.set(pixels, egui::TextureOptions::NEAREST);
+                texture
+            }
+            None => self.texture.insert(ui.ctx().load_texture("vram", pixels, egui::TextureOptions::NEAREST)),
+        };
+        let zoom = self.zoom as f32;
+        let size = egui::vec2(image.width as f32, image.height as f32) * zoom;
+        egui::ScrollArea::both().id_salt("vram").show(ui, |ui| {
+            let response = ui.add(egui::Image::new((texture.id(), size)).sense(Sense::hover()));
+            if let Some(pos) = response.hover_pos() {
+                let at = (pos - response.rect.min) / zoom;
+                let (x, y) = ((at.x as usize).min(image.width - 1), (at.y as usize).min(image.height - 1));
+                response.on_hover_text_at_pointer(describe(self.view, &vram, &image, x, y));
+            }
+        });
+    }
+
+    fn audio(&mut self, ui: &mut egui::Ui, gb: &mut GameBoy) {
+        let apu = gb.apu();
+        apu.scope.get_or_insert_default();
+        ui.horizontal(|ui| {
+            match self.recording {
+                None => if ui.button("Record stems").clicked() {
+                    self.recording = Some(apu.sample_rate);
+                    apu.sample_rate.get_or_insert(STEM_RATE);
+                    apu.stems = Some(Vec::new());
+                }
+                Some(rate_before) => {
+                    let rate = apu.sample_rate.unwrap_or(STEM_RATE);
+                    let recorded = apu.stems.as_ref().map_or(0, |stems| stems.len());
+                    ui.colored_label(Color32::RED, format!("● {:.1}s", recorded as f32 / rate as f32));
+                    if ui.button("Stop and save...").clicked() {
+                        let stems = apu.stems.take().unwrap_or_default();
+                        if rate_before.is_none() {
+                            apu.sample_rate = None;
+                            apu.take_samples();
+                        }
+                        self.recording = None;
+                        self.stems = Some((rate, stems));
+                    }
+                }
+            }
+            if !apu.powered() {
+                ui.weak("APU off (NR52)");
+            }
+        });
+        for (n, name) in CHANNEL_NAMES.iter().enumerate() {
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.strong(*name);
+                let channel = apu.channels[n];
+                ui.label(if channel.enabled { "on" } else if channel.dac { "off" } else { "DAC off" });
+                ui.checkbox(&mut apu.muted[n], "Mute");
+                let soloed = (0..4).all(|m| apu.muted[m] == (m != n));
+                if ui.selectable_label(soloed, "Solo").clicked() {
+                    apu.muted = [0, 1, 2, 3].map(|m| !soloed && m != n);
+                }
+            });
+            ui.monospace(channel_info(apu, n));
+            scope(ui, apu.scope.as_ref(), n);
+            if n == 2 {
+                self.wave_ram(ui, apu);
+            }
+        }
+    }
+
+    fn cheats(&mut self, ui: &mut egui::Ui, gb: &mut GameBoy) {
+        let cheats = gb.cheats();
+        let mut remove = None;
+        egui::Grid::new("cheats").num_columns(4).striped(true).show(ui, |ui| {
+            for (i, cheat) in cheats.list().to_vec().into_iter().enumerate() {
+                let mut enabled = cheat.enabled;
+                if ui.checkbox(&mut enabled, "").changed() {
+                    cheats.set_enabled(i, enabled);
+                    self.cheats_changed = true;
+                }
+                ui.monospace(&cheat.text).on_hover_text(cheat.code.to_string());
+                ui.label(&cheat.name);
+                if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = remove {
+            cheats.remove(i);
+            self.cheats_changed = true;
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.cheat_code).hint_text("01FF34D1 or ABC-DEF-GHI").font(TextStyle::Monospace).desired_width(100.0));
+            let name = ui.add(egui::TextEdit::singleline(&mut self.cheat_name).hint_text("name").desired_width(120.0));
+            let entered = name.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if entered || ui.button("Add").clicked() {
+                match Cheat::new(&self.cheat_code, &self.cheat_name) {
+                    Ok(cheat) => {
+                        cheats.add(cheat);
+                        self.cheats_changed = true;
+                        self.cheat_code.clear();
+                        self.cheat_name.clear();
+                        self.cheat_error.clear();
+                    }
+                    Err(e) => self.cheat_error = e,
+                }
+            }
+        });
+        if !self.cheat_error.is_empty() {
+            ui.colored_label(Color32::LIGHT_RED, &self.cheat_error);
+        }
+    }
+
+    fn ram_search(&mut self, ui: &mut egui::Ui, gb: &mut GameBoy) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("search size").selected_text(self.search_size.to_string()).show_ui(ui, |ui| {
+                for size in SIZES {
+                    ui.selectable_value(&mut self.search_size, size, size.to_string());
+                }
+            });
+            if ui.button("New search").on_hover_text("Snapshot cartridge RAM, WRAM and HRAM").clicked() {
+                let search = Search::new(&gb.bus, self.search_size);
+                self.search_status = format!("{} addresses", search.candidates().len());
+                self.ram_search = Some(search);
+            }
+        });
+        let Some(search) = &mut self.ram_search else {
+            ui.weak("Start a search, then filter after the value changes in the game");
+            return self.watch_list(ui, gb);
+        };
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("comparison").selected_text(COMPARISONS[self.comparison]).show_ui(ui, |ui| {
+                for (i, name) in COMPARISONS.iter().enumerate() {
+                    ui.selectable_value(&mut self.comparison, i, *name);
+                }
+            });
+            if self.comparison >= 4 {
+                ui.add(egui::TextEdit::singleline(&mut self.search_value).hint_text("10, -1 or $0A").desired_width(60.0));
+            }
+            if ui.button("Filter").clicked() {
+                let value = parse_number(&self.search_value);
+                let comparison = match (self.comparison, value) {
+                    (0, _) => Some(Comparison::Equal),
+                    (1, _) => Some(Comparison::Changed),
+                    (2, _) => Some(Comparison::Increased),
+                    (3, _) => Some(Comparison::Decreased),
+                    (4, Some(delta)) => Some(Comparison::ChangedBy(delta)),
+                    (_, Some(value)) => Some(Comparison::Value(value as u16)),
+                    _ => None,
+                };
+                self.search_status = match comparison {
+                    Some(comparison) => {
+                        let left = search.filter(&gb.bus, comparison);
+                        format!("pass {}, {}: {} left", search.passes, comparison, left)
+                    }
+                    None => format!("'{}' is not a number", self.search_value),
+                };
+            }
+        });
+        ui.label(&self.search_status);
+
+        let candidates = search.candidates();
+        if candidates.len() > MAX_LISTED {
+            return self.watch_list(ui, gb);
+        }
+        let row_height = ui.text_style_height(&TextStyle::Monospace);
+        let mut watch = None;
+        egui::ScrollArea::vertical().id_salt("candidates").max_height(200.0).show_rows(ui, row_height, candidates.len(), |ui, rows| {
+            for &addr in &candidates[rows] {
+                ui.horizontal(|ui| {
+                    let (previous, current) = (search.previous(addr), search.current(&gb.bus, addr));
+                    ui.monospace(format!("{:04X}  was {:5}  now {:5}", addr, previous, current));
+                    if ui.small_button("Watch").clicked() {
+                        watch = Some(addr);
+                    }
+                });
+            }
+        });
+        if let Some(addr) = watch
+            && !self.watches.contains(&(addr, search.size))
+        {
+            self.watches.push((addr, search.size));
+        }
+        self.watch_list(ui, gb);
+    }
+
+    // watched addresses with their live value, frozen with GameShark codes in the cheat list
+    fn watch_list(&mut self, ui: &mut egui::Ui, gb: &mut GameBoy) {
+        if self.watches.is_empty() {
+            return;
+        }
+        ui.separator();
+        ui.strong("Watch");
+        let mut remove = None;
+        for (i, &(addr, size)) in self.watches.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let value = size.value(addr, |a| gb.bus.read(a));
+                ui.monospace(format!("{:04X} {:9} {:5} ${:0width$X}", addr, size.to_string(), value, value, width = size.bytes() as usize * 2));
+                let name = format!("freeze {:04X}", addr);
+                let frozen = gb.cheats().list().iter().any(|cheat| cheat.name == name);
+                if ui.selectable_label(frozen, "Freeze").on_hover_text("Hold the value with a GameShark cheat").clicked() {
+                    let cheats = gb.cheats();
+                    match frozen {
+                        true => cheats.replace(cheats.list().iter().filter(|cheat| cheat.name != name).cloned().collect()),
+                        false => search::freeze(size, addr, value).into_iter().for_each(|cheat| cheats.add(cheat)),
+                    }
+                    self.cheats_changed = true;
+                }
+                if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.watches.remove(i);
+        }
+    }
+
+    // the 32 samples as bars to click or drag on, and as hex to paste
+    fn wave_ram<T: Mmu>(&mut self, ui: &mut egui::Ui, apu: &mut Apu<T>) {
+        let (response, painter) = ui.allocate_painter(vec2(32.0 * 8.0, 64.0), Sense::click_and_drag());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+        let playing = apu.channels[2].enabled.then_some(apu.channels[2].position as usize);
+        for i in 0..32usize {
+            let byte = apu.read(WAVE_RAM + i as u16 / 2);
+            let sample = if i.is_multiple_of(2) { byte >> 4 } else { byte & 0x0F };
+            let top = rect.bottom() - (sample as f32 + 1.0) / 16.0 * rect.height();
+            let bar = egui::Rect::from_min_max(pos2(rect.left() + i as f32 * 8.0, top), pos2(rect.left() + i as f32 * 8.0 + 7.0, rect.bottom()));
+            painter.rect_filled(bar, 0.0, if playing == Some(i) { Color32::YELLOW } else { Color32::LIGHT_BLUE });
+        }
+        if let Some(pos) = response.interact_pointer_pos()
+            && rect.contains(pos)
+        {
+            let i = (((pos.x - rect.left()) / 8.0) as usize).min(31);
+            let sample = (((rect.bottom() - pos.y) / rect.height() * 16.0) as u8).min(15);
+            let addr = WAVE_RAM + i as u16 / 2;
+            let byte = apu.read(addr);
+            apu.write(addr, if i.is_multiple_of(2) { sample << 4 | (byte & 0x0F) } else { (byte & 0xF0) | sample });
+        }
+
+        let hex: String = (0..16).map(|i| format!("{:02X}", apu.read(WAVE_RAM + i))).collect();
+        ui.horizontal(|ui| {
+            let edit = ui.add(egui::TextEdit::singleline(&mut self.wave_text).hint_text(&hex).font(TextStyle::Monospace).desired_width(260.0));
+            let entered = edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if (entered || ui.button("Set").clicked())
+                && let Ok(bytes) = parse_pattern(&self.wave_text)
+                && bytes.len() == 16
+            {
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    apu.write(WAVE_RAM + i as u16, byte);
+                }
+                self.wave_text.clear();
+            }
+        });
+    }
+}
+
+// frequency, duty or volume, sweep, envelope and length of channel `n`
+fn channel_info<T: Mmu>(apu: &Apu<T>, n: usize) -> String {
+    let channel = &apu.channels[n];
+    let base = NR10 + n as u16 * 5;
+    let mut lines = Vec::new();
+    match n {
+        0 | 1 => {
+            let duty = ["12.5", "25", "50", "75"][(apu.read(base + 1) >> 6) as usize];
+            lines.push(format!("frequency {:03X} = {:.1}Hz, duty {}%", channel.frequency, apu.frequency_hz(n), duty));
+        }
+        2 => {
+            let volume = ["0%", "100%", "50%", "25%"][(apu.read(NR32) >> 5 & 0x03) as usize];
+            lines.push(format!("frequency {:03X} = {:.1}Hz, volume {}, sample {}", channel.frequency, apu.frequency_hz(n), volume, channel.position));
+        }
+        _ => {
+            let nr43 = apu.read(NR43);
+            lines.push(format!("LFSR {:04X} clocked at {:.0}Hz, {} bits (NR43 {:02X})",
+                apu.lfsr, apu.frequency_hz(n), if nr43 & 0x08 != 0 { 7 } else { 15 }, nr43));
+        }
+    }
+    if n == 0 {
+        let nr10 = apu.read(NR10);
+        lines.push(format!("sweep {} period {} {} shift {}, shadow {:03X}", if apu.sweep.enabled { "on" } else { "off" },
+            nr10 >> 4 & 0x07, if nr10 & 0x08 != 0 { "down" } else { "up" }, nr10 & 0x07, apu.sweep.shadow));
+    }
+    if n != 2 {
+        let envelope = &channel.envelope;
+        lines.push(format!("envelope volume {} {} period {}", envelope.volume, if envelope.increase { "up" } else { "down" }, envelope.period));
+    }
+    lines.push(format!("length {} {}", channel.length, if channel.length_enabled { "counting" } else { "off" }));
+    lines.join("\n")
+}
+
+// the recent output of channel `n`, 0 at the bottom and 15 at the top
+fn scope(ui: &mut egui::Ui, scope: Option<&VecDeque<[u8; 4]>>, n: usize) {
+    let (response, painter) = ui.allocate_painter(vec2(ui.available_width().min(512.0), 40.0), Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+    let values: Vec<u8> = scope.map(|scope| scope.iter().map(|outputs| outputs[n]).collect()).unwrap_or_default();
+    if values.len() < SCOPE_POINTS * 2 {
+        return;
+    }
+    let start = (1..=SCOPE_POINTS).find(|&i| values[i - 1] < values[i]).unwrap_or(SCOPE_POINTS);
+    let points = values[start..start + SCOPE_POINTS].iter().enumerate()
+        .map(|(i, &value)| pos2(
+            rect.left() + i as f32 / SCOPE_POINTS as f32 * rect.width(),
+            rect.bottom() - 2.0 - value as f32 / 15.0 * (rect.height() - 4.0),
+        ))
+        .collect();
+    painter.add(egui::Shape::line(points, Stroke::new(1.0, Color32::LIGHT_GREEN)));
+}
+
+// what is under pixel (x, y) of the VRAM view
+fn describe(view: View, vram: &Vram, image: &Image, x: usize, y: usize) -> String {
+    match view {
+        View::Tiles(bank) => {
+            let tile = y / 8 * SHEET_COLUMNS + x / 8;
+            let addressing = match tile {
+                0..128 => "8000 addressing",
+                128..256 => "8000 and 8800 addressing",
+                _ => "8800 addressing",
+            };
+            format!("tile {} at {}:{:04X}\nindex {:02X} ({})", tile, bank, 0x8000 + tile * 16, tile & 0xFF, addressing)
+        }
+        View::Map(layer) => {
+            let (column, row) = (x / 8, y / 8);
+            let (addr, tile, data, attributes) = vram.map_entry(layer, column, row);
+            let mut text = format!("map {:04X} ({}, {})\ntile {:02X}, data at {:04X}", addr, column, row, tile, data);
+            if let Some(a) = attributes {
+                text += &format!("\nattributes {:02X}: palette {} bank {}{}{}{}", a, a & 0x07, a >> 3 & 1,
+                    if a & 0x20 != 0 { " X-flip" } else { "" }, if a & 0x40 != 0 { " Y-flip" } else { "" },
+                    if a & 0x80 != 0 { " over sprites" } else { "" });
+            }
+            text
+        }
+        View::Oam => match y / 16 * OAM_COLUMNS + x / 8 {
+            index if index < 40 => vram.sprites()[index].describe(),
+            _ => String::new(),
+        },
+        View::Palettes => {
+            let (row, color) = (y / SWATCH, x / SWATCH);
+            let name = match row {
+                0 => "BGP FF47".to_string(),
+                1 | 2 => format!("OBP{} FF{:02X}", row - 1, 0x47 + row),
+                3..11 => format!("CGB BG palette {}", row - 3),
+                _ => format!("CGB OBJ palette {}", row - 11),
+            };
+            format!("{} colour {}: #{:06X}", name, color, image.pixels[y * image.width + x])
+        }
+    }
+}
+
+// decimal, negative too, or hex after $ or 0x
+fn parse_number(text: &str) -> Option<i32> {
+    let text = text.trim();
+    match text.strip_prefix('$').or_else(|| text.strip_prefix("0x")) {
+        Some(hex) => i32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// hex bytes with or without spaces, or ASCII text in quotes
+fn parse_pattern(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim();
+    if let Some(quoted) = text.strip_prefix('"') {
+        let quoted = quoted.strip_suffix('"').unwrap_or(quoted);
+        return match quoted.is_empty() {
+            true => Err("nothing to search for".to_string()),
+            false => Ok(quoted.as_bytes().to_vec()),
+        };
+    }
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err("search for hex bytes or \"text\"".to_string());
+    }
+    (0..digits.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| format!("bad hex {}", &digits[i..i + 2])))
+        .collect()
+}
+
+// `before` instructions leading up to `addr` and `after` from it. Going backwards is a guess:
+// the furthest start within reach whose instructions line up with `addr` wins.
+fn lines_around<T: Mmu>(bus: &T, addr: u16, before: usize, after: usize) -> Vec<Line> {
+    let decode = |from: u32, until: u32, limit: usize| {
+        let mut lines = Vec::new();
+        let mut at = from;
+        while at < until && at <= 0xFFFF && lines.len() < limit {
+            let line = disassembler::disassemble_at(bus, at as u16, Syntax::Rgbds);
+            at += line.len.max(1) as u32;
+            lines.push(line);
+        }
+        (lines, at)
+    };
+    let addr32 = addr as u32;
+    let reach = (before as u32 * 3).min(addr32);
+    let start = (addr32 - reach..addr32)
+        .find(|&start| decode(start, addr32, usize::MAX).1 == addr32)
+        .unwrap_or(addr32);
+    let (lines, _) = decode(start, addr32, usize::MAX);
+    let mut lines = lines[lines.len().saturating_sub(before)..].to_vec();
+    lines.extend(decode(addr32, u32::MAX, after).0);
+    lines
+}
+
+fn registers(ui: &mut egui::Ui, gb: &mut GameBoy, paused: bool) {
+    if !paused {
+        ui.weak("Pause to edit");
+    }
+    ui.add_enabled_ui(paused, |ui| {
+        let cpu = &mut gb.cpu;
+        let hex8 = |ui: &mut egui::Ui, name: &str, value: &mut u8| {
+            ui.monospace(name);
+            ui.add(egui::DragValue::new(value).hexadecimal(2, false, true).speed(0.1));
+        };
+        egui::Grid::new("registers").num_columns(4).show(ui, |ui| {
+            let r = &mut cpu.regs;
+            for [(high, h), (low, l)] in [[("A", &mut r.a), ("B", &mut r.b)], [("C", &mut r.c), ("D", &mut r.d)], [("E", &mut r.e), ("H", &mut r.h)]] {
+                hex8(ui, high, h);
+                hex8(ui, low, l);
+                ui.end_row();
+            }
+            hex8(ui, "L", &mut r.l);
+            ui.end_row();
+            for (name, value) in [("SP", &mut r.sp), ("PC", &mut r.pc)] {
+                ui.monospace(name);
+                ui.add(egui::DragValue::new(value).hexadecimal(4, false, true).speed(0.1));
+            }
+            ui.end_row();
+        });
+        ui.horizontal(|ui| {
+            for (name, flag) in [("Z", CpuFlag::Z), ("N", CpuFlag::N), ("H", CpuFlag::H), ("C", CpuFlag::C)] {
+                let mut set = cpu.regs.getflag(&flag);
+                if ui.checkbox(&mut set, name).changed() {
+                    cpu.regs.flag(&flag, set);
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut cpu.ime, "IME");
+            ui.checkbox(&mut cpu.halted, "HALT");
+        });
+    });
+    ui.separator();
+
+    let (cpu, bus) = (&gb.cpu, &gb.bus);
+    let r = &cpu.regs;
+    let pressed: Vec<&str> = BUTTONS.iter().filter(|(_, bit)| bus.buttons() & bit != 0).map(|(name, _)| *name).collect();
+    let lines = [
+        format!("AF {:04X}  BC {:04X}", r.af(), r.bc()),
+        format!("DE {:04X}  HL {:04X}", r.de(), r.hl()),
+        format!("IE {:02X}  IF {:02X}", bus.read(IE), bus.read(IF)),
+        format!("LCDC {:02X}  STAT {:02X}  LY {:3}", bus.read(LCDC), bus.read(STAT), bus.read(LY)),
+        format!("frame {}  cycle {}", cpu.cycles / FRAME_CYCLES, cpu.cycles % FRAME_CYCLES),
+        format!("buttons {}", pressed.join(" ")),
+    ];
+    for line in lines {
+        ui.monospace(line);
+    }
+}
@@ -0,0 +1,56 @@
+// Recently opened ROMs, newest first, one path per line in GameBoyEmulator/recent.txt under the
+// config directory ($XDG_CONFIG_HOME or ~/.config, %APPDATA% on Windows).
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MAX_RECENT: usize = 10;
+
+pub struct Recent {
+    pub paths: Vec<PathBuf>,
+    file: Option<PathBuf>,
+}
+
+fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME").or_else(|| env::var_os("APPDATA")).map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+impl Recent {
+    pub fn load() -> Recent {
+        let file = config_dir().map(|dir| dir.join("GameBoyEmulator").join("recent.txt"));
+        let paths = file.as_ref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|text| text.lines().filter(|line| !line.is_empty()).map(PathBuf::from).take(MAX_RECENT).collect())
+            .unwrap_or_default();
+        Recent { paths, file }
+    }
+
+    pub fn add(&mut self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.paths.retain(|p| *p != path);
+        self.paths.insert(0, path);
+        self.paths.truncate(MAX_RECENT);
+        self.save();
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.paths.retain(|p| p != path);
+        self.save();
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.save();
+    }
+
+    // a list that cannot be written is only lost at exit, not worth a dialog
+    fn save(&self) {
+        let Some(file) = &self.file else { return };
+        let text: String = self.paths.iter().map(|p| format!("{}\n", p.display())).collect();
+        let result = fs::create_dir_all(file.parent().unwrap_or(Path::new("."))).and_then(|_| fs::write(file, text));
+        if let Err(e) = result {
+            eprintln!("GameBoyEmulator: cannot write {}: {}", file.display(), e);
+        }
+    }
+}